@@ -1,7 +1,7 @@
-/// abi related to uniswap interaction
+//! abi related to uniswap interaction
 use ethers_contract::abigen;
 
-/// V2 pair ABI
+// V2 pair ABI
 abigen!(
     IUniswapV2Pair,
     r#"[
@@ -9,10 +9,21 @@ abigen!(
         function token0() external view returns (address)
         function token1() external view returns (address)
         function totalSupply() external view returns (uint256)
+        function balanceOf(address owner) external view returns (uint256)
+        function kLast() external view returns (uint256)
+        function price0CumulativeLast() external view returns (uint256)
+        function price1CumulativeLast() external view returns (uint256)
+        function nonces(address owner) external view returns (uint256)
+        function DOMAIN_SEPARATOR() external view returns (bytes32)
+        function approve(address spender, uint256 value) external returns (bool)
+        event Swap(address indexed sender, uint256 amount0In, uint256 amount1In, uint256 amount0Out, uint256 amount1Out, address indexed to)
+        event Mint(address indexed sender, uint256 amount0, uint256 amount1)
+        event Burn(address indexed sender, uint256 amount0, uint256 amount1, address indexed to)
+        event Sync(uint112 reserve0, uint112 reserve1)
     ]"#,
 );
 
-/// ERC20 standard ABI
+// ERC20 standard ABI
 abigen!(
     IERC20,
     r#"[
@@ -24,10 +35,11 @@ abigen!(
         function transfer(address to, uint256 amount) external returns (bool)
         function approve(address spender, uint256 amount) external returns (bool)
         function allowance(address owner, address spender) external view returns (uint256)
+        event Transfer(address indexed from, address indexed to, uint256 value)
     ]"#,
 );
 
-/// V3 Pool ABI
+// V3 Pool ABI
 abigen!(
     IUniswapV3Pool,
     r#"[
@@ -36,32 +48,83 @@ abigen!(
         function token0() external view returns (address)
         function token1() external view returns (address)
         function fee() external view returns (uint24)
+        function tickSpacing() external view returns (int24)
+        function observe(uint32[] calldata secondsAgos) external view returns (int56[] memory tickCumulatives, uint160[] memory secondsPerLiquidityCumulativeX128s)
+        function observations(uint256 index) external view returns (uint32 blockTimestamp, int56 tickCumulative, uint160 secondsPerLiquidityCumulativeX128, bool initialized)
+        function increaseObservationCardinalityNext(uint16 observationCardinalityNext) external
+        function tickBitmap(int16 wordPosition) external view returns (uint256)
+        function ticks(int24 tick) external view returns (uint128 liquidityGross, int128 liquidityNet, uint256 feeGrowthOutside0X128, uint256 feeGrowthOutside1X128, int56 tickCumulativeOutside, uint160 secondsPerLiquidityOutsideX128, uint32 secondsOutside, bool initialized)
+        event Swap(address indexed sender, address indexed recipient, int256 amount0, int256 amount1, uint160 sqrtPriceX96, uint128 liquidity, int24 tick)
+        event Mint(address sender, address indexed owner, int24 indexed tickLower, int24 indexed tickUpper, uint128 amount, uint256 amount0, uint256 amount1)
+        event Burn(address indexed owner, int24 indexed tickLower, int24 indexed tickUpper, uint128 amount, uint256 amount0, uint256 amount1)
     ]"#,
 );
 
-/// V2 Router ABI
+// V2 Router ABI
 abigen!(
     IUniswapV2Router,
     r#"[
         function swapExactTokensForTokens(uint amountIn, uint amountOutMin, address[] calldata path, address to, uint deadline) external returns (uint[] memory amounts)
         function swapTokensForExactTokens(uint amountOut, uint amountInMax, address[] calldata path, address to, uint deadline) external returns (uint[] memory amounts)
+        function swapExactETHForTokens(uint amountOutMin, address[] calldata path, address to, uint deadline) external payable returns (uint[] memory amounts)
+        function swapExactTokensForETH(uint amountIn, uint amountOutMin, address[] calldata path, address to, uint deadline) external returns (uint[] memory amounts)
+        function swapExactTokensForTokensSupportingFeeOnTransferTokens(uint amountIn, uint amountOutMin, address[] calldata path, address to, uint deadline) external
+        function swapExactETHForTokensSupportingFeeOnTransferTokens(uint amountOutMin, address[] calldata path, address to, uint deadline) external payable
+        function swapExactTokensForETHSupportingFeeOnTransferTokens(uint amountIn, uint amountOutMin, address[] calldata path, address to, uint deadline) external
         function getAmountsOut(uint amountIn, address[] calldata path) external view returns (uint[] memory amounts)
         function getAmountsIn(uint amountOut, address[] calldata path) external view returns (uint[] memory amounts)
+        function factory() external pure returns (address)
+        function WETH() external pure returns (address)
+        function addLiquidity(address tokenA, address tokenB, uint amountADesired, uint amountBDesired, uint amountAMin, uint amountBMin, address to, uint deadline) external returns (uint amountA, uint amountB, uint liquidity)
+        function addLiquidityETH(address token, uint amountTokenDesired, uint amountTokenMin, uint amountETHMin, address to, uint deadline) external payable returns (uint amountToken, uint amountETH, uint liquidity)
+        function removeLiquidity(address tokenA, address tokenB, uint liquidity, uint amountAMin, uint amountBMin, address to, uint deadline) external returns (uint amountA, uint amountB)
+        function removeLiquidityETH(address token, uint liquidity, uint amountTokenMin, uint amountETHMin, address to, uint deadline) external returns (uint amountToken, uint amountETH)
+        function removeLiquidityWithPermit(address tokenA, address tokenB, uint liquidity, uint amountAMin, uint amountBMin, address to, uint deadline, bool approveMax, uint8 v, bytes32 r, bytes32 s) external returns (uint amountA, uint amountB)
+        function removeLiquidityETHWithPermit(address token, uint liquidity, uint amountTokenMin, uint amountETHMin, address to, uint deadline, bool approveMax, uint8 v, bytes32 r, bytes32 s) external returns (uint amountToken, uint amountETH)
+        function removeLiquidityETHSupportingFeeOnTransferTokens(address token, uint liquidity, uint amountTokenMin, uint amountETHMin, address to, uint deadline) external returns (uint amountETH)
     ]"#,
 );
 
-/// V2 Factory ABI
+// V2 Factory ABI
 abigen!(
     IUniswapV2Factory,
     r#"[
         function getPair(address tokenA, address tokenB) external view returns (address pair)
+        function feeTo() external view returns (address)
+        function allPairsLength() external view returns (uint256)
+        function allPairs(uint256 index) external view returns (address pair)
+        event PairCreated(address indexed token0, address indexed token1, address pair, uint256 index)
     ]"#,
 );
 
-/// V3 Factory ABI
+// V3 Factory ABI
 abigen!(
     IUniswapV3Factory,
     r#"[
         function getPool(address tokenA, address tokenB, uint24 fee) external view returns (address pool)
+        event PoolCreated(address indexed token0, address indexed token1, uint24 indexed fee, int24 tickSpacing, address pool)
+    ]"#,
+);
+
+// V3 NonfungiblePositionManager ABI
+abigen!(
+    INonfungiblePositionManager,
+    r#"[
+        struct MintParams { address token0; address token1; uint24 fee; int24 tickLower; int24 tickUpper; uint256 amount0Desired; uint256 amount1Desired; uint256 amount0Min; uint256 amount1Min; address recipient; uint256 deadline; }
+        struct IncreaseLiquidityParams { uint256 tokenId; uint256 amount0Desired; uint256 amount1Desired; uint256 amount0Min; uint256 amount1Min; uint256 deadline; }
+        struct DecreaseLiquidityParams { uint256 tokenId; uint128 liquidity; uint256 amount0Min; uint256 amount1Min; uint256 deadline; }
+        struct CollectParams { uint256 tokenId; address recipient; uint128 amount0Max; uint128 amount1Max; }
+        function positions(uint256 tokenId) external view returns (uint96 nonce, address operator, address token0, address token1, uint24 fee, int24 tickLower, int24 tickUpper, uint128 liquidity, uint256 feeGrowthInside0LastX128, uint256 feeGrowthInside1LastX128, uint128 tokensOwed0, uint128 tokensOwed1)
+        function balanceOf(address owner) external view returns (uint256)
+        function tokenOfOwnerByIndex(address owner, uint256 index) external view returns (uint256)
+        function mint(MintParams calldata params) external payable returns (uint256 tokenId, uint128 liquidity, uint256 amount0, uint256 amount1)
+        function increaseLiquidity(IncreaseLiquidityParams calldata params) external payable returns (uint128 liquidity, uint256 amount0, uint256 amount1)
+        function decreaseLiquidity(DecreaseLiquidityParams calldata params) external payable returns (uint256 amount0, uint256 amount1)
+        function collect(CollectParams calldata params) external payable returns (uint256 amount0, uint256 amount1)
+        function burn(uint256 tokenId) external payable
+        function multicall(bytes[] calldata data) external payable returns (bytes[] memory results)
+        event IncreaseLiquidity(uint256 indexed tokenId, uint128 liquidity, uint256 amount0, uint256 amount1)
+        event DecreaseLiquidity(uint256 indexed tokenId, uint128 liquidity, uint256 amount0, uint256 amount1)
+        event Collect(uint256 indexed tokenId, address recipient, uint256 amount0, uint256 amount1)
     ]"#,
 );
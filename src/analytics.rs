@@ -0,0 +1,296 @@
+use ethers::{contract::parse_log, prelude::*, types::Filter};
+use std::sync::Arc;
+
+use crate::{
+    abi::{
+        IERC20, IUniswapV3Pool, i_uniswap_v2_pair::SwapFilter as V2SwapFilter,
+        i_uniswap_v3_pool::SwapFilter as V3SwapFilter,
+    },
+    events::get_logs_in_chunks,
+    math::{tick::sqrt_price_x96_to_price, v2},
+    price::{AVERAGE_BLOCK_TIME, Price},
+    tool::num::u256_to_decimal,
+    types::{BlockSnapshot, PoolInfo, PoolStats, PoolVersion, PriceFinder, UniswapError},
+    v2::UniswapV2,
+    v3::UniswapV3,
+};
+
+/// seconds per year, used to annualize fees
+pub const SECONDS_PER_YEAR: f64 = 365.0 * 86400.0;
+
+/// swap totals of a pool, raw token amounts
+#[derive(Debug, Clone, Default)]
+struct SwapTotals {
+    swap_count: u64,
+    volume0: U256,
+    volume1: U256,
+    amount0_in: U256,
+    amount1_in: U256,
+}
+
+pub struct PoolAnalytics {
+    provider: Arc<Provider<Http>>,
+    price: Price,
+}
+
+impl PoolAnalytics {
+    pub fn new(provider: Arc<Provider<Http>>) -> Result<Self, UniswapError> {
+        let price = Price::new_with_finder(provider.clone(), PriceFinder::mainnet())?;
+        Ok(Self::new_with_price(provider, price))
+    }
+
+    pub fn new_with_price(provider: Arc<Provider<Http>>, price: Price) -> Self {
+        Self { provider, price }
+    }
+
+    /// get volume, fees, TVL and fee APR of a pool over the `window_seconds` ending at `block`,
+    /// defaulting to the latest block. USD figures are priced at the end block.
+    pub async fn get_pool_stats(
+        &self,
+        pool_address: Address,
+        version: PoolVersion,
+        window_seconds: u64,
+        block: Option<BlockId>,
+    ) -> Result<PoolStats, UniswapError> {
+        let end = self.price.snapshot(block).await?;
+        let window_blocks = window_seconds.div_ceil(AVERAGE_BLOCK_TIME).max(1);
+        let start = self
+            .price
+            .snapshot(Some(end.block_number.saturating_sub(window_blocks).into()))
+            .await?;
+        let window_seconds = end.block_timestamp.saturating_sub(start.block_timestamp);
+        let (pool_info, fee, balance0, balance1, in_range_liquidity, spot_price) = match version {
+            PoolVersion::V2 => self.get_v2_state(pool_address, &end).await?,
+            PoolVersion::V3 => self.get_v3_state(pool_address, &end).await?,
+        };
+        let totals = self
+            .get_swap_totals(
+                pool_address,
+                version,
+                start.block_number + 1,
+                end.block_number,
+            )
+            .await?;
+        let decimals0 = pool_info.token0.decimals;
+        let decimals1 = pool_info.token1.decimals;
+        let (token0_usd_price, token1_usd_price) =
+            self.get_usd_prices(&pool_info, spot_price, &end).await;
+        // fees are charged on the input amount
+        let fee_rate = fee as f64 / 1_000_000.0;
+        let amount0_in = u256_to_decimal(totals.amount0_in, decimals0);
+        let amount1_in = u256_to_decimal(totals.amount1_in, decimals1);
+        let fees0 = amount0_in * fee_rate;
+        let fees1 = amount1_in * fee_rate;
+        let balance0 = u256_to_decimal(balance0, decimals0);
+        let balance1 = u256_to_decimal(balance1, decimals1);
+        let (volume_usd, fees_usd, tvl_usd) = match (token0_usd_price, token1_usd_price) {
+            (Some(usd0), Some(usd1)) => (
+                Some(amount0_in * usd0 + amount1_in * usd1),
+                Some(fees0 * usd0 + fees1 * usd1),
+                Some(balance0 * usd0 + balance1 * usd1),
+            ),
+            _ => (None, None, None),
+        };
+        let annualize = if window_seconds > 0 {
+            SECONDS_PER_YEAR / window_seconds as f64
+        } else {
+            0.0
+        };
+        let fee_apr = match (fees_usd, tvl_usd) {
+            (Some(fees_usd), Some(tvl_usd)) if tvl_usd > 0.0 => {
+                Some(fees_usd * annualize / tvl_usd * 100.0)
+            }
+            _ => None,
+        };
+        // in range LPs share the fees pro rata to their liquidity
+        let annual_fees_usd_per_liquidity = match (fees_usd, in_range_liquidity) {
+            (Some(fees_usd), Some(liquidity)) if liquidity > 0 => {
+                Some(fees_usd * annualize / liquidity as f64)
+            }
+            _ => None,
+        };
+        Ok(PoolStats {
+            pool_info,
+            version,
+            fee,
+            start_block: start.block_number,
+            end_block: end.block_number,
+            window_seconds,
+            swap_count: totals.swap_count,
+            volume0: u256_to_decimal(totals.volume0, decimals0),
+            volume1: u256_to_decimal(totals.volume1, decimals1),
+            volume_usd,
+            fees0,
+            fees1,
+            fees_usd,
+            balance0,
+            balance1,
+            token0_usd_price,
+            token1_usd_price,
+            tvl_usd,
+            fee_apr,
+            in_range_liquidity,
+            annual_fees_usd_per_liquidity,
+        })
+    }
+
+    /// pool info, fee, raw balances, in range liquidity and the token0 price in token1
+    async fn get_v2_state(
+        &self,
+        pool_address: Address,
+        snapshot: &BlockSnapshot,
+    ) -> Result<(PoolInfo, u32, U256, U256, Option<u128>, f64), UniswapError> {
+        let uniswap_v2 = UniswapV2::new_with_provider(self.provider.clone());
+        let pool_info = uniswap_v2
+            .get_pool_info(pool_address, snapshot.block())
+            .await?;
+        let reserve0 = u256_to_decimal(pool_info.reserve0, pool_info.token0.decimals);
+        let reserve1 = u256_to_decimal(pool_info.reserve1, pool_info.token1.decimals);
+        let spot_price = if reserve0 > 0.0 {
+            reserve1 / reserve0
+        } else {
+            0.0
+        };
+        let (reserve0, reserve1) = (pool_info.reserve0, pool_info.reserve1);
+        Ok((pool_info, v2::FEE, reserve0, reserve1, None, spot_price))
+    }
+
+    /// pool info, fee, raw balances, in range liquidity and the token0 price in token1
+    async fn get_v3_state(
+        &self,
+        pool_address: Address,
+        snapshot: &BlockSnapshot,
+    ) -> Result<(PoolInfo, u32, U256, U256, Option<u128>, f64), UniswapError> {
+        let uniswap_v3 = UniswapV3::new_with_provider(self.provider.clone());
+        let pool_info = uniswap_v3
+            .get_pool_info(pool_address, snapshot.block())
+            .await?;
+        let (sqrt_price_x96, _, liquidity) = uniswap_v3
+            .get_pool_state(pool_address, snapshot.block())
+            .await?;
+        let pool_contract = IUniswapV3Pool::new(pool_address, self.provider.clone());
+        let fee = pool_contract
+            .fee()
+            .block(snapshot.block_id())
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("Fee error: {}", e)))?;
+        let mut balances = Vec::new();
+        for token in [pool_info.token0.address, pool_info.token1.address] {
+            let balance = IERC20::new(token, self.provider.clone())
+                .balance_of(pool_address)
+                .block(snapshot.block_id())
+                .call()
+                .await
+                .map_err(|e| UniswapError::ContractError(format!("Balance error: {}", e)))?;
+            balances.push(balance);
+        }
+        let spot_price = sqrt_price_x96_to_price(
+            sqrt_price_x96,
+            pool_info.token0.decimals,
+            pool_info.token1.decimals,
+        );
+        Ok((
+            pool_info,
+            fee,
+            balances[0],
+            balances[1],
+            Some(liquidity),
+            spot_price,
+        ))
+    }
+
+    /// sum the swaps of a pool between two blocks, inclusive
+    async fn get_swap_totals(
+        &self,
+        pool_address: Address,
+        version: PoolVersion,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<SwapTotals, UniswapError> {
+        let topic = match version {
+            PoolVersion::V2 => V2SwapFilter::signature(),
+            PoolVersion::V3 => V3SwapFilter::signature(),
+        };
+        let filter = Filter::new().address(pool_address).topic0(topic);
+        let logs =
+            get_logs_in_chunks(self.provider.as_ref(), &filter, from_block, to_block).await?;
+        let mut totals = SwapTotals::default();
+        for log in logs {
+            let (amount0_in, amount1_in, amount0_out, amount1_out) = match version {
+                PoolVersion::V2 => {
+                    let Ok(event) = parse_log::<V2SwapFilter>(log) else {
+                        continue;
+                    };
+                    (
+                        event.amount_0_in,
+                        event.amount_1_in,
+                        event.amount_0_out,
+                        event.amount_1_out,
+                    )
+                }
+                PoolVersion::V3 => {
+                    let Ok(event) = parse_log::<V3SwapFilter>(log) else {
+                        continue;
+                    };
+                    // positive amounts are paid into the pool
+                    let split = |amount: I256| {
+                        if amount.is_positive() {
+                            (amount.unsigned_abs(), U256::zero())
+                        } else {
+                            (U256::zero(), amount.unsigned_abs())
+                        }
+                    };
+                    let (amount0_in, amount0_out) = split(event.amount_0);
+                    let (amount1_in, amount1_out) = split(event.amount_1);
+                    (amount0_in, amount1_in, amount0_out, amount1_out)
+                }
+            };
+            totals.swap_count += 1;
+            totals.amount0_in += amount0_in;
+            totals.amount1_in += amount1_in;
+            totals.volume0 += amount0_in.abs_diff(amount0_out);
+            totals.volume1 += amount1_in.abs_diff(amount1_out);
+        }
+        Ok(totals)
+    }
+
+    /// USD prices of both tokens, a missing side is derived through the pool's spot price
+    async fn get_usd_prices(
+        &self,
+        pool_info: &PoolInfo,
+        spot_price: f64,
+        snapshot: &BlockSnapshot,
+    ) -> (Option<f64>, Option<f64>) {
+        let mut usd_prices = Vec::new();
+        for token in [pool_info.token0.address, pool_info.token1.address] {
+            usd_prices.push(
+                self.price
+                    .get_token_prices_at_snapshot(token, snapshot)
+                    .await
+                    .ok()
+                    .and_then(|price_info| price_info.usd_price),
+            );
+        }
+        let valid = spot_price.is_finite() && spot_price > 0.0;
+        match (usd_prices[0], usd_prices[1]) {
+            (Some(usd0), None) if valid => (Some(usd0), Some(usd0 / spot_price)),
+            (None, Some(usd1)) if valid => (Some(usd1 * spot_price), Some(usd1)),
+            (usd0, usd1) => (usd0, usd1),
+        }
+    }
+}
+
+/// estimate the fee APR of a V3 position from pool stats, assuming it stays in range.
+/// `position_value_usd` is the current value of the position's token amounts.
+pub fn position_fee_apr(
+    stats: &PoolStats,
+    liquidity: u128,
+    position_value_usd: f64,
+) -> Option<f64> {
+    let annual_fees_usd_per_liquidity = stats.annual_fees_usd_per_liquidity?;
+    if position_value_usd <= 0.0 {
+        return None;
+    }
+    Some(annual_fees_usd_per_liquidity * liquidity as f64 / position_value_usd * 100.0)
+}
@@ -0,0 +1,376 @@
+use ethers::prelude::*;
+use std::{collections::HashSet, sync::Arc};
+
+use crate::{
+    abi::{IUniswapV2Factory, IUniswapV3Factory},
+    depth::{Depth, pool_address, pool_amount_out, pool_tokens},
+    global::mainnet::{
+        MAINNET_UNISWAP_V2_FACTORY_ADDRESS, MAINNET_UNISWAP_V3_FACTORY_ADDRESS, WETH_ADDRESS,
+    },
+    math::{swap::simulate_swap, v2},
+    price::V3_FEE_TIERS,
+    tool::block::block_or_latest,
+    types::{ArbitrageOpportunity, PoolLiquidity, PoolVersion, UniswapError},
+    v2::UniswapV2,
+};
+
+/// gas of an arbitrage transaction besides its swaps
+pub const ARBITRAGE_BASE_GAS: u64 = 50_000;
+/// gas of a V2 swap
+pub const V2_SWAP_GAS: u64 = 60_000;
+/// gas of a V3 swap
+pub const V3_SWAP_GAS: u64 = 100_000;
+
+/// gas of a swap on a pool
+pub fn swap_gas(pool: &PoolLiquidity) -> u64 {
+    match pool {
+        PoolLiquidity::V2(_) => V2_SWAP_GAS,
+        PoolLiquidity::V3(_) => V3_SWAP_GAS,
+    }
+}
+
+/// profit-maximizing input of buying on a V2 pair and selling on another, in closed form.
+/// reserves are (token_in, token_out) of the buy pair and (token_out, token_in) of the sell
+/// pair. the round trip returns a x / (b + c x) with the 0.3% fee as 997/1000, whose
+/// profit peaks at x = (sqrt(a b) - b) / c. zero when no input is profitable.
+pub fn v2_optimal_amount_in(buy_reserves: (U256, U256), sell_reserves: (U256, U256)) -> U256 {
+    let (buy_in, buy_out) = (U512::from(buy_reserves.0), U512::from(buy_reserves.1));
+    let (sell_in, sell_out) = (U512::from(sell_reserves.0), U512::from(sell_reserves.1));
+    let a = U512::from(997u64 * 997) * buy_out * sell_out;
+    let b = U512::from(1_000_000u64) * buy_in * sell_in;
+    let c = U512::from(997u64) * (U512::from(1000u64) * sell_in + U512::from(997u64) * buy_out);
+    if a <= b || c.is_zero() {
+        return U256::zero();
+    }
+    let amount_in = ((a * b).integer_sqrt() - b) / c;
+    U256::try_from(amount_in).unwrap_or(U256::MAX)
+}
+
+/// amounts of buying on `buy` with `amount_in` of `token_in` and selling on `sell`,
+/// as (intermediate, out)
+pub fn round_trip(
+    buy: &PoolLiquidity,
+    sell: &PoolLiquidity,
+    token_in: Address,
+    amount_in: U256,
+) -> Result<(U256, U256), UniswapError> {
+    let (token0, token1) = pool_tokens(buy);
+    let token_out = if token_in == token0 { token1 } else { token0 };
+    let intermediate = pool_amount_out(buy, token_in, amount_in)?;
+    if intermediate.is_zero() {
+        return Ok((intermediate, U256::zero()));
+    }
+    Ok((
+        intermediate,
+        pool_amount_out(sell, token_out, intermediate)?,
+    ))
+}
+
+/// largest input a pool can fill within its loaded liquidity, for V3 the input that moves
+/// the price to the edge of the state's loaded tick window
+pub fn pool_capacity(pool: &PoolLiquidity, token_in: Address) -> Result<U256, UniswapError> {
+    match pool {
+        PoolLiquidity::V2(pool_info) if token_in == pool_info.token0.address => {
+            Ok(pool_info.reserve0)
+        }
+        PoolLiquidity::V2(pool_info) => Ok(pool_info.reserve1),
+        PoolLiquidity::V3(state) => {
+            Ok(simulate_swap(state, token_in == state.token0, I256::MAX, None)?.amount_in)
+        }
+    }
+}
+
+/// input in [1, upper] maximizing `amount_out(x) - x`, by ternary search. a chain of exact
+/// input swaps has a concave output, so the profit is unimodal. `upper` is halved until the
+/// chain can fill it.
+pub fn search_optimal_amount_in<F>(mut upper: U256, amount_out: F) -> U256
+where
+    F: Fn(U256) -> Result<U256, UniswapError>,
+{
+    while !upper.is_zero() && amount_out(upper).is_err() {
+        upper /= 2;
+    }
+    let profit = |amount_in: U256| match amount_out(amount_in) {
+        Ok(amount_out) => I256::from_raw(amount_out) - I256::from_raw(amount_in),
+        Err(_) => I256::MIN,
+    };
+    let (mut low, mut high) = (U256::one(), upper);
+    while high > low && high - low > U256::from(2) {
+        let third = (high - low) / 3;
+        let (left, right) = (low + third, high - third);
+        let (left_profit, right_profit) = (profit(left), profit(right));
+        if left_profit < right_profit {
+            low = left + 1;
+        } else if left_profit > right_profit {
+            high = right - 1;
+        } else {
+            (low, high) = (left, right);
+        }
+    }
+    let mut best = low;
+    let mut amount_in = low;
+    while amount_in <= high {
+        if profit(amount_in) > profit(best) {
+            best = amount_in;
+        }
+        amount_in += U256::one();
+    }
+    best
+}
+
+/// the most profitable round trip of `token_in` buying on `buy` and selling on `sell`, as
+/// (amount_in, intermediate, out). closed form for two V2 pairs, a numeric search otherwise.
+pub fn optimal_round_trip(
+    buy: &PoolLiquidity,
+    sell: &PoolLiquidity,
+    token_in: Address,
+) -> Result<Option<(U256, U256, U256)>, UniswapError> {
+    let amount_in = match (buy, sell) {
+        (PoolLiquidity::V2(buy_info), PoolLiquidity::V2(sell_info)) => {
+            let buy_reserves = if token_in == buy_info.token0.address {
+                (buy_info.reserve0, buy_info.reserve1)
+            } else {
+                (buy_info.reserve1, buy_info.reserve0)
+            };
+            let sell_reserves = if token_in == sell_info.token0.address {
+                (sell_info.reserve1, sell_info.reserve0)
+            } else {
+                (sell_info.reserve0, sell_info.reserve1)
+            };
+            let amount_in = v2_optimal_amount_in(buy_reserves, sell_reserves);
+            // the closed form is real valued, its floor or ceiling is the integer optimum
+            let profit = |amount_in: U256| {
+                round_trip(buy, sell, token_in, amount_in)
+                    .map(|(_, amount_out)| amount_out.saturating_sub(amount_in))
+                    .unwrap_or_default()
+            };
+            if profit(amount_in + 1) > profit(amount_in) {
+                amount_in + 1
+            } else {
+                amount_in
+            }
+        }
+        _ => search_optimal_amount_in(pool_capacity(buy, token_in)?, |amount_in| {
+            round_trip(buy, sell, token_in, amount_in).map(|(_, amount_out)| amount_out)
+        }),
+    };
+    if amount_in.is_zero() {
+        return Ok(None);
+    }
+    let (intermediate, amount_out) = round_trip(buy, sell, token_in, amount_in)?;
+    if amount_out <= amount_in {
+        return Ok(None);
+    }
+    Ok(Some((amount_in, intermediate, amount_out)))
+}
+
+/// gross arbitrage between every ordered pair of pools of the same token pair, profits in
+/// `token_in`. gas costs are left for the caller to price.
+pub fn find_pair_arbitrage(
+    pools: &[PoolLiquidity],
+    token_in: Address,
+    block_number: u64,
+) -> Vec<ArbitrageOpportunity> {
+    let mut opportunities = Vec::new();
+    for (i, buy) in pools.iter().enumerate() {
+        let (token0, token1) = pool_tokens(buy);
+        if token_in != token0 && token_in != token1 {
+            continue;
+        }
+        let token_out = if token_in == token0 { token1 } else { token0 };
+        for (j, sell) in pools.iter().enumerate() {
+            let (sell0, sell1) = pool_tokens(sell);
+            if i == j || !(sell0 == token0 && sell1 == token1) {
+                continue;
+            }
+            if let Ok(Some((amount_in, intermediate_amount, amount_out))) =
+                optimal_round_trip(buy, sell, token_in)
+            {
+                opportunities.push(ArbitrageOpportunity {
+                    token_in,
+                    token_out,
+                    buy_pool: pool_address(buy),
+                    sell_pool: pool_address(sell),
+                    amount_in,
+                    intermediate_amount,
+                    amount_out,
+                    profit: amount_out - amount_in,
+                    gas_units: ARBITRAGE_BASE_GAS + swap_gas(buy) + swap_gas(sell),
+                    gas_cost: None,
+                    net_profit: None,
+                    block_number,
+                });
+            }
+        }
+    }
+    opportunities
+}
+
+/// finds arbitrage between the V2 pairs and V3 pools of a token pair across Uniswap and its forks.
+/// forks are assumed to charge the Uniswap V2 fee.
+pub struct ArbitrageDetector {
+    pub provider: Arc<Provider<Http>>,
+    pub factories: Vec<(Address, Address)>, // (V2 factory, V3 factory), zero when a fork lacks one
+    pub weth: Address,
+}
+
+impl ArbitrageDetector {
+    pub fn new(provider: Arc<Provider<Http>>) -> Self {
+        Self::new_with_factories(
+            provider,
+            vec![(
+                MAINNET_UNISWAP_V2_FACTORY_ADDRESS.parse().unwrap(),
+                MAINNET_UNISWAP_V3_FACTORY_ADDRESS.parse().unwrap(),
+            )],
+            WETH_ADDRESS.parse().unwrap(),
+        )
+    }
+
+    pub fn new_with_factories(
+        provider: Arc<Provider<Http>>,
+        factories: Vec<(Address, Address)>,
+        weth: Address,
+    ) -> Self {
+        Self {
+            provider,
+            factories,
+            weth,
+        }
+    }
+
+    /// load the pools of a pair from every factory
+    pub async fn get_pools(
+        &self,
+        token_a: Address,
+        token_b: Address,
+        block: Option<BlockId>,
+    ) -> Result<Vec<PoolLiquidity>, UniswapError> {
+        let block_id = block_or_latest(block);
+        let mut found = Vec::new();
+        for (factory_v2, factory_v3) in self.factories.iter() {
+            if *factory_v2 != Address::zero() {
+                let factory = IUniswapV2Factory::new(*factory_v2, self.provider.clone());
+                let pair_address = factory
+                    .get_pair(token_a, token_b)
+                    .block(block_id)
+                    .call()
+                    .await
+                    .map_err(|e| UniswapError::ContractError(format!("Factory error: {}", e)))?;
+                if pair_address != Address::zero() {
+                    found.push((pair_address, PoolVersion::V2));
+                }
+            }
+            if *factory_v3 != Address::zero() {
+                let factory = IUniswapV3Factory::new(*factory_v3, self.provider.clone());
+                for fee in V3_FEE_TIERS.iter() {
+                    let pool_address = factory
+                        .get_pool(token_a, token_b, *fee)
+                        .block(block_id)
+                        .call()
+                        .await
+                        .map_err(|e| {
+                            UniswapError::ContractError(format!("V3 Factory error: {}", e))
+                        })?;
+                    if pool_address != Address::zero() {
+                        found.push((pool_address, PoolVersion::V3));
+                    }
+                }
+            }
+        }
+        let depth = Depth::new(self.provider.clone());
+        let mut seen = HashSet::new();
+        let mut pools = Vec::new();
+        for (pool_address, version) in found {
+            if seen.insert(pool_address) {
+                pools.push(
+                    depth
+                        .get_pool_liquidity(pool_address, version, block)
+                        .await?,
+                );
+            }
+        }
+        Ok(pools)
+    }
+
+    /// cost of `gas_units` in `token`, priced through its V2 pair with WETH on the first factory
+    pub async fn gas_cost(
+        &self,
+        token: Address,
+        gas_units: u64,
+        gas_price: U256,
+        block: Option<BlockId>,
+    ) -> Result<Option<U256>, UniswapError> {
+        let cost = gas_price * gas_units;
+        if token == self.weth {
+            return Ok(Some(cost));
+        }
+        let Some((factory_v2, _)) = self.factories.first() else {
+            return Ok(None);
+        };
+        let factory = IUniswapV2Factory::new(*factory_v2, self.provider.clone());
+        let pair_address = factory
+            .get_pair(token, self.weth)
+            .block(block_or_latest(block))
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("Factory error: {}", e)))?;
+        if pair_address == Address::zero() {
+            return Ok(None);
+        }
+        let (reserve0, reserve1, _) = UniswapV2::new_with_provider(self.provider.clone())
+            .get_reserves(pair_address, block)
+            .await?;
+        let (reserve_token, reserve_weth) = if token < self.weth {
+            (reserve0, reserve1)
+        } else {
+            (reserve1, reserve0)
+        };
+        Ok(v2::quote(cost, reserve_weth, reserve_token).ok())
+    }
+
+    /// arbitrage between the pools of a pair, most profitable after gas first. profits are in
+    /// WETH when it is one of the tokens.
+    pub async fn scan_pair(
+        &self,
+        token_a: Address,
+        token_b: Address,
+        block: Option<BlockId>,
+    ) -> Result<Vec<ArbitrageOpportunity>, UniswapError> {
+        let block_number = self
+            .provider
+            .get_block(block_or_latest(block))
+            .await
+            .map_err(UniswapError::EthersError)?
+            .and_then(|block| block.number)
+            .ok_or_else(|| UniswapError::ContractError("Block not found".to_string()))?
+            .as_u64();
+        let block = Some(BlockId::from(block_number));
+        let pools = self.get_pools(token_a, token_b, block).await?;
+        let token_in = if token_b == self.weth {
+            token_b
+        } else {
+            token_a
+        };
+        let mut opportunities = find_pair_arbitrage(&pools, token_in, block_number);
+        if opportunities.is_empty() {
+            return Ok(opportunities);
+        }
+        let gas_price = self
+            .provider
+            .get_gas_price()
+            .await
+            .map_err(UniswapError::EthersError)?;
+        for opportunity in opportunities.iter_mut() {
+            opportunity.gas_cost = self
+                .gas_cost(token_in, opportunity.gas_units, gas_price, block)
+                .await?;
+            opportunity.net_profit = opportunity
+                .gas_cost
+                .map(|gas_cost| I256::from_raw(opportunity.profit) - I256::from_raw(gas_cost));
+        }
+        opportunities.sort_by_key(|opportunity| {
+            std::cmp::Reverse((opportunity.net_profit, opportunity.profit))
+        });
+        Ok(opportunities)
+    }
+}
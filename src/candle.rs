@@ -0,0 +1,344 @@
+use ethers::{contract::parse_log, prelude::*, types::Filter};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+use crate::{
+    abi::{
+        i_uniswap_v2_pair::SwapFilter as V2SwapFilter,
+        i_uniswap_v3_pool::SwapFilter as V3SwapFilter,
+    },
+    events::get_logs_in_chunks,
+    price::Price,
+    tool::num::u256_to_decimal,
+    types::{Candle, CandleInterval, PoolInfo, PoolVersion, PricePoint, TokenInfo, UniswapError},
+    v2::UniswapV2,
+    v3::UniswapV3,
+};
+
+/// aggregates the swaps of a pool into OHLCV candles.
+/// swaps must be pushed in chain order.
+#[derive(Debug, Clone)]
+pub struct CandleBuilder {
+    pub pool: Address,
+    pub version: PoolVersion,
+    pub base: TokenInfo,
+    pub quote: TokenInfo,
+    pub interval: CandleInterval,
+    pub base_usd_price: Option<f64>,
+    pub quote_usd_price: Option<f64>,
+    candles: BTreeMap<u64, Candle>,
+}
+
+impl CandleBuilder {
+    /// price the pool's `base_token` in the other token of the pool
+    pub fn new(
+        pool_info: &PoolInfo,
+        version: PoolVersion,
+        base_token: Address,
+        interval: CandleInterval,
+    ) -> Result<Self, UniswapError> {
+        let (base, quote) = if pool_info.token0.address == base_token {
+            (pool_info.token0.clone(), pool_info.token1.clone())
+        } else if pool_info.token1.address == base_token {
+            (pool_info.token1.clone(), pool_info.token0.clone())
+        } else {
+            return Err(UniswapError::PoolNotFound);
+        };
+        Ok(Self {
+            pool: pool_info.address,
+            version,
+            base,
+            quote,
+            interval,
+            base_usd_price: None,
+            quote_usd_price: None,
+            candles: BTreeMap::new(),
+        })
+    }
+
+    /// the swap event topic of the pool version
+    pub fn swap_topic(&self) -> H256 {
+        match self.version {
+            PoolVersion::V2 => V2SwapFilter::signature(),
+            PoolVersion::V3 => V3SwapFilter::signature(),
+        }
+    }
+
+    /// decode a swap log of the pool into a price point, `timestamp` is the log's block timestamp
+    pub fn decode_log(&self, log: &Log, timestamp: u64) -> Result<PricePoint, UniswapError> {
+        if log.address != self.pool {
+            return Err(UniswapError::ContractError(
+                "Log belongs to a different pool".to_string(),
+            ));
+        }
+        let (amount0, amount1) = match self.version {
+            PoolVersion::V2 => {
+                let event = parse_log::<V2SwapFilter>(log.clone()).map_err(|e| {
+                    UniswapError::ContractError(format!("Swap decode error: {}", e))
+                })?;
+                // one side is paid in and the other paid out
+                (
+                    event.amount_0_in.abs_diff(event.amount_0_out),
+                    event.amount_1_in.abs_diff(event.amount_1_out),
+                )
+            }
+            PoolVersion::V3 => {
+                let event = parse_log::<V3SwapFilter>(log.clone()).map_err(|e| {
+                    UniswapError::ContractError(format!("Swap decode error: {}", e))
+                })?;
+                (event.amount_0.unsigned_abs(), event.amount_1.unsigned_abs())
+            }
+        };
+        let (base_amount, quote_amount) = if self.base.address < self.quote.address {
+            (amount0, amount1)
+        } else {
+            (amount1, amount0)
+        };
+        let base_amount = u256_to_decimal(base_amount, self.base.decimals);
+        let quote_amount = u256_to_decimal(quote_amount, self.quote.decimals);
+        if base_amount == 0.0 {
+            return Err(UniswapError::MathError(
+                "Swap has no base amount".to_string(),
+            ));
+        }
+        Ok(PricePoint {
+            pool: self.pool,
+            version: self.version,
+            tx_hash: log.transaction_hash.unwrap_or_default(),
+            block_number: log.block_number.unwrap_or_default().as_u64(),
+            log_index: log.log_index.unwrap_or_default().as_u64(),
+            timestamp,
+            price: quote_amount / base_amount,
+            base_amount,
+            quote_amount,
+        })
+    }
+
+    /// USD value of a trade, from the quote token price when known
+    fn volume_usd(&self, point: &PricePoint) -> Option<f64> {
+        self.quote_usd_price
+            .map(|usd| point.quote_amount * usd)
+            .or_else(|| self.base_usd_price.map(|usd| point.base_amount * usd))
+    }
+
+    /// aggregate a price point into its candle
+    pub fn push(&mut self, point: &PricePoint) -> &Candle {
+        let open_time = self.interval.open_time(point.timestamp);
+        let volume_usd = self.volume_usd(point);
+        let candle = self.candles.entry(open_time).or_insert_with(|| Candle {
+            pool: self.pool,
+            base_token: self.base.address,
+            quote_token: self.quote.address,
+            interval: self.interval.seconds(),
+            open_time,
+            open: point.price,
+            high: point.price,
+            low: point.price,
+            close: point.price,
+            volume_base: 0.0,
+            volume_quote: 0.0,
+            volume_usd: None,
+            trades: 0,
+        });
+        candle.high = candle.high.max(point.price);
+        candle.low = candle.low.min(point.price);
+        candle.close = point.price;
+        candle.volume_base += point.base_amount;
+        candle.volume_quote += point.quote_amount;
+        if let Some(volume_usd) = volume_usd {
+            candle.volume_usd = Some(candle.volume_usd.unwrap_or(0.0) + volume_usd);
+        }
+        candle.trades += 1;
+        candle
+    }
+
+    /// set the USD prices used for candle USD volume
+    pub fn set_usd_prices(&mut self, base_usd_price: Option<f64>, quote_usd_price: Option<f64>) {
+        self.base_usd_price = base_usd_price;
+        self.quote_usd_price = quote_usd_price;
+    }
+
+    /// refresh the USD prices from the price module at a block
+    pub async fn update_usd_prices(
+        &mut self,
+        price: &Price,
+        block: Option<BlockId>,
+    ) -> Result<(), UniswapError> {
+        let snapshot = price.snapshot(block).await?;
+        let quote_usd_price = price
+            .get_token_prices_at_snapshot(self.quote.address, &snapshot)
+            .await
+            .ok()
+            .and_then(|price_info| price_info.usd_price);
+        let base_usd_price = if quote_usd_price.is_none() {
+            price
+                .get_token_prices_at_snapshot(self.base.address, &snapshot)
+                .await
+                .ok()
+                .and_then(|price_info| price_info.usd_price)
+        } else {
+            None
+        };
+        self.set_usd_prices(base_usd_price, quote_usd_price);
+        Ok(())
+    }
+
+    /// the candle opened at `open_time`
+    pub fn candle(&self, open_time: u64) -> Option<&Candle> {
+        self.candles.get(&open_time)
+    }
+
+    /// the most recent candle
+    pub fn latest(&self) -> Option<&Candle> {
+        self.candles.values().next_back()
+    }
+
+    /// all candles in time order
+    pub fn candles(&self) -> Vec<Candle> {
+        self.candles.values().cloned().collect()
+    }
+}
+
+/// feeds a candle builder from historical swap logs and a live swap stream
+pub struct CandleFeed {
+    pub provider: Arc<Provider<Http>>,
+    pub builder: CandleBuilder,
+    timestamps: HashMap<u64, u64>,
+}
+
+impl CandleFeed {
+    pub fn new(provider: Arc<Provider<Http>>, builder: CandleBuilder) -> Self {
+        Self {
+            provider,
+            builder,
+            timestamps: HashMap::new(),
+        }
+    }
+
+    /// build a feed for a pool, pricing `base_token` in the other token of the pool
+    pub async fn new_with_pool(
+        provider: Arc<Provider<Http>>,
+        pool_address: Address,
+        version: PoolVersion,
+        base_token: Address,
+        interval: CandleInterval,
+    ) -> Result<Self, UniswapError> {
+        let pool_info = match version {
+            PoolVersion::V2 => {
+                UniswapV2::new_with_provider(provider.clone())
+                    .get_pool_info(pool_address, None)
+                    .await?
+            }
+            PoolVersion::V3 => {
+                UniswapV3::new_with_provider(provider.clone())
+                    .get_pool_info(pool_address, None)
+                    .await?
+            }
+        };
+        let builder = CandleBuilder::new(&pool_info, version, base_token, interval)?;
+        Ok(Self::new(provider, builder))
+    }
+
+    fn filter(&self) -> Filter {
+        Filter::new()
+            .address(self.builder.pool)
+            .topic0(self.builder.swap_topic())
+    }
+
+    /// block timestamp, cached per block. logs arrive in block order, so older blocks are
+    /// evicted once a newer one is fetched and the cache stays bounded while listening.
+    async fn block_timestamp(&mut self, block_number: u64) -> Result<u64, UniswapError> {
+        if let Some(timestamp) = self.timestamps.get(&block_number) {
+            return Ok(*timestamp);
+        }
+        let block = self
+            .provider
+            .get_block(block_number)
+            .await
+            .map_err(UniswapError::EthersError)?
+            .ok_or_else(|| UniswapError::ContractError("Block not found".to_string()))?;
+        let timestamp = block.timestamp.as_u64();
+        self.timestamps.retain(|cached, _| *cached > block_number);
+        self.timestamps.insert(block_number, timestamp);
+        Ok(timestamp)
+    }
+
+    /// decode a swap log and aggregate it, `None` when the log is not a well formed swap.
+    /// when a price module is given, USD prices are refreshed at the block of the first swap
+    /// of every new candle.
+    async fn push_log(
+        &mut self,
+        log: &Log,
+        price: Option<&Price>,
+    ) -> Result<Option<PricePoint>, UniswapError> {
+        let block_number = log.block_number.unwrap_or_default().as_u64();
+        let timestamp = self.block_timestamp(block_number).await?;
+        let Ok(point) = self.builder.decode_log(log, timestamp) else {
+            return Ok(None);
+        };
+        if let Some(price) = price
+            && self
+                .builder
+                .candle(self.builder.interval.open_time(timestamp))
+                .is_none()
+        {
+            self.builder
+                .update_usd_prices(price, Some(block_number.into()))
+                .await?;
+        }
+        self.builder.push(&point);
+        Ok(Some(point))
+    }
+
+    /// backfill candles from the swaps between two blocks, inclusive, returns the price series
+    pub async fn backfill(
+        &mut self,
+        from_block: u64,
+        to_block: u64,
+        price: Option<&Price>,
+    ) -> Result<Vec<PricePoint>, UniswapError> {
+        let logs = get_logs_in_chunks(self.provider.as_ref(), &self.filter(), from_block, to_block)
+            .await?;
+        let mut points = Vec::new();
+        for log in logs.iter() {
+            if let Some(point) = self.push_log(log, price).await? {
+                points.push(point);
+            }
+        }
+        Ok(points)
+    }
+
+    /// aggregate new swaps as they arrive, calling `on_swap` with each trade and its updated candle
+    pub async fn listen<F>(
+        &mut self,
+        price: Option<&Price>,
+        mut on_swap: F,
+    ) -> Result<(), UniswapError>
+    where
+        F: FnMut(&PricePoint, &Candle),
+    {
+        let filter = self.filter();
+        let provider = self.provider.clone();
+        let mut stream = provider
+            .watch(&filter)
+            .await
+            .map_err(UniswapError::EthersError)?;
+        while let Some(log) = stream.next().await {
+            if log.removed == Some(true) {
+                continue;
+            }
+            let Some(point) = self.push_log(&log, price).await? else {
+                continue;
+            };
+            if let Some(candle) = self
+                .builder
+                .candle(self.builder.interval.open_time(point.timestamp))
+            {
+                on_swap(&point, candle);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,254 @@
+use ethers::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    arbitrage::{ARBITRAGE_BASE_GAS, pool_capacity, search_optimal_amount_in, swap_gas},
+    depth::{pool_amount_out, pool_tokens},
+    state::PoolStateManager,
+    tool::num::u256_to_f64,
+    types::{ArbitrageCycle, CycleOpportunity, PoolLiquidity, UniswapError},
+};
+
+/// longest cycle searched by default
+pub const DEFAULT_MAX_HOPS: usize = 3;
+
+/// marginal rate of a swap out of `token_in`, fee included, in raw units
+fn marginal_rate(pool: &PoolLiquidity, token_in: Address) -> f64 {
+    match pool {
+        PoolLiquidity::V2(pool_info) => {
+            let (reserve_in, reserve_out) = if token_in == pool_info.token0.address {
+                (pool_info.reserve0, pool_info.reserve1)
+            } else {
+                (pool_info.reserve1, pool_info.reserve0)
+            };
+            if reserve_in.is_zero() {
+                return 0.0;
+            }
+            0.997 * u256_to_f64(reserve_out) / u256_to_f64(reserve_in)
+        }
+        PoolLiquidity::V3(state) => {
+            let sqrt_price = u256_to_f64(state.sqrt_price_x96) / 2f64.powi(96);
+            let price = sqrt_price * sqrt_price; // token1 per token0
+            let fee = 1.0 - state.fee as f64 / 1_000_000.0;
+            if state.liquidity == 0 || price == 0.0 {
+                0.0
+            } else if token_in == state.token0 {
+                price * fee
+            } else {
+                fee / price
+            }
+        }
+    }
+}
+
+/// amounts held after each swap of a cycle, starting with `amount_in`
+pub fn cycle_amounts(
+    state: &PoolStateManager,
+    cycle: &ArbitrageCycle,
+    amount_in: U256,
+) -> Result<Vec<U256>, UniswapError> {
+    let mut amounts = vec![amount_in];
+    let mut amount = amount_in;
+    for (pool_address, token_in) in cycle.pools.iter().zip(cycle.tokens.iter()) {
+        let pool = state.get(*pool_address).ok_or(UniswapError::PoolNotFound)?;
+        amount = if amount.is_zero() {
+            amount
+        } else {
+            pool_amount_out(pool, *token_in, amount)?
+        };
+        amounts.push(amount);
+    }
+    Ok(amounts)
+}
+
+/// searches the token graph of a pool state manager for profitable cycles by bounded DFS.
+/// cycles are screened with marginal rates and sized by exact integer simulation, and after
+/// the first scan only cycles through pools updated since the last evaluation are re-evaluated.
+pub struct CycleScanner {
+    pub start_tokens: Vec<Address>, // cycles start and end at these tokens, profits are in them
+    pub max_hops: usize,
+    cycles: Vec<ArbitrageCycle>,
+    by_pool: HashMap<Address, Vec<usize>>, // cycle indexes through each pool
+    indexed_pools: HashSet<Address>,
+    opportunities: HashMap<usize, CycleOpportunity>,
+    last_block: Option<u64>,
+}
+
+impl CycleScanner {
+    pub fn new(start_tokens: Vec<Address>) -> Self {
+        Self::new_with_max_hops(start_tokens, DEFAULT_MAX_HOPS)
+    }
+
+    pub fn new_with_max_hops(start_tokens: Vec<Address>, max_hops: usize) -> Self {
+        Self {
+            start_tokens,
+            max_hops,
+            cycles: Vec::new(),
+            by_pool: HashMap::new(),
+            indexed_pools: HashSet::new(),
+            opportunities: HashMap::new(),
+            last_block: None,
+        }
+    }
+
+    pub fn cycles(&self) -> &[ArbitrageCycle] {
+        &self.cycles
+    }
+
+    /// the profitable cycles of the last evaluation, most profitable first
+    pub fn opportunities(&self) -> Vec<CycleOpportunity> {
+        let mut opportunities: Vec<CycleOpportunity> =
+            self.opportunities.values().cloned().collect();
+        opportunities.sort_by_key(|opportunity| std::cmp::Reverse(opportunity.profit));
+        opportunities
+    }
+
+    /// enumerate the simple cycles of at most `max_hops` swaps through the start tokens.
+    /// a cycle through several start tokens is kept once, from the first of them.
+    pub fn build(&mut self, state: &PoolStateManager) {
+        let mut graph: HashMap<Address, Vec<(Address, Address)>> = HashMap::new();
+        let mut pool_addresses = state.pool_addresses();
+        pool_addresses.sort();
+        for pool_address in pool_addresses.iter() {
+            if let Some(pool) = state.get(*pool_address) {
+                let (token0, token1) = pool_tokens(pool);
+                graph
+                    .entry(token0)
+                    .or_default()
+                    .push((*pool_address, token1));
+                graph
+                    .entry(token1)
+                    .or_default()
+                    .push((*pool_address, token0));
+            }
+        }
+        self.cycles.clear();
+        for (index, start) in self.start_tokens.iter().enumerate() {
+            let skipped: HashSet<Address> = self.start_tokens[..index].iter().copied().collect();
+            let mut stack = vec![(vec![*start], Vec::<Address>::new())];
+            while let Some((tokens, pools)) = stack.pop() {
+                let current = tokens[tokens.len() - 1];
+                for (pool_address, next) in graph.get(&current).into_iter().flatten() {
+                    if pools.contains(pool_address) || skipped.contains(next) {
+                        continue;
+                    }
+                    let mut next_pools = pools.clone();
+                    next_pools.push(*pool_address);
+                    let mut next_tokens = tokens.clone();
+                    next_tokens.push(*next);
+                    if next == start {
+                        if next_pools.len() >= 2 {
+                            self.cycles.push(ArbitrageCycle {
+                                tokens: next_tokens,
+                                pools: next_pools,
+                            });
+                        }
+                    } else if next_pools.len() < self.max_hops && !tokens.contains(next) {
+                        stack.push((next_tokens, next_pools));
+                    }
+                }
+            }
+        }
+        self.by_pool.clear();
+        for (index, cycle) in self.cycles.iter().enumerate() {
+            for pool_address in cycle.pools.iter() {
+                self.by_pool.entry(*pool_address).or_default().push(index);
+            }
+        }
+        self.indexed_pools = pool_addresses.into_iter().collect();
+        self.opportunities.clear();
+        self.last_block = None;
+    }
+
+    /// size a cycle against the current state, `None` when it is not profitable
+    pub fn evaluate(&self, state: &PoolStateManager, index: usize) -> Option<CycleOpportunity> {
+        let cycle = self.cycles.get(index)?;
+        let mut pools = Vec::new();
+        for pool_address in cycle.pools.iter() {
+            pools.push(state.get(*pool_address)?);
+        }
+        // a cycle can only profit when the product of its marginal rates exceeds one,
+        // that is when its negative log rates sum below zero
+        let log_rate: f64 = pools
+            .iter()
+            .zip(cycle.tokens.iter())
+            .map(|(pool, token_in)| -marginal_rate(pool, *token_in).ln())
+            .sum();
+        if log_rate.is_nan() || log_rate >= 0.0 {
+            return None;
+        }
+        let upper = pool_capacity(pools[0], cycle.tokens[0]).ok()?;
+        let amount_in = search_optimal_amount_in(upper, |amount_in| {
+            cycle_amounts(state, cycle, amount_in).map(|amounts| amounts[amounts.len() - 1])
+        });
+        let amounts = cycle_amounts(state, cycle, amount_in).ok()?;
+        let amount_out = amounts[amounts.len() - 1];
+        if amount_out <= amount_in {
+            return None;
+        }
+        Some(CycleOpportunity {
+            cycle: cycle.clone(),
+            amount_in,
+            amounts,
+            profit: amount_out - amount_in,
+            gas_units: ARBITRAGE_BASE_GAS + pools.iter().map(|pool| swap_gas(pool)).sum::<u64>(),
+            block_number: state.last_block.unwrap_or_default(),
+        })
+    }
+
+    fn evaluate_cycles(&mut self, state: &PoolStateManager, indexes: impl Iterator<Item = usize>) {
+        for index in indexes {
+            match self.evaluate(state, index) {
+                Some(opportunity) => self.opportunities.insert(index, opportunity),
+                None => self.opportunities.remove(&index),
+            };
+        }
+        self.last_block = state.last_block;
+    }
+
+    /// whether the state tracks other pools than the cycles were built from
+    fn pools_changed(&self, state: &PoolStateManager) -> bool {
+        self.indexed_pools.len() != state.len()
+            || state
+                .pool_addresses()
+                .iter()
+                .any(|pool_address| !self.indexed_pools.contains(pool_address))
+    }
+
+    /// evaluate every cycle, building them first when the state's pools changed
+    pub fn scan(&mut self, state: &PoolStateManager) -> Vec<CycleOpportunity> {
+        if self.pools_changed(state) {
+            self.build(state);
+        }
+        self.evaluate_cycles(state, 0..self.cycles.len());
+        self.opportunities()
+    }
+
+    /// re-evaluate the cycles through pools updated since the last evaluation. falls back to
+    /// a full scan when the pools changed, or the state's history no longer covers the
+    /// blocks since. after a reorg rollback call `scan`, as rolled back pools are not
+    /// reported as updated.
+    pub fn update(&mut self, state: &PoolStateManager) -> Vec<CycleOpportunity> {
+        let Some(last_block) = self.last_block else {
+            return self.scan(state);
+        };
+        let history_start = state
+            .last_block
+            .unwrap_or_default()
+            .saturating_sub(state.reorg_depth);
+        if last_block < history_start || self.pools_changed(state) {
+            return self.scan(state);
+        }
+        let mut indexes: Vec<usize> = state
+            .changed_pools(last_block + 1)
+            .iter()
+            .filter_map(|pool_address| self.by_pool.get(pool_address))
+            .flatten()
+            .copied()
+            .collect();
+        indexes.sort_unstable();
+        indexes.dedup();
+        self.evaluate_cycles(state, indexes.into_iter());
+        self.opportunities()
+    }
+}
@@ -0,0 +1,383 @@
+use ethers::prelude::*;
+use std::sync::Arc;
+
+use crate::{
+    abi::{IUniswapV2Factory, IUniswapV3Factory},
+    global::mainnet::{MAINNET_UNISWAP_V2_FACTORY_ADDRESS, MAINNET_UNISWAP_V3_FACTORY_ADDRESS},
+    math::{full::mul_div, swap::simulate_swap, tick::sqrt_price_x96_to_price, v2},
+    price::V3_FEE_TIERS,
+    tool::block::block_or_latest,
+    tool::num::{u256_to_decimal, u256_to_f64},
+    types::{DepthPoint, MarketDepth, PoolLiquidity, PoolVersion, TokenInfo, UniswapError},
+    v2::UniswapV2,
+    v3::UniswapV3,
+};
+
+/// price moves in percent reported by market depth
+pub const DEPTH_LEVELS: [f64; 5] = [0.1, 0.5, 1.0, 2.0, 5.0];
+/// points sampled on a price impact curve
+pub const DEFAULT_CURVE_SAMPLES: usize = 20;
+/// V2 pair fee numerator over 1000
+const V2_FEE_NUMERATOR: f64 = 997.0;
+
+/// token0 and token1 of a pool
+pub(crate) fn pool_tokens(pool: &PoolLiquidity) -> (Address, Address) {
+    match pool {
+        PoolLiquidity::V2(pool_info) => (pool_info.token0.address, pool_info.token1.address),
+        PoolLiquidity::V3(state) => (state.token0, state.token1),
+    }
+}
+
+/// address of a pool
+pub(crate) fn pool_address(pool: &PoolLiquidity) -> Address {
+    match pool {
+        PoolLiquidity::V2(pool_info) => pool_info.address,
+        PoolLiquidity::V3(state) => state.pool,
+    }
+}
+
+/// spot price of token0 in token1, decimals adjusted
+pub fn spot_price(pool: &PoolLiquidity, decimals0: u8, decimals1: u8) -> f64 {
+    match pool {
+        PoolLiquidity::V2(pool_info) => {
+            let reserve0 = u256_to_decimal(pool_info.reserve0, decimals0);
+            let reserve1 = u256_to_decimal(pool_info.reserve1, decimals1);
+            if reserve0 > 0.0 {
+                reserve1 / reserve0
+            } else {
+                0.0
+            }
+        }
+        PoolLiquidity::V3(state) => {
+            sqrt_price_x96_to_price(state.sqrt_price_x96, decimals0, decimals1)
+        }
+    }
+}
+
+/// output amount of an exact input swap against a pool. V3 swaps that would run past the
+/// state's loaded tick window fail instead of filling partially.
+pub fn pool_amount_out(
+    pool: &PoolLiquidity,
+    token_in: Address,
+    amount_in: U256,
+) -> Result<U256, UniswapError> {
+    match pool {
+        PoolLiquidity::V2(pool_info) => {
+            if token_in == pool_info.token0.address {
+                v2::get_amount_out(amount_in, pool_info.reserve0, pool_info.reserve1)
+            } else if token_in == pool_info.token1.address {
+                v2::get_amount_out(amount_in, pool_info.reserve1, pool_info.reserve0)
+            } else {
+                Err(UniswapError::PoolNotFound)
+            }
+        }
+        PoolLiquidity::V3(state) => {
+            if token_in != state.token0 && token_in != state.token1 {
+                return Err(UniswapError::PoolNotFound);
+            }
+            let amount_specified = I256::try_from(amount_in)
+                .map_err(|_| UniswapError::MathError("Amount overflow".to_string()))?;
+            let simulation =
+                simulate_swap(state, token_in == state.token0, amount_specified, None)?;
+            if simulation.partial {
+                return Err(UniswapError::MathError(
+                    "Insufficient liquidity in loaded ticks".to_string(),
+                ));
+            }
+            Ok(simulation.amount_out)
+        }
+    }
+}
+
+/// V2 input needed to move the price of the input token, in the output token, to `ratio`
+/// times its current value. solves (x + 0.997 dx)(x + dx) = x^2 / ratio.
+fn v2_amount_in(reserve_in: f64, reserve_out: f64, ratio: f64) -> (f64, f64) {
+    let fee = V2_FEE_NUMERATOR / 1000.0;
+    let b = reserve_in * (1.0 + fee);
+    let c = reserve_in * reserve_in * (1.0 - 1.0 / ratio);
+    let amount_in = (-b + (b * b - 4.0 * fee * c).sqrt()) / (2.0 * fee);
+    let amount_out = fee * amount_in * reserve_out / (reserve_in + fee * amount_in);
+    (amount_in, amount_out)
+}
+
+/// raw amounts in and out of a trade that moves the marginal price of token0 by `impact`
+/// percent, down when selling token0 (`zero_for_one`) and up when buying it.
+pub fn amounts_to_price_impact(
+    pool: &PoolLiquidity,
+    zero_for_one: bool,
+    impact: f64,
+) -> Result<(f64, f64), UniswapError> {
+    let move_ratio = impact / 100.0;
+    if move_ratio <= 0.0 || (zero_for_one && move_ratio >= 1.0) {
+        return Err(UniswapError::MathError(format!(
+            "Invalid price impact: {}",
+            impact
+        )));
+    }
+    match pool {
+        PoolLiquidity::V2(pool_info) => {
+            let reserve0 = u256_to_f64(pool_info.reserve0);
+            let reserve1 = u256_to_f64(pool_info.reserve1);
+            if reserve0 == 0.0 || reserve1 == 0.0 {
+                return Err(UniswapError::MathError(
+                    "Insufficient liquidity".to_string(),
+                ));
+            }
+            if zero_for_one {
+                Ok(v2_amount_in(reserve0, reserve1, 1.0 - move_ratio))
+            } else {
+                Ok(v2_amount_in(reserve1, reserve0, 1.0 / (1.0 + move_ratio)))
+            }
+        }
+        PoolLiquidity::V3(state) => {
+            let factor = if zero_for_one {
+                (1.0 - move_ratio).sqrt()
+            } else {
+                (1.0 + move_ratio).sqrt()
+            };
+            let scale = U256::exp10(18);
+            let sqrt_price_limit_x96 = mul_div(
+                state.sqrt_price_x96,
+                U256::from((factor * 1e18) as u128),
+                scale,
+            )
+            .ok_or_else(|| UniswapError::MathError("Price limit overflow".to_string()))?;
+            let simulation =
+                simulate_swap(state, zero_for_one, I256::MAX, Some(sqrt_price_limit_x96))?;
+            if simulation.partial {
+                return Err(UniswapError::MathError(
+                    "Price impact beyond loaded ticks".to_string(),
+                ));
+            }
+            Ok((
+                u256_to_f64(simulation.amount_in),
+                u256_to_f64(simulation.amount_out),
+            ))
+        }
+    }
+}
+
+/// depth of a set of pools of the same pair at each price move, pools that cannot absorb a
+/// move contribute nothing to it.
+fn depth_points(
+    pools: &[PoolLiquidity],
+    token0: &TokenInfo,
+    token1: &TokenInfo,
+    zero_for_one: bool,
+    spot_price: f64,
+    impacts: &[f64],
+) -> Vec<DepthPoint> {
+    let (decimals_in, decimals_out) = if zero_for_one {
+        (token0.decimals, token1.decimals)
+    } else {
+        (token1.decimals, token0.decimals)
+    };
+    impacts
+        .iter()
+        .map(|impact| {
+            let (amount_in, amount_out) = pools
+                .iter()
+                .filter_map(|pool| amounts_to_price_impact(pool, zero_for_one, *impact).ok())
+                .fold(
+                    (0.0, 0.0),
+                    |(total_in, total_out), (amount_in, amount_out)| {
+                        (total_in + amount_in, total_out + amount_out)
+                    },
+                );
+            let amount_in = amount_in / 10f64.powi(decimals_in as i32);
+            let amount_out = amount_out / 10f64.powi(decimals_out as i32);
+            let execution_price = match (zero_for_one, amount_in > 0.0 && amount_out > 0.0) {
+                (_, false) => 0.0,
+                (true, true) => amount_out / amount_in,
+                (false, true) => amount_in / amount_out,
+            };
+            let slippage = if spot_price > 0.0 && execution_price > 0.0 {
+                (execution_price - spot_price).abs() / spot_price * 100.0
+            } else {
+                0.0
+            };
+            DepthPoint {
+                price_impact: *impact,
+                amount_in,
+                amount_out,
+                execution_price,
+                slippage,
+            }
+        })
+        .collect()
+}
+
+/// market depth of a pair across pools at the given price moves, with price impact curves
+/// sampled evenly up to the largest move.
+pub fn market_depth(
+    token0: &TokenInfo,
+    token1: &TokenInfo,
+    pools: &[PoolLiquidity],
+    levels: &[f64],
+    samples: usize,
+) -> Result<MarketDepth, UniswapError> {
+    if pools
+        .iter()
+        .any(|pool| pool_tokens(pool) != (token0.address, token1.address))
+    {
+        return Err(UniswapError::ContractError(
+            "Pools do not belong to the pair".to_string(),
+        ));
+    }
+    let first_level = levels.first().copied().unwrap_or(DEPTH_LEVELS[0]);
+    let deepest = pools
+        .iter()
+        .max_by(|a, b| {
+            let depth = |pool: &PoolLiquidity| {
+                amounts_to_price_impact(pool, true, first_level)
+                    .map(|(amount_in, _)| amount_in)
+                    .unwrap_or(0.0)
+            };
+            depth(a).total_cmp(&depth(b))
+        })
+        .ok_or(UniswapError::PoolNotFound)?;
+    let spot_price = spot_price(deepest, token0.decimals, token1.decimals);
+    let max_level = levels.iter().copied().fold(0.0, f64::max);
+    let curve: Vec<f64> = (1..=samples)
+        .map(|sample| max_level * sample as f64 / samples as f64)
+        .collect();
+    Ok(MarketDepth {
+        token0: token0.address,
+        token1: token1.address,
+        pools: pools.iter().map(pool_address).collect(),
+        spot_price,
+        sell: depth_points(pools, token0, token1, true, spot_price, levels),
+        buy: depth_points(pools, token0, token1, false, spot_price, levels),
+        sell_curve: depth_points(pools, token0, token1, true, spot_price, &curve),
+        buy_curve: depth_points(pools, token0, token1, false, spot_price, &curve),
+    })
+}
+
+/// market depth reader
+pub struct Depth {
+    provider: Arc<Provider<Http>>,
+    factory_v2: Address,
+    factory_v3: Address,
+}
+
+impl Depth {
+    pub fn new(provider: Arc<Provider<Http>>) -> Self {
+        Self {
+            provider,
+            factory_v2: MAINNET_UNISWAP_V2_FACTORY_ADDRESS.parse().unwrap(),
+            factory_v3: MAINNET_UNISWAP_V3_FACTORY_ADDRESS.parse().unwrap(),
+        }
+    }
+
+    pub fn new_with_factories(
+        provider: Arc<Provider<Http>>,
+        factory_v2: Address,
+        factory_v3: Address,
+    ) -> Self {
+        Self {
+            provider,
+            factory_v2,
+            factory_v3,
+        }
+    }
+
+    /// load the liquidity of a pool, V3 ticks are loaded far enough to cover the depth levels
+    pub async fn get_pool_liquidity(
+        &self,
+        pool_address: Address,
+        version: PoolVersion,
+        block: Option<BlockId>,
+    ) -> Result<PoolLiquidity, UniswapError> {
+        match version {
+            PoolVersion::V2 => Ok(PoolLiquidity::V2(
+                UniswapV2::new_with_provider(self.provider.clone())
+                    .get_pool_info(pool_address, block)
+                    .await?,
+            )),
+            PoolVersion::V3 => {
+                let max_level = DEPTH_LEVELS[DEPTH_LEVELS.len() - 1] / 100.0;
+                // ticks to a price move down by the largest level, plus the widest tick spacing
+                let tick_range = (-(1.0 - max_level).ln() / 1.0001f64.ln()).ceil() as i32 + 200;
+                Ok(PoolLiquidity::V3(
+                    UniswapV3::new_with_provider(self.provider.clone())
+                        .get_tick_state(pool_address, tick_range, block)
+                        .await?,
+                ))
+            }
+        }
+    }
+
+    /// find the V2 pair and V3 pools of a pair
+    pub async fn find_pools(
+        &self,
+        token_a: Address,
+        token_b: Address,
+        block: Option<BlockId>,
+    ) -> Result<Vec<(Address, PoolVersion)>, UniswapError> {
+        let block = block_or_latest(block);
+        let mut pools = Vec::new();
+        let factory_v2 = IUniswapV2Factory::new(self.factory_v2, self.provider.clone());
+        let pair_address = factory_v2
+            .get_pair(token_a, token_b)
+            .block(block)
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("Factory error: {}", e)))?;
+        if pair_address != Address::zero() {
+            pools.push((pair_address, PoolVersion::V2));
+        }
+        let factory_v3 = IUniswapV3Factory::new(self.factory_v3, self.provider.clone());
+        for fee in V3_FEE_TIERS.iter() {
+            let pool_address = factory_v3
+                .get_pool(token_a, token_b, *fee)
+                .block(block)
+                .call()
+                .await
+                .map_err(|e| UniswapError::ContractError(format!("V3 Factory error: {}", e)))?;
+            if pool_address != Address::zero() {
+                pools.push((pool_address, PoolVersion::V3));
+            }
+        }
+        Ok(pools)
+    }
+
+    /// get the market depth of a set of pools of the same pair
+    pub async fn get_pools_depth(
+        &self,
+        pools: &[(Address, PoolVersion)],
+        block: Option<BlockId>,
+    ) -> Result<MarketDepth, UniswapError> {
+        let mut liquidity = Vec::new();
+        for (pool_address, version) in pools.iter() {
+            liquidity.push(
+                self.get_pool_liquidity(*pool_address, *version, block)
+                    .await?,
+            );
+        }
+        let (token0, token1) = liquidity
+            .first()
+            .map(pool_tokens)
+            .ok_or(UniswapError::PoolNotFound)?;
+        let uniswap_v2 = UniswapV2::new_with_provider(self.provider.clone());
+        let token0 = uniswap_v2.get_token_info(token0, block).await?;
+        let token1 = uniswap_v2.get_token_info(token1, block).await?;
+        market_depth(
+            &token0,
+            &token1,
+            &liquidity,
+            &DEPTH_LEVELS,
+            DEFAULT_CURVE_SAMPLES,
+        )
+    }
+
+    /// get the market depth of a pair across its V2 pair and every V3 fee tier
+    pub async fn get_pair_depth(
+        &self,
+        token_a: Address,
+        token_b: Address,
+        block: Option<BlockId>,
+    ) -> Result<MarketDepth, UniswapError> {
+        let pools = self.find_pools(token_a, token_b, block).await?;
+        self.get_pools_depth(&pools, block).await
+    }
+}
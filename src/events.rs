@@ -1,2 +1,34 @@
+use ethers::{prelude::*, types::Filter};
+
+use crate::types::UniswapError;
+
 /// swap event
-pub const EVENT_SWAP: &'static str = "Swap(address,uint256,uint256,uint256,uint256,address)";
+pub const EVENT_SWAP: &str = "Swap(address,uint256,uint256,uint256,uint256,address)";
+
+/// blocks per eth_getLogs request
+pub const LOG_CHUNK_BLOCKS: u64 = 2000;
+
+/// get the logs matching `filter` between two blocks, inclusive, in chain order.
+/// the range is split into chunks to stay under provider limits.
+pub async fn get_logs_in_chunks<M: Middleware>(
+    provider: &M,
+    filter: &Filter,
+    from_block: u64,
+    to_block: u64,
+) -> Result<Vec<Log>, UniswapError> {
+    let mut logs = Vec::new();
+    let mut start = from_block;
+    while start <= to_block {
+        let end = (start + LOG_CHUNK_BLOCKS - 1).min(to_block);
+        let chunk_filter = filter.clone().from_block(start).to_block(end);
+        logs.extend(
+            provider
+                .get_logs(&chunk_filter)
+                .await
+                .map_err(|e| UniswapError::ProviderError(format!("Get logs error: {}", e)))?,
+        );
+        start = end + 1;
+    }
+    logs.sort_by_key(|log| (log.block_number, log.log_index));
+    Ok(logs)
+}
@@ -2,9 +2,19 @@ pub mod mainnet {
     /// mainnet uniswap v2 factory address
     pub const MAINNET_UNISWAP_V2_FACTORY_ADDRESS: &str =
         "0x5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f";
+    /// mainnet uniswap v2 router02 address
+    pub const MAINNET_UNISWAP_V2_ROUTER_ADDRESS: &str =
+        "0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D";
     /// mainnet uniswap v3 factory address
     pub const MAINNET_UNISWAP_V3_FACTORY_ADDRESS: &str =
         "0x1F98431c8aD98523631AE4a59f267346ea31F984";
+    /// block the mainnet uniswap v2 factory was deployed at
+    pub const MAINNET_UNISWAP_V2_FACTORY_BLOCK: u64 = 10000835;
+    /// block the mainnet uniswap v3 factory was deployed at
+    pub const MAINNET_UNISWAP_V3_FACTORY_BLOCK: u64 = 12369621;
+    /// mainnet uniswap v3 nonfungible position manager address
+    pub const MAINNET_UNISWAP_V3_POSITION_MANAGER_ADDRESS: &str =
+        "0xC36442b4a4522E871399CD717aBDD847Ab11FE88";
     /// mainnet WETH address
     pub const WETH_ADDRESS: &str = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2";
     /// mainnet USDC address
@@ -15,4 +25,10 @@ pub mod mainnet {
     pub const DAI_ADDRESS: &str = "0x6B175474E89094C44Da98b954EedeAC495271d0F";
     /// mainnet WBTC address
     pub const WBTC_ADDRESS: &str = "0x2260FAC5E5542a773Aa44fBCfeDf7C193bc2C599";
+    /// mainnet unicrypt v2 liquidity locker address
+    pub const MAINNET_UNICRYPT_V2_LOCKER_ADDRESS: &str =
+        "0x663A5C229c09b049E36dCc11a9B0d4a8Eb9db214";
+    /// mainnet team finance liquidity locker address
+    pub const MAINNET_TEAM_FINANCE_LOCKER_ADDRESS: &str =
+        "0xE2fE530C047f2d85298b07D9333C05737f1435fB";
 }
@@ -5,4 +5,24 @@ pub mod v2;
 pub mod v3;
 pub mod global;
 pub mod tool;
-pub mod events;
\ No newline at end of file
+pub mod events;
+pub mod math;
+pub mod position;
+pub mod liquidity;
+pub mod oracle;
+pub mod candle;
+pub mod analytics;
+pub mod pnl;
+pub mod depth;
+pub mod registry;
+pub mod state;
+pub mod snapshot;
+pub mod replay;
+pub mod mock;
+pub mod token;
+pub mod tokenlist;
+pub mod tax;
+pub mod swap;
+pub mod risk;
+pub mod arbitrage;
+pub mod cycle;
@@ -0,0 +1,525 @@
+use ethers::{prelude::*, types::transaction::eip2718::TypedTransaction, utils::keccak256};
+use std::sync::Arc;
+
+use crate::{
+    abi::{IUniswapV2Factory, IUniswapV2Pair, IUniswapV2Router},
+    global::mainnet::{MAINNET_UNISWAP_V2_FACTORY_ADDRESS, MAINNET_UNISWAP_V2_ROUTER_ADDRESS},
+    math::{liquidity::apply_slippage, v2},
+    tool::num::u256_to_f64,
+    tool::tx::{WalletClient, ensure_allowance, send_transaction, wallet_client},
+    types::{
+        AddLiquidityQuote, AddLiquidityRequest, PoolInfo, RemoveLiquidityQuote,
+        RemoveLiquidityRequest, TransactionResult, UniswapError,
+    },
+    v2::UniswapV2,
+};
+
+/// uniswap v2 router liquidity client
+pub struct LiquidityRouter {
+    pub provider: Arc<Provider<Http>>,
+    pub router: Address,
+    pub v2_factory: Address,
+}
+
+/// pair state needed for offline LP math
+#[derive(Debug, Clone)]
+pub struct PairState {
+    pub pool_info: PoolInfo,
+    pub k_last: U256,
+    pub fee_on: bool,
+}
+
+impl PairState {
+    /// reserves ordered as (token_a, token_b)
+    pub fn reserves_for(&self, token_a: Address) -> Result<(U256, U256), UniswapError> {
+        if self.pool_info.token0.address == token_a {
+            Ok((self.pool_info.reserve0, self.pool_info.reserve1))
+        } else if self.pool_info.token1.address == token_a {
+            Ok((self.pool_info.reserve1, self.pool_info.reserve0))
+        } else {
+            Err(UniswapError::PoolNotFound)
+        }
+    }
+
+    /// quote an addLiquidity for the desired amounts
+    pub fn quote_add_liquidity(
+        &self,
+        request: &AddLiquidityRequest,
+    ) -> Result<AddLiquidityQuote, UniswapError> {
+        let (reserve_a, reserve_b) = self.reserves_for(request.token_a)?;
+        let (amount_a, amount_b) = v2::get_optimal_amounts(
+            request.amount_a_desired,
+            request.amount_b_desired,
+            reserve_a,
+            reserve_b,
+        )?;
+        let total_supply = self.pool_info.liquidity;
+        let (liquidity_minted, protocol_fee_liquidity) = v2::get_liquidity_minted(
+            amount_a,
+            amount_b,
+            reserve_a,
+            reserve_b,
+            total_supply,
+            self.k_last,
+            self.fee_on,
+        )?;
+        let supply_after = if total_supply.is_zero() {
+            liquidity_minted + v2::MINIMUM_LIQUIDITY
+        } else {
+            total_supply + protocol_fee_liquidity + liquidity_minted
+        };
+        Ok(AddLiquidityQuote {
+            amount_a,
+            amount_b,
+            amount_a_min: apply_slippage(amount_a, request.slippage),
+            amount_b_min: apply_slippage(amount_b, request.slippage),
+            liquidity_minted,
+            protocol_fee_liquidity,
+            pool_share: u256_to_f64(liquidity_minted) / u256_to_f64(supply_after),
+        })
+    }
+
+    /// quote a removeLiquidity for an LP balance
+    pub fn quote_remove_liquidity(
+        &self,
+        request: &RemoveLiquidityRequest,
+    ) -> Result<RemoveLiquidityQuote, UniswapError> {
+        let (reserve_a, reserve_b) = self.reserves_for(request.token_a)?;
+        let (amount_a, amount_b, protocol_fee_liquidity) = v2::get_liquidity_value(
+            request.liquidity,
+            reserve_a,
+            reserve_b,
+            self.pool_info.liquidity,
+            self.k_last,
+            self.fee_on,
+        )?;
+        Ok(RemoveLiquidityQuote {
+            amount_a,
+            amount_b,
+            amount_a_min: apply_slippage(amount_a, request.slippage),
+            amount_b_min: apply_slippage(amount_b, request.slippage),
+            protocol_fee_liquidity,
+        })
+    }
+}
+
+impl LiquidityRouter {
+    pub fn new(provider: Arc<Provider<Http>>) -> Self {
+        Self {
+            provider,
+            router: MAINNET_UNISWAP_V2_ROUTER_ADDRESS.parse().unwrap(),
+            v2_factory: MAINNET_UNISWAP_V2_FACTORY_ADDRESS.parse().unwrap(),
+        }
+    }
+
+    pub fn new_with_address(
+        provider: Arc<Provider<Http>>,
+        router: Address,
+        v2_factory: Address,
+    ) -> Self {
+        Self {
+            provider,
+            router,
+            v2_factory,
+        }
+    }
+
+    fn contract(&self) -> IUniswapV2Router<Provider<Http>> {
+        IUniswapV2Router::new(self.router, self.provider.clone())
+    }
+
+    /// get pair address by tokens
+    pub async fn get_pair_address(
+        &self,
+        token_a: Address,
+        token_b: Address,
+    ) -> Result<Address, UniswapError> {
+        let factory = IUniswapV2Factory::new(self.v2_factory, self.provider.clone());
+        let pair_address = factory
+            .get_pair(token_a, token_b)
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("Factory error: {}", e)))?;
+        if pair_address == Address::zero() {
+            return Err(UniswapError::PoolNotFound);
+        }
+        Ok(pair_address)
+    }
+
+    /// get pair state, including kLast and whether the protocol fee is on
+    pub async fn get_pair_state(&self, pair_address: Address) -> Result<PairState, UniswapError> {
+        let uniswap_v2 = UniswapV2::new_with_provider(self.provider.clone());
+        let pool_info = uniswap_v2.get_pool_info(pair_address, None).await?;
+        let pair_contract = IUniswapV2Pair::new(pair_address, self.provider.clone());
+        let k_last = pair_contract
+            .k_last()
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("KLast error: {}", e)))?;
+        let factory = IUniswapV2Factory::new(self.v2_factory, self.provider.clone());
+        let fee_to = factory
+            .fee_to()
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("FeeTo error: {}", e)))?;
+        Ok(PairState {
+            pool_info,
+            k_last,
+            fee_on: fee_to != Address::zero(),
+        })
+    }
+
+    /// quote an addLiquidity against the current pair state
+    pub async fn quote_add_liquidity(
+        &self,
+        request: &AddLiquidityRequest,
+    ) -> Result<AddLiquidityQuote, UniswapError> {
+        match self
+            .get_pair_address(request.token_a, request.token_b)
+            .await
+        {
+            Ok(pair_address) => self
+                .get_pair_state(pair_address)
+                .await?
+                .quote_add_liquidity(request),
+            // the router creates the pair on the first deposit
+            Err(UniswapError::PoolNotFound) => {
+                let (liquidity_minted, _) = v2::get_liquidity_minted(
+                    request.amount_a_desired,
+                    request.amount_b_desired,
+                    U256::zero(),
+                    U256::zero(),
+                    U256::zero(),
+                    U256::zero(),
+                    false,
+                )?;
+                Ok(AddLiquidityQuote {
+                    amount_a: request.amount_a_desired,
+                    amount_b: request.amount_b_desired,
+                    amount_a_min: apply_slippage(request.amount_a_desired, request.slippage),
+                    amount_b_min: apply_slippage(request.amount_b_desired, request.slippage),
+                    liquidity_minted,
+                    protocol_fee_liquidity: U256::zero(),
+                    pool_share: 1.0,
+                })
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// quote a removeLiquidity against the current pair state
+    pub async fn quote_remove_liquidity(
+        &self,
+        request: &RemoveLiquidityRequest,
+    ) -> Result<RemoveLiquidityQuote, UniswapError> {
+        let pair_address = self
+            .get_pair_address(request.token_a, request.token_b)
+            .await?;
+        self.get_pair_state(pair_address)
+            .await?
+            .quote_remove_liquidity(request)
+    }
+
+    /// build addLiquidity transaction
+    pub fn build_add_liquidity_tx(
+        &self,
+        request: &AddLiquidityRequest,
+        quote: &AddLiquidityQuote,
+    ) -> TypedTransaction {
+        self.contract()
+            .add_liquidity(
+                request.token_a,
+                request.token_b,
+                request.amount_a_desired,
+                request.amount_b_desired,
+                quote.amount_a_min,
+                quote.amount_b_min,
+                request.recipient,
+                request.deadline.into(),
+            )
+            .tx
+    }
+
+    /// build addLiquidityETH transaction, `token_b` of the request is the ETH leg
+    pub fn build_add_liquidity_eth_tx(
+        &self,
+        request: &AddLiquidityRequest,
+        quote: &AddLiquidityQuote,
+    ) -> TypedTransaction {
+        self.contract()
+            .add_liquidity_eth(
+                request.token_a,
+                request.amount_a_desired,
+                quote.amount_a_min,
+                quote.amount_b_min,
+                request.recipient,
+                request.deadline.into(),
+            )
+            .value(request.amount_b_desired)
+            .tx
+    }
+
+    /// build removeLiquidity transaction
+    pub fn build_remove_liquidity_tx(
+        &self,
+        request: &RemoveLiquidityRequest,
+        quote: &RemoveLiquidityQuote,
+    ) -> TypedTransaction {
+        self.contract()
+            .remove_liquidity(
+                request.token_a,
+                request.token_b,
+                request.liquidity,
+                quote.amount_a_min,
+                quote.amount_b_min,
+                request.recipient,
+                request.deadline.into(),
+            )
+            .tx
+    }
+
+    /// build removeLiquidityETH transaction, `token_b` of the request is the ETH leg
+    pub fn build_remove_liquidity_eth_tx(
+        &self,
+        request: &RemoveLiquidityRequest,
+        quote: &RemoveLiquidityQuote,
+    ) -> TypedTransaction {
+        self.contract()
+            .remove_liquidity_eth(
+                request.token_a,
+                request.liquidity,
+                quote.amount_a_min,
+                quote.amount_b_min,
+                request.recipient,
+                request.deadline.into(),
+            )
+            .tx
+    }
+
+    /// build removeLiquidityWithPermit transaction from a signed permit
+    pub fn build_remove_liquidity_with_permit_tx(
+        &self,
+        request: &RemoveLiquidityRequest,
+        quote: &RemoveLiquidityQuote,
+        signature: &Signature,
+    ) -> TypedTransaction {
+        self.contract()
+            .remove_liquidity_with_permit(
+                request.token_a,
+                request.token_b,
+                request.liquidity,
+                quote.amount_a_min,
+                quote.amount_b_min,
+                request.recipient,
+                request.deadline.into(),
+                false,
+                signature.v as u8,
+                signature.r.into(),
+                signature.s.into(),
+            )
+            .tx
+    }
+
+    /// build removeLiquidityETHWithPermit transaction from a signed permit
+    pub fn build_remove_liquidity_eth_with_permit_tx(
+        &self,
+        request: &RemoveLiquidityRequest,
+        quote: &RemoveLiquidityQuote,
+        signature: &Signature,
+    ) -> TypedTransaction {
+        self.contract()
+            .remove_liquidity_eth_with_permit(
+                request.token_a,
+                request.liquidity,
+                quote.amount_a_min,
+                quote.amount_b_min,
+                request.recipient,
+                request.deadline.into(),
+                false,
+                signature.v as u8,
+                signature.r.into(),
+                signature.s.into(),
+            )
+            .tx
+    }
+
+    /// sign an EIP-2612 style permit allowing the router to spend `liquidity` LP tokens
+    pub async fn sign_permit(
+        &self,
+        wallet: &LocalWallet,
+        pair_address: Address,
+        liquidity: U256,
+        deadline: u64,
+    ) -> Result<Signature, UniswapError> {
+        let pair_contract = IUniswapV2Pair::new(pair_address, self.provider.clone());
+        let domain_separator =
+            pair_contract.domain_separator().call().await.map_err(|e| {
+                UniswapError::ContractError(format!("Domain separator error: {}", e))
+            })?;
+        let nonce = pair_contract
+            .nonces(wallet.address())
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("Nonces error: {}", e)))?;
+        let permit_typehash = keccak256(
+            "Permit(address owner,address spender,uint256 value,uint256 nonce,uint256 deadline)",
+        );
+        let struct_hash = keccak256(ethers::abi::encode(&[
+            ethers::abi::Token::FixedBytes(permit_typehash.to_vec()),
+            ethers::abi::Token::Address(wallet.address()),
+            ethers::abi::Token::Address(self.router),
+            ethers::abi::Token::Uint(liquidity),
+            ethers::abi::Token::Uint(nonce),
+            ethers::abi::Token::Uint(deadline.into()),
+        ]));
+        let mut message = Vec::with_capacity(66);
+        message.extend_from_slice(&[0x19, 0x01]);
+        message.extend_from_slice(&domain_separator);
+        message.extend_from_slice(&struct_hash);
+        wallet
+            .sign_hash(H256::from(keccak256(message)))
+            .map_err(|e| UniswapError::WalletError(format!("Permit sign error: {}", e)))
+    }
+
+    async fn execute(
+        &self,
+        client: Arc<WalletClient>,
+        tx: TypedTransaction,
+    ) -> Result<TransactionResult, UniswapError> {
+        let (result, _) = send_transaction(client, tx).await?;
+        Ok(result)
+    }
+
+    /// add liquidity to a token/token pair
+    pub async fn add_liquidity(
+        &self,
+        wallet: LocalWallet,
+        request: &AddLiquidityRequest,
+    ) -> Result<TransactionResult, UniswapError> {
+        let quote = self.quote_add_liquidity(request).await?;
+        let client = wallet_client(self.provider.clone(), wallet).await?;
+        ensure_allowance(
+            client.clone(),
+            request.token_a,
+            self.router,
+            request.amount_a_desired,
+        )
+        .await?;
+        ensure_allowance(
+            client.clone(),
+            request.token_b,
+            self.router,
+            request.amount_b_desired,
+        )
+        .await?;
+        self.execute(client, self.build_add_liquidity_tx(request, &quote))
+            .await
+    }
+
+    /// add liquidity to a token/WETH pair paying with ETH, `token_b` of the request must be WETH
+    pub async fn add_liquidity_eth(
+        &self,
+        wallet: LocalWallet,
+        request: &AddLiquidityRequest,
+    ) -> Result<TransactionResult, UniswapError> {
+        self.check_weth(request.token_b).await?;
+        let quote = self.quote_add_liquidity(request).await?;
+        let client = wallet_client(self.provider.clone(), wallet).await?;
+        ensure_allowance(
+            client.clone(),
+            request.token_a,
+            self.router,
+            request.amount_a_desired,
+        )
+        .await?;
+        self.execute(client, self.build_add_liquidity_eth_tx(request, &quote))
+            .await
+    }
+
+    /// remove liquidity from a token/token pair
+    pub async fn remove_liquidity(
+        &self,
+        wallet: LocalWallet,
+        request: &RemoveLiquidityRequest,
+    ) -> Result<TransactionResult, UniswapError> {
+        let quote = self.quote_remove_liquidity(request).await?;
+        let pair_address = self
+            .get_pair_address(request.token_a, request.token_b)
+            .await?;
+        let client = wallet_client(self.provider.clone(), wallet).await?;
+        ensure_allowance(client.clone(), pair_address, self.router, request.liquidity).await?;
+        self.execute(client, self.build_remove_liquidity_tx(request, &quote))
+            .await
+    }
+
+    /// remove liquidity from a token/WETH pair receiving ETH, `token_b` of the request must be WETH
+    pub async fn remove_liquidity_eth(
+        &self,
+        wallet: LocalWallet,
+        request: &RemoveLiquidityRequest,
+    ) -> Result<TransactionResult, UniswapError> {
+        self.check_weth(request.token_b).await?;
+        let quote = self.quote_remove_liquidity(request).await?;
+        let pair_address = self
+            .get_pair_address(request.token_a, request.token_b)
+            .await?;
+        let client = wallet_client(self.provider.clone(), wallet).await?;
+        ensure_allowance(client.clone(), pair_address, self.router, request.liquidity).await?;
+        self.execute(client, self.build_remove_liquidity_eth_tx(request, &quote))
+            .await
+    }
+
+    /// remove liquidity using a permit signature instead of an approve transaction
+    pub async fn remove_liquidity_with_permit(
+        &self,
+        wallet: LocalWallet,
+        request: &RemoveLiquidityRequest,
+    ) -> Result<TransactionResult, UniswapError> {
+        let quote = self.quote_remove_liquidity(request).await?;
+        let pair_address = self
+            .get_pair_address(request.token_a, request.token_b)
+            .await?;
+        let signature = self
+            .sign_permit(&wallet, pair_address, request.liquidity, request.deadline)
+            .await?;
+        let client = wallet_client(self.provider.clone(), wallet).await?;
+        let tx = self.build_remove_liquidity_with_permit_tx(request, &quote, &signature);
+        self.execute(client, tx).await
+    }
+
+    /// remove liquidity receiving ETH using a permit signature, `token_b` of the request must be WETH
+    pub async fn remove_liquidity_eth_with_permit(
+        &self,
+        wallet: LocalWallet,
+        request: &RemoveLiquidityRequest,
+    ) -> Result<TransactionResult, UniswapError> {
+        self.check_weth(request.token_b).await?;
+        let quote = self.quote_remove_liquidity(request).await?;
+        let pair_address = self
+            .get_pair_address(request.token_a, request.token_b)
+            .await?;
+        let signature = self
+            .sign_permit(&wallet, pair_address, request.liquidity, request.deadline)
+            .await?;
+        let client = wallet_client(self.provider.clone(), wallet).await?;
+        let tx = self.build_remove_liquidity_eth_with_permit_tx(request, &quote, &signature);
+        self.execute(client, tx).await
+    }
+
+    /// check that a token is the WETH used by the router
+    async fn check_weth(&self, token: Address) -> Result<(), UniswapError> {
+        let weth = self
+            .contract()
+            .weth()
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("WETH error: {}", e)))?;
+        if weth != token {
+            return Err(UniswapError::InvalidAddress(format!(
+                "{:?} is not the router WETH",
+                token
+            )));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,871 @@
+/// full precision math module
+pub mod full {
+    use ethers::types::{U256, U512};
+
+    /// calculates floor(a * b / denominator) with full precision.
+    pub fn mul_div(a: U256, b: U256, denominator: U256) -> Option<U256> {
+        if denominator.is_zero() {
+            return None;
+        }
+        let result = a.full_mul(b) / U512::from(denominator);
+        U256::try_from(result).ok()
+    }
+
+    /// calculates ceil(a * b / denominator) with full precision.
+    pub fn mul_div_rounding_up(a: U256, b: U256, denominator: U256) -> Option<U256> {
+        if denominator.is_zero() {
+            return None;
+        }
+        let product = a.full_mul(b);
+        let denominator = U512::from(denominator);
+        let mut result = product / denominator;
+        if !(product % denominator).is_zero() {
+            result += U512::one();
+        }
+        U256::try_from(result).ok()
+    }
+
+    /// integer square root (floor).
+    pub fn sqrt(value: U256) -> U256 {
+        value.integer_sqrt()
+    }
+}
+
+/// uniswap v3 tick math module
+pub mod tick {
+    use crate::types::UniswapError;
+    use ethers::types::U256;
+
+    /// the minimum tick that may be passed to `get_sqrt_ratio_at_tick`
+    pub const MIN_TICK: i32 = -887272;
+    /// the maximum tick that may be passed to `get_sqrt_ratio_at_tick`
+    pub const MAX_TICK: i32 = 887272;
+
+    /// the minimum value that can be returned from `get_sqrt_ratio_at_tick`
+    pub fn min_sqrt_ratio() -> U256 {
+        U256::from(4295128739u64)
+    }
+
+    /// the maximum value that can be returned from `get_sqrt_ratio_at_tick`
+    pub fn max_sqrt_ratio() -> U256 {
+        U256::from_dec_str("1461446703485210103287273052203988822378723970342").unwrap()
+    }
+
+    /// calculates sqrt(1.0001^tick) * 2^96, ported from TickMath.getSqrtRatioAtTick.
+    pub fn get_sqrt_ratio_at_tick(tick: i32) -> Result<U256, UniswapError> {
+        if !(MIN_TICK..=MAX_TICK).contains(&tick) {
+            return Err(UniswapError::MathError(format!(
+                "Tick out of range: {}",
+                tick
+            )));
+        }
+        let abs_tick = tick.unsigned_abs();
+        let mut ratio = if abs_tick & 0x1 != 0 {
+            hex_u256("fffcb933bd6fad37aa2d162d1a594001")
+        } else {
+            U256::one() << 128
+        };
+        const MULTIPLIERS: [(u32, &str); 19] = [
+            (0x2, "fff97272373d413259a46990580e213a"),
+            (0x4, "fff2e50f5f656932ef12357cf3c7fdcc"),
+            (0x8, "ffe5caca7e10e4e61c3624eaa0941cd0"),
+            (0x10, "ffcb9843d60f6159c9db58835c926644"),
+            (0x20, "ff973b41fa98c081472e6896dfb254c0"),
+            (0x40, "ff2ea16466c96a3843ec78b326b52861"),
+            (0x80, "fe5dee046a99a2a811c461f1969c3053"),
+            (0x100, "fcbe86c7900a88aedcffc83b479aa3a4"),
+            (0x200, "f987a7253ac413176f2b074cf7815e54"),
+            (0x400, "f3392b0822b70005940c7a398e4b70f3"),
+            (0x800, "e7159475a2c29b7443b29c7fa6e889d9"),
+            (0x1000, "d097f3bdfd2022b8845ad8f792aa5825"),
+            (0x2000, "a9f746462d870fdf8a65dc1f90e061e5"),
+            (0x4000, "70d869a156d2a1b890bb3df62baf32f7"),
+            (0x8000, "31be135f97d08fd981231505542fcfa6"),
+            (0x10000, "9aa508b5b7a84e1c677de54f3e99bc9"),
+            (0x20000, "5d6af8dedb81196699c329225ee604"),
+            (0x40000, "2216e584f5fa1ea926041bedfe98"),
+            (0x80000, "48a170391f7dc42444e8fa2"),
+        ];
+        for (mask, multiplier) in MULTIPLIERS.iter() {
+            if abs_tick & mask != 0 {
+                ratio = (ratio * hex_u256(multiplier)) >> 128;
+            }
+        }
+        if tick > 0 {
+            ratio = U256::MAX / ratio;
+        }
+        // round up to go from a Q128.128 to a Q128.96
+        let remainder = ratio % (U256::one() << 32);
+        let sqrt_price_x96 = (ratio >> 32) + if remainder.is_zero() { 0 } else { 1 };
+        Ok(sqrt_price_x96)
+    }
+
+    /// calculates the greatest tick value such that get_sqrt_ratio_at_tick(tick) <= sqrt_price_x96.
+    pub fn get_tick_at_sqrt_ratio(sqrt_price_x96: U256) -> Result<i32, UniswapError> {
+        if sqrt_price_x96 < min_sqrt_ratio() || sqrt_price_x96 >= max_sqrt_ratio() {
+            return Err(UniswapError::MathError(format!(
+                "Sqrt price out of range: {}",
+                sqrt_price_x96
+            )));
+        }
+        let (mut low, mut high) = (MIN_TICK, MAX_TICK);
+        while low < high {
+            // upper middle, so that the loop always makes progress
+            let mid = low + (high - low + 1) / 2;
+            if get_sqrt_ratio_at_tick(mid)? <= sqrt_price_x96 {
+                low = mid;
+            } else {
+                high = mid - 1;
+            }
+        }
+        Ok(low)
+    }
+
+    /// rounds a tick to the nearest tick usable with the given tick spacing.
+    pub fn nearest_usable_tick(tick: i32, tick_spacing: i32) -> i32 {
+        let rounded = ((tick as f64 / tick_spacing as f64).round() as i32) * tick_spacing;
+        if rounded < MIN_TICK {
+            rounded + tick_spacing
+        } else if rounded > MAX_TICK {
+            rounded - tick_spacing
+        } else {
+            rounded
+        }
+    }
+
+    /// convert a human readable price of token0 in token1 to the tick at or below it.
+    pub fn price_to_tick(price: f64, decimals0: u8, decimals1: u8) -> i32 {
+        let raw_price = price * 10f64.powi(decimals1 as i32 - decimals0 as i32);
+        (raw_price.ln() / 1.0001f64.ln()).floor() as i32
+    }
+
+    /// convert a tick to the human readable price of token0 in token1.
+    pub fn tick_to_price(tick: i32, decimals0: u8, decimals1: u8) -> f64 {
+        1.0001f64.powi(tick) * 10f64.powi(decimals0 as i32 - decimals1 as i32)
+    }
+
+    /// convert a sqrtPriceX96 to the human readable price of token0 in token1.
+    pub fn sqrt_price_x96_to_price(sqrt_price_x96: U256, decimals0: u8, decimals1: u8) -> f64 {
+        let sqrt_price = crate::tool::num::u256_to_f64(sqrt_price_x96) / 2f64.powi(96);
+        sqrt_price * sqrt_price * 10f64.powi(decimals0 as i32 - decimals1 as i32)
+    }
+
+    fn hex_u256(value: &str) -> U256 {
+        U256::from_str_radix(value, 16).unwrap()
+    }
+}
+
+/// uniswap v3 liquidity amounts module, ported from LiquidityAmounts.sol
+pub mod liquidity {
+    use super::full::mul_div;
+    use crate::types::UniswapError;
+    use ethers::types::U256;
+
+    /// 2^96
+    pub fn q96() -> U256 {
+        U256::one() << 96
+    }
+
+    fn sort(a: U256, b: U256) -> (U256, U256) {
+        if a > b { (b, a) } else { (a, b) }
+    }
+
+    fn to_u128(value: U256) -> Result<u128, UniswapError> {
+        if value > U256::from(u128::MAX) {
+            return Err(UniswapError::MathError(
+                "Liquidity overflows uint128".to_string(),
+            ));
+        }
+        Ok(value.as_u128())
+    }
+
+    /// computes the amount of liquidity received for a given amount of token0 and price range.
+    pub fn get_liquidity_for_amount0(
+        sqrt_ratio_a_x96: U256,
+        sqrt_ratio_b_x96: U256,
+        amount0: U256,
+    ) -> Result<u128, UniswapError> {
+        let (sqrt_a, sqrt_b) = sort(sqrt_ratio_a_x96, sqrt_ratio_b_x96);
+        let intermediate = mul_div(sqrt_a, sqrt_b, q96())
+            .ok_or_else(|| UniswapError::MathError("Liquidity overflow".to_string()))?;
+        let liquidity = mul_div(amount0, intermediate, sqrt_b - sqrt_a)
+            .ok_or_else(|| UniswapError::MathError("Empty price range".to_string()))?;
+        to_u128(liquidity)
+    }
+
+    /// computes the amount of liquidity received for a given amount of token1 and price range.
+    pub fn get_liquidity_for_amount1(
+        sqrt_ratio_a_x96: U256,
+        sqrt_ratio_b_x96: U256,
+        amount1: U256,
+    ) -> Result<u128, UniswapError> {
+        let (sqrt_a, sqrt_b) = sort(sqrt_ratio_a_x96, sqrt_ratio_b_x96);
+        let liquidity = mul_div(amount1, q96(), sqrt_b - sqrt_a)
+            .ok_or_else(|| UniswapError::MathError("Empty price range".to_string()))?;
+        to_u128(liquidity)
+    }
+
+    /// computes the maximum amount of liquidity received for the given amounts, the current
+    /// pool price and the prices at the tick boundaries.
+    pub fn get_liquidity_for_amounts(
+        sqrt_ratio_x96: U256,
+        sqrt_ratio_a_x96: U256,
+        sqrt_ratio_b_x96: U256,
+        amount0: U256,
+        amount1: U256,
+    ) -> Result<u128, UniswapError> {
+        let (sqrt_a, sqrt_b) = sort(sqrt_ratio_a_x96, sqrt_ratio_b_x96);
+        if sqrt_ratio_x96 <= sqrt_a {
+            get_liquidity_for_amount0(sqrt_a, sqrt_b, amount0)
+        } else if sqrt_ratio_x96 < sqrt_b {
+            let liquidity0 = get_liquidity_for_amount0(sqrt_ratio_x96, sqrt_b, amount0)?;
+            let liquidity1 = get_liquidity_for_amount1(sqrt_a, sqrt_ratio_x96, amount1)?;
+            Ok(liquidity0.min(liquidity1))
+        } else {
+            get_liquidity_for_amount1(sqrt_a, sqrt_b, amount1)
+        }
+    }
+
+    /// computes the amount of token0 for a given amount of liquidity and a price range.
+    pub fn get_amount0_for_liquidity(
+        sqrt_ratio_a_x96: U256,
+        sqrt_ratio_b_x96: U256,
+        liquidity: u128,
+    ) -> U256 {
+        let (sqrt_a, sqrt_b) = sort(sqrt_ratio_a_x96, sqrt_ratio_b_x96);
+        if sqrt_a.is_zero() {
+            return U256::zero();
+        }
+        mul_div(U256::from(liquidity) << 96, sqrt_b - sqrt_a, sqrt_b).unwrap_or_default() / sqrt_a
+    }
+
+    /// computes the amount of token1 for a given amount of liquidity and a price range.
+    pub fn get_amount1_for_liquidity(
+        sqrt_ratio_a_x96: U256,
+        sqrt_ratio_b_x96: U256,
+        liquidity: u128,
+    ) -> U256 {
+        let (sqrt_a, sqrt_b) = sort(sqrt_ratio_a_x96, sqrt_ratio_b_x96);
+        mul_div(U256::from(liquidity), sqrt_b - sqrt_a, q96()).unwrap_or_default()
+    }
+
+    /// computes the token0 and token1 value for a given amount of liquidity, the current pool
+    /// price and the prices at the tick boundaries.
+    pub fn get_amounts_for_liquidity(
+        sqrt_ratio_x96: U256,
+        sqrt_ratio_a_x96: U256,
+        sqrt_ratio_b_x96: U256,
+        liquidity: u128,
+    ) -> (U256, U256) {
+        let (sqrt_a, sqrt_b) = sort(sqrt_ratio_a_x96, sqrt_ratio_b_x96);
+        if sqrt_ratio_x96 <= sqrt_a {
+            (
+                get_amount0_for_liquidity(sqrt_a, sqrt_b, liquidity),
+                U256::zero(),
+            )
+        } else if sqrt_ratio_x96 < sqrt_b {
+            (
+                get_amount0_for_liquidity(sqrt_ratio_x96, sqrt_b, liquidity),
+                get_amount1_for_liquidity(sqrt_a, sqrt_ratio_x96, liquidity),
+            )
+        } else {
+            (
+                U256::zero(),
+                get_amount1_for_liquidity(sqrt_a, sqrt_b, liquidity),
+            )
+        }
+    }
+
+    /// reduce an amount by a slippage percentage, e.g. 0.5 for 0.5%.
+    pub fn apply_slippage(amount: U256, slippage: f64) -> U256 {
+        let slippage_bps = (slippage * 100.0).round().clamp(0.0, 10_000.0) as u64;
+        amount * U256::from(10_000 - slippage_bps) / U256::from(10_000)
+    }
+}
+
+/// uniswap v2 pair math module, ported from UniswapV2Library.sol and UniswapV2Pair.sol
+pub mod v2 {
+    use crate::types::UniswapError;
+    use ethers::types::U256;
+
+    /// liquidity permanently locked by the first mint of every pair
+    pub const MINIMUM_LIQUIDITY: u64 = 1000;
+    /// pair swap fee in hundredths of a bip
+    pub const FEE: u32 = 3000;
+
+    /// given some amount of an asset and pair reserves, returns an equivalent amount of the other asset.
+    pub fn quote(amount_a: U256, reserve_a: U256, reserve_b: U256) -> Result<U256, UniswapError> {
+        if amount_a.is_zero() {
+            return Err(UniswapError::MathError("Insufficient amount".to_string()));
+        }
+        if reserve_a.is_zero() || reserve_b.is_zero() {
+            return Err(UniswapError::MathError(
+                "Insufficient liquidity".to_string(),
+            ));
+        }
+        super::full::mul_div(amount_a, reserve_b, reserve_a)
+            .ok_or_else(|| UniswapError::MathError("Quote overflow".to_string()))
+    }
+
+    /// given an input amount and pair reserves, returns the maximum output amount after the 0.3% fee.
+    pub fn get_amount_out(
+        amount_in: U256,
+        reserve_in: U256,
+        reserve_out: U256,
+    ) -> Result<U256, UniswapError> {
+        if amount_in.is_zero() {
+            return Err(UniswapError::MathError(
+                "Insufficient input amount".to_string(),
+            ));
+        }
+        if reserve_in.is_zero() || reserve_out.is_zero() {
+            return Err(UniswapError::MathError(
+                "Insufficient liquidity".to_string(),
+            ));
+        }
+        let amount_in_with_fee = amount_in * 997;
+        let numerator = amount_in_with_fee * reserve_out;
+        let denominator = reserve_in * 1000 + amount_in_with_fee;
+        Ok(numerator / denominator)
+    }
+
+    /// given an output amount and pair reserves, returns the required input amount after the 0.3% fee.
+    pub fn get_amount_in(
+        amount_out: U256,
+        reserve_in: U256,
+        reserve_out: U256,
+    ) -> Result<U256, UniswapError> {
+        if amount_out.is_zero() {
+            return Err(UniswapError::MathError(
+                "Insufficient output amount".to_string(),
+            ));
+        }
+        if reserve_in.is_zero() || amount_out >= reserve_out {
+            return Err(UniswapError::MathError(
+                "Insufficient liquidity".to_string(),
+            ));
+        }
+        let numerator = reserve_in * amount_out * 1000;
+        let denominator = (reserve_out - amount_out) * 997;
+        Ok(numerator / denominator + 1)
+    }
+
+    /// the optimal deposit amounts for the desired amounts, as computed by the router.
+    pub fn get_optimal_amounts(
+        amount_a_desired: U256,
+        amount_b_desired: U256,
+        reserve_a: U256,
+        reserve_b: U256,
+    ) -> Result<(U256, U256), UniswapError> {
+        if reserve_a.is_zero() && reserve_b.is_zero() {
+            return Ok((amount_a_desired, amount_b_desired));
+        }
+        let amount_b_optimal = quote(amount_a_desired, reserve_a, reserve_b)?;
+        if amount_b_optimal <= amount_b_desired {
+            return Ok((amount_a_desired, amount_b_optimal));
+        }
+        let amount_a_optimal = quote(amount_b_desired, reserve_b, reserve_a)?;
+        Ok((amount_a_optimal, amount_b_desired))
+    }
+
+    /// liquidity minted to the protocol fee recipient before a mint or burn.
+    pub fn get_protocol_fee_liquidity(
+        reserve0: U256,
+        reserve1: U256,
+        total_supply: U256,
+        k_last: U256,
+        fee_on: bool,
+    ) -> U256 {
+        if !fee_on || k_last.is_zero() {
+            return U256::zero();
+        }
+        let root_k = super::full::sqrt(reserve0 * reserve1);
+        let root_k_last = super::full::sqrt(k_last);
+        if root_k <= root_k_last {
+            return U256::zero();
+        }
+        let numerator = total_supply * (root_k - root_k_last);
+        let denominator = root_k * 5 + root_k_last;
+        numerator / denominator
+    }
+
+    /// liquidity tokens minted for a deposit, returns (liquidity, protocol fee liquidity).
+    pub fn get_liquidity_minted(
+        amount0: U256,
+        amount1: U256,
+        reserve0: U256,
+        reserve1: U256,
+        total_supply: U256,
+        k_last: U256,
+        fee_on: bool,
+    ) -> Result<(U256, U256), UniswapError> {
+        let overflow = || UniswapError::MathError("Liquidity overflow".to_string());
+        let fee_liquidity =
+            get_protocol_fee_liquidity(reserve0, reserve1, total_supply, k_last, fee_on);
+        let total_supply = total_supply + fee_liquidity;
+        let liquidity = if total_supply.is_zero() {
+            let root = super::full::sqrt(amount0.checked_mul(amount1).ok_or_else(overflow)?);
+            if root <= U256::from(MINIMUM_LIQUIDITY) {
+                return Err(UniswapError::MathError(
+                    "Insufficient liquidity minted".to_string(),
+                ));
+            }
+            root - MINIMUM_LIQUIDITY
+        } else {
+            if reserve0.is_zero() || reserve1.is_zero() {
+                return Err(UniswapError::MathError(
+                    "Insufficient liquidity".to_string(),
+                ));
+            }
+            let liquidity0 = amount0.checked_mul(total_supply).ok_or_else(overflow)? / reserve0;
+            let liquidity1 = amount1.checked_mul(total_supply).ok_or_else(overflow)? / reserve1;
+            liquidity0.min(liquidity1)
+        };
+        if liquidity.is_zero() {
+            return Err(UniswapError::MathError(
+                "Insufficient liquidity minted".to_string(),
+            ));
+        }
+        Ok((liquidity, fee_liquidity))
+    }
+
+    /// underlying token amounts returned for burning `liquidity`, returns (amount0, amount1, protocol fee liquidity).
+    pub fn get_liquidity_value(
+        liquidity: U256,
+        reserve0: U256,
+        reserve1: U256,
+        total_supply: U256,
+        k_last: U256,
+        fee_on: bool,
+    ) -> Result<(U256, U256, U256), UniswapError> {
+        if total_supply.is_zero() {
+            return Err(UniswapError::MathError(
+                "Insufficient liquidity".to_string(),
+            ));
+        }
+        if liquidity > total_supply {
+            return Err(UniswapError::MathError(
+                "Liquidity exceeds total supply".to_string(),
+            ));
+        }
+        let fee_liquidity =
+            get_protocol_fee_liquidity(reserve0, reserve1, total_supply, k_last, fee_on);
+        let total_supply = total_supply + fee_liquidity;
+        let overflow = || UniswapError::MathError("Liquidity value overflow".to_string());
+        Ok((
+            super::full::mul_div(liquidity, reserve0, total_supply).ok_or_else(overflow)?,
+            super::full::mul_div(liquidity, reserve1, total_supply).ok_or_else(overflow)?,
+            fee_liquidity,
+        ))
+    }
+}
+
+/// uniswap v3 swap math module, ported from SqrtPriceMath.sol, SwapMath.sol and the
+/// UniswapV3Pool swap loop
+pub mod swap {
+    use super::{
+        full::{mul_div, mul_div_rounding_up},
+        liquidity::q96,
+        tick::{
+            MAX_TICK, MIN_TICK, get_sqrt_ratio_at_tick, get_tick_at_sqrt_ratio, max_sqrt_ratio,
+            min_sqrt_ratio,
+        },
+    };
+    use crate::types::{SwapSimulation, UniswapError, V3PoolState};
+    use ethers::types::{I256, U256};
+
+    fn overflow() -> UniswapError {
+        UniswapError::MathError("Swap math overflow".to_string())
+    }
+
+    fn div_rounding_up(a: U256, b: U256) -> U256 {
+        let quotient = a / b;
+        if (a % b).is_zero() {
+            quotient
+        } else {
+            quotient + 1
+        }
+    }
+
+    fn u160_max() -> U256 {
+        (U256::one() << 160) - 1
+    }
+
+    /// the next sqrt price given a delta of token0, always rounded up.
+    pub fn get_next_sqrt_price_from_amount0_rounding_up(
+        sqrt_price_x96: U256,
+        liquidity: u128,
+        amount: U256,
+        add: bool,
+    ) -> Result<U256, UniswapError> {
+        if amount.is_zero() {
+            return Ok(sqrt_price_x96);
+        }
+        let numerator1 = U256::from(liquidity) << 96;
+        let (product, overflowed) = amount.overflowing_mul(sqrt_price_x96);
+        if add {
+            if !overflowed {
+                let (denominator, overflowed) = numerator1.overflowing_add(product);
+                if !overflowed {
+                    return mul_div_rounding_up(numerator1, sqrt_price_x96, denominator)
+                        .ok_or_else(overflow);
+                }
+            }
+            Ok(div_rounding_up(
+                numerator1,
+                numerator1 / sqrt_price_x96 + amount,
+            ))
+        } else {
+            if overflowed || numerator1 <= product {
+                return Err(overflow());
+            }
+            mul_div_rounding_up(numerator1, sqrt_price_x96, numerator1 - product)
+                .ok_or_else(overflow)
+        }
+    }
+
+    /// the next sqrt price given a delta of token1, always rounded down.
+    pub fn get_next_sqrt_price_from_amount1_rounding_down(
+        sqrt_price_x96: U256,
+        liquidity: u128,
+        amount: U256,
+        add: bool,
+    ) -> Result<U256, UniswapError> {
+        let liquidity = U256::from(liquidity);
+        if add {
+            let quotient = if amount <= u160_max() {
+                (amount << 96) / liquidity
+            } else {
+                mul_div(amount, q96(), liquidity).ok_or_else(overflow)?
+            };
+            sqrt_price_x96.checked_add(quotient).ok_or_else(overflow)
+        } else {
+            let quotient = if amount <= u160_max() {
+                div_rounding_up(amount << 96, liquidity)
+            } else {
+                mul_div_rounding_up(amount, q96(), liquidity).ok_or_else(overflow)?
+            };
+            if sqrt_price_x96 <= quotient {
+                return Err(overflow());
+            }
+            Ok(sqrt_price_x96 - quotient)
+        }
+    }
+
+    /// the next sqrt price given an input amount of token0 or token1.
+    pub fn get_next_sqrt_price_from_input(
+        sqrt_price_x96: U256,
+        liquidity: u128,
+        amount_in: U256,
+        zero_for_one: bool,
+    ) -> Result<U256, UniswapError> {
+        if zero_for_one {
+            get_next_sqrt_price_from_amount0_rounding_up(sqrt_price_x96, liquidity, amount_in, true)
+        } else {
+            get_next_sqrt_price_from_amount1_rounding_down(
+                sqrt_price_x96,
+                liquidity,
+                amount_in,
+                true,
+            )
+        }
+    }
+
+    /// the next sqrt price given an output amount of token0 or token1.
+    pub fn get_next_sqrt_price_from_output(
+        sqrt_price_x96: U256,
+        liquidity: u128,
+        amount_out: U256,
+        zero_for_one: bool,
+    ) -> Result<U256, UniswapError> {
+        if zero_for_one {
+            get_next_sqrt_price_from_amount1_rounding_down(
+                sqrt_price_x96,
+                liquidity,
+                amount_out,
+                false,
+            )
+        } else {
+            get_next_sqrt_price_from_amount0_rounding_up(
+                sqrt_price_x96,
+                liquidity,
+                amount_out,
+                false,
+            )
+        }
+    }
+
+    /// the amount of token0 between two sqrt prices for a liquidity.
+    pub fn get_amount0_delta(
+        sqrt_ratio_a_x96: U256,
+        sqrt_ratio_b_x96: U256,
+        liquidity: u128,
+        round_up: bool,
+    ) -> Result<U256, UniswapError> {
+        let (sqrt_a, sqrt_b) = if sqrt_ratio_a_x96 > sqrt_ratio_b_x96 {
+            (sqrt_ratio_b_x96, sqrt_ratio_a_x96)
+        } else {
+            (sqrt_ratio_a_x96, sqrt_ratio_b_x96)
+        };
+        if sqrt_a.is_zero() {
+            return Err(overflow());
+        }
+        let numerator1 = U256::from(liquidity) << 96;
+        let numerator2 = sqrt_b - sqrt_a;
+        if round_up {
+            let value = mul_div_rounding_up(numerator1, numerator2, sqrt_b).ok_or_else(overflow)?;
+            Ok(div_rounding_up(value, sqrt_a))
+        } else {
+            Ok(mul_div(numerator1, numerator2, sqrt_b).ok_or_else(overflow)? / sqrt_a)
+        }
+    }
+
+    /// the amount of token1 between two sqrt prices for a liquidity.
+    pub fn get_amount1_delta(
+        sqrt_ratio_a_x96: U256,
+        sqrt_ratio_b_x96: U256,
+        liquidity: u128,
+        round_up: bool,
+    ) -> Result<U256, UniswapError> {
+        let delta = sqrt_ratio_a_x96.abs_diff(sqrt_ratio_b_x96);
+        if round_up {
+            mul_div_rounding_up(U256::from(liquidity), delta, q96()).ok_or_else(overflow)
+        } else {
+            mul_div(U256::from(liquidity), delta, q96()).ok_or_else(overflow)
+        }
+    }
+
+    /// computes a single swap step within a tick range, returns the next sqrt price and the
+    /// amount in, amount out and fee amount. `amount_remaining` is positive for exact input.
+    pub fn compute_swap_step(
+        sqrt_ratio_current_x96: U256,
+        sqrt_ratio_target_x96: U256,
+        liquidity: u128,
+        amount_remaining: I256,
+        fee_pips: u32,
+    ) -> Result<(U256, U256, U256, U256), UniswapError> {
+        let zero_for_one = sqrt_ratio_current_x96 >= sqrt_ratio_target_x96;
+        let exact_in = !amount_remaining.is_negative();
+        let fee_pips = U256::from(fee_pips);
+        let pips = U256::from(1_000_000u32);
+        let remaining = amount_remaining.unsigned_abs();
+        let mut amount_in = U256::zero();
+        let mut amount_out = U256::zero();
+        let sqrt_ratio_next_x96 = if exact_in {
+            let amount_remaining_less_fee =
+                mul_div(remaining, pips - fee_pips, pips).ok_or_else(overflow)?;
+            amount_in = if zero_for_one {
+                get_amount0_delta(
+                    sqrt_ratio_target_x96,
+                    sqrt_ratio_current_x96,
+                    liquidity,
+                    true,
+                )?
+            } else {
+                get_amount1_delta(
+                    sqrt_ratio_current_x96,
+                    sqrt_ratio_target_x96,
+                    liquidity,
+                    true,
+                )?
+            };
+            if amount_remaining_less_fee >= amount_in {
+                sqrt_ratio_target_x96
+            } else {
+                get_next_sqrt_price_from_input(
+                    sqrt_ratio_current_x96,
+                    liquidity,
+                    amount_remaining_less_fee,
+                    zero_for_one,
+                )?
+            }
+        } else {
+            amount_out = if zero_for_one {
+                get_amount1_delta(
+                    sqrt_ratio_target_x96,
+                    sqrt_ratio_current_x96,
+                    liquidity,
+                    false,
+                )?
+            } else {
+                get_amount0_delta(
+                    sqrt_ratio_current_x96,
+                    sqrt_ratio_target_x96,
+                    liquidity,
+                    false,
+                )?
+            };
+            if remaining >= amount_out {
+                sqrt_ratio_target_x96
+            } else {
+                get_next_sqrt_price_from_output(
+                    sqrt_ratio_current_x96,
+                    liquidity,
+                    remaining,
+                    zero_for_one,
+                )?
+            }
+        };
+        let max = sqrt_ratio_target_x96 == sqrt_ratio_next_x96;
+        if zero_for_one {
+            if !(max && exact_in) {
+                amount_in = get_amount0_delta(
+                    sqrt_ratio_next_x96,
+                    sqrt_ratio_current_x96,
+                    liquidity,
+                    true,
+                )?;
+            }
+            if !max || exact_in {
+                amount_out = get_amount1_delta(
+                    sqrt_ratio_next_x96,
+                    sqrt_ratio_current_x96,
+                    liquidity,
+                    false,
+                )?;
+            }
+        } else {
+            if !(max && exact_in) {
+                amount_in = get_amount1_delta(
+                    sqrt_ratio_current_x96,
+                    sqrt_ratio_next_x96,
+                    liquidity,
+                    true,
+                )?;
+            }
+            if !max || exact_in {
+                amount_out = get_amount0_delta(
+                    sqrt_ratio_current_x96,
+                    sqrt_ratio_next_x96,
+                    liquidity,
+                    false,
+                )?;
+            }
+        }
+        if !exact_in && amount_out > remaining {
+            amount_out = remaining;
+        }
+        let fee_amount = if exact_in && sqrt_ratio_next_x96 != sqrt_ratio_target_x96 {
+            remaining - amount_in
+        } else {
+            mul_div_rounding_up(amount_in, fee_pips, pips - fee_pips).ok_or_else(overflow)?
+        };
+        Ok((sqrt_ratio_next_x96, amount_in, amount_out, fee_amount))
+    }
+
+    /// simulate a swap against a pool state. `amount_specified` is positive for exact input
+    /// and negative for exact output. the swap stops at the edge of the state's loaded ticks,
+    /// as the liquidity beyond is unknown, and the result is then marked partial.
+    pub fn simulate_swap(
+        pool: &V3PoolState,
+        zero_for_one: bool,
+        amount_specified: I256,
+        sqrt_price_limit_x96: Option<U256>,
+    ) -> Result<SwapSimulation, UniswapError> {
+        if amount_specified.is_zero() {
+            return Err(UniswapError::MathError(
+                "Amount specified is zero".to_string(),
+            ));
+        }
+        let sqrt_price_limit_x96 = sqrt_price_limit_x96.unwrap_or_else(|| {
+            if zero_for_one {
+                min_sqrt_ratio() + 1
+            } else {
+                max_sqrt_ratio() - 1
+            }
+        });
+        if zero_for_one
+            && (sqrt_price_limit_x96 >= pool.sqrt_price_x96
+                || sqrt_price_limit_x96 <= min_sqrt_ratio())
+            || !zero_for_one
+                && (sqrt_price_limit_x96 <= pool.sqrt_price_x96
+                    || sqrt_price_limit_x96 >= max_sqrt_ratio())
+        {
+            return Err(UniswapError::MathError("Invalid price limit".to_string()));
+        }
+        let exact_in = amount_specified.is_positive();
+        let mut amount_remaining = amount_specified;
+        let mut amount_calculated = U256::zero();
+        let mut amount_in = U256::zero();
+        let mut fee_amount = U256::zero();
+        let mut sqrt_price_x96 = pool.sqrt_price_x96;
+        let mut tick = pool.tick;
+        let mut liquidity = pool.liquidity;
+        let mut ticks_crossed = 0;
+        while !amount_remaining.is_zero() && sqrt_price_x96 != sqrt_price_limit_x96 {
+            let sqrt_price_start = sqrt_price_x96;
+            let (next, edge) = if zero_for_one {
+                let edge = pool.tick_lower.max(MIN_TICK);
+                let next = pool.ticks.iter().rev().find(|next| next.tick <= tick);
+                (next.filter(|next| next.tick >= edge), edge)
+            } else {
+                let edge = pool.tick_upper.min(MAX_TICK);
+                let next = pool.ticks.iter().find(|next| next.tick > tick);
+                (next.filter(|next| next.tick <= edge), edge)
+            };
+            let tick_next = next.map(|next| next.tick).unwrap_or(edge);
+            // the price already left the loaded ticks
+            if zero_for_one && tick_next > tick || !zero_for_one && tick_next <= tick {
+                break;
+            }
+            let sqrt_price_next_x96 = get_sqrt_ratio_at_tick(tick_next)?;
+            let sqrt_price_target_x96 = if zero_for_one {
+                sqrt_price_next_x96.max(sqrt_price_limit_x96)
+            } else {
+                sqrt_price_next_x96.min(sqrt_price_limit_x96)
+            };
+            let (sqrt_price_after, step_in, step_out, step_fee) = compute_swap_step(
+                sqrt_price_x96,
+                sqrt_price_target_x96,
+                liquidity,
+                amount_remaining,
+                pool.fee,
+            )?;
+            sqrt_price_x96 = sqrt_price_after;
+            amount_in += step_in + step_fee;
+            fee_amount += step_fee;
+            if exact_in {
+                amount_remaining -= I256::from_raw(step_in + step_fee);
+                amount_calculated += step_out;
+            } else {
+                amount_remaining += I256::from_raw(step_out);
+                amount_calculated += step_out;
+            }
+            if sqrt_price_x96 == sqrt_price_next_x96 {
+                if let Some(next) = next {
+                    let liquidity_net = if zero_for_one {
+                        -next.liquidity_net
+                    } else {
+                        next.liquidity_net
+                    };
+                    liquidity = liquidity
+                        .checked_add_signed(liquidity_net)
+                        .ok_or_else(overflow)?;
+                    ticks_crossed += 1;
+                }
+                tick = if zero_for_one {
+                    tick_next - 1
+                } else {
+                    tick_next
+                };
+            } else if sqrt_price_x96 != sqrt_price_start {
+                tick = get_tick_at_sqrt_ratio(sqrt_price_x96)?;
+            }
+            // the price reached the edge of the loaded ticks
+            if next.is_none() && sqrt_price_x96 == sqrt_price_next_x96 {
+                break;
+            }
+        }
+        let partial = !amount_remaining.is_zero() && sqrt_price_x96 != sqrt_price_limit_x96;
+        Ok(SwapSimulation {
+            amount_in,
+            amount_out: amount_calculated,
+            fee_amount,
+            sqrt_price_x96_after: sqrt_price_x96,
+            tick_after: tick,
+            liquidity_after: liquidity,
+            ticks_crossed,
+            partial,
+        })
+    }
+}
@@ -0,0 +1,624 @@
+use ethers::{
+    abi::{self, AbiDecode, Tokenize},
+    prelude::*,
+};
+use serde_json::{Value, json};
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    abi::{
+        IERC20Calls, IUniswapV2FactoryCalls, IUniswapV2PairCalls, IUniswapV3PoolCalls,
+        i_uniswap_v3_factory::GetPoolCall,
+    },
+    global::mainnet::{
+        DAI_ADDRESS, MAINNET_UNISWAP_V2_FACTORY_ADDRESS, MAINNET_UNISWAP_V3_FACTORY_ADDRESS,
+        USDC_ADDRESS, USDT_ADDRESS, WBTC_ADDRESS, WETH_ADDRESS,
+    },
+    math::tick::{MAX_TICK, MIN_TICK, get_tick_at_sqrt_ratio},
+    oracle::current_cumulative_prices,
+    replay::{RPC_NOT_FOUND_CODE, RpcHandler, RpcServer},
+    types::{RpcErrorResponse, TickLiquidity, UniswapError, V3PoolState},
+};
+
+/// chain id of the mock chain, not mainnet so the shared token cache's mainnet entries never
+/// shadow mock tokens
+pub const MOCK_CHAIN_ID: u64 = 31337;
+/// block number the mock chain starts at
+pub const MOCK_START_BLOCK: u64 = 1_000_000;
+/// timestamp of the mock chain's start block
+pub const MOCK_START_TIMESTAMP: u64 = 1_700_000_000;
+/// seconds between mock blocks
+pub const MOCK_BLOCK_TIME: u64 = 12;
+/// JSON-RPC error code of a reverted call
+pub const RPC_REVERT_CODE: i64 = 3;
+
+/// an ERC20 of the mock chain, missing metadata makes its getter revert
+#[derive(Debug, Clone)]
+pub struct MockToken {
+    pub address: Address,
+    pub symbol: Option<String>,
+    pub name: Option<String>,
+    pub decimals: Option<u8>,
+    pub bytes32_metadata: bool, // symbol and name returned as bytes32, as MKR and SAI
+    pub total_supply: U256,
+    pub balances: HashMap<Address, U256>,
+}
+
+impl MockToken {
+    pub fn new(address: Address, symbol: &str, name: &str, decimals: u8) -> Self {
+        Self {
+            address,
+            symbol: Some(symbol.to_string()),
+            name: Some(name.to_string()),
+            decimals: Some(decimals),
+            bytes32_metadata: false,
+            total_supply: U256::zero(),
+            balances: HashMap::new(),
+        }
+    }
+}
+
+/// a V2 pair of the mock chain
+#[derive(Debug, Clone)]
+pub struct MockV2Pair {
+    pub address: Address,
+    pub token0: Address,
+    pub token1: Address,
+    pub reserve0: U256,
+    pub reserve1: U256,
+    pub total_supply: U256,
+    pub block_timestamp_last: u32,
+    pub price0_cumulative_last: U256,
+    pub price1_cumulative_last: U256,
+}
+
+#[derive(Debug)]
+struct MockState {
+    factory_v2: Address,
+    factory_v3: Address,
+    block_number: u64,
+    timestamp: u64,
+    next_address: u64,
+    tokens: HashMap<Address, MockToken>,
+    v2_pairs: HashMap<Address, MockV2Pair>,
+    v3_pools: HashMap<Address, V3PoolState>,
+}
+
+impl MockState {
+    fn new_address(&mut self) -> Address {
+        self.next_address += 1;
+        Address::from_low_u64_be(self.next_address)
+    }
+
+    fn set_balance(&mut self, token: Address, owner: Address, amount: U256) {
+        if let Some(token) = self.tokens.get_mut(&token) {
+            let previous = token.balances.insert(owner, amount).unwrap_or_default();
+            token.total_supply = token.total_supply - previous.min(token.total_supply) + amount;
+        }
+    }
+}
+
+/// the tick spacing V3 assigns to a fee tier
+fn tick_spacing_for_fee(fee: u32) -> i32 {
+    match fee {
+        100 => 1,
+        500 => 10,
+        3000 => 60,
+        _ => 200,
+    }
+}
+
+fn sort_tokens(token_a: Address, token_b: Address) -> (Address, Address, bool) {
+    if token_a < token_b {
+        (token_a, token_b, false)
+    } else {
+        (token_b, token_a, true)
+    }
+}
+
+fn encode_return<T: Tokenize>(value: T) -> Value {
+    json!(Bytes::from(abi::encode(&value.into_tokens())))
+}
+
+fn revert() -> RpcErrorResponse {
+    RpcErrorResponse {
+        code: RPC_REVERT_CODE,
+        message: "execution reverted".to_string(),
+        data: Some(json!("0x")),
+    }
+}
+
+/// a revert with an Error(string) reason, as solidity's require emits
+fn revert_with_reason(reason: &str) -> RpcErrorResponse {
+    let data = [
+        ethers::utils::id("Error(string)").to_vec(),
+        abi::encode(&[abi::Token::String(reason.to_string())]),
+    ]
+    .concat();
+    RpcErrorResponse {
+        code: RPC_REVERT_CODE,
+        message: format!("execution reverted: {}", reason),
+        data: Some(json!(Bytes::from(data))),
+    }
+}
+
+/// oracle cumulatives of a V3 pool at a timestamp, accrued at its current tick and liquidity
+fn v3_cumulatives(pool: &V3PoolState, timestamp: u64) -> (i64, U256) {
+    let seconds_per_liquidity = if pool.liquidity == 0 {
+        U256::zero()
+    } else {
+        (U256::from(timestamp) << 128) / U256::from(pool.liquidity)
+    };
+    (pool.tick as i64 * timestamp as i64, seconds_per_liquidity)
+}
+
+/// a short string as a left aligned bytes32
+fn to_bytes32(value: &str) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    let length = value.len().min(32);
+    bytes[..length].copy_from_slice(&value.as_bytes()[..length]);
+    bytes
+}
+
+/// programmable in-process chain of tokens, V2 pairs and V3 pools. serving it gives a url that
+/// `UniswapV2`, `UniswapV3` and `Price` run against unchanged. calls at any block read the
+/// current state.
+pub struct MockEnvironment {
+    state: Mutex<MockState>,
+}
+
+impl Default for MockEnvironment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockEnvironment {
+    /// an empty chain using the mainnet factory addresses
+    pub fn new() -> Self {
+        Self::new_with_factories(
+            Address::from_str(MAINNET_UNISWAP_V2_FACTORY_ADDRESS).unwrap(),
+            Address::from_str(MAINNET_UNISWAP_V3_FACTORY_ADDRESS).unwrap(),
+        )
+    }
+
+    pub fn new_with_factories(factory_v2: Address, factory_v3: Address) -> Self {
+        Self {
+            state: Mutex::new(MockState {
+                factory_v2,
+                factory_v3,
+                block_number: MOCK_START_BLOCK,
+                timestamp: MOCK_START_TIMESTAMP,
+                next_address: 0x1000,
+                tokens: HashMap::new(),
+                v2_pairs: HashMap::new(),
+                v3_pools: HashMap::new(),
+            }),
+        }
+    }
+
+    /// start a JSON-RPC server backed by the environment
+    pub async fn serve(self: &Arc<Self>) -> Result<RpcServer, UniswapError> {
+        RpcServer::start(self.clone()).await
+    }
+
+    pub fn add_token(&self, token: MockToken) {
+        self.state
+            .lock()
+            .unwrap()
+            .tokens
+            .insert(token.address, token);
+    }
+
+    /// add WETH, USDC, USDT, DAI and WBTC at their mainnet addresses
+    pub fn add_mainnet_tokens(&self) {
+        for (address, symbol, name, decimals) in [
+            (WETH_ADDRESS, "WETH", "Wrapped Ether", 18),
+            (USDC_ADDRESS, "USDC", "USD Coin", 6),
+            (USDT_ADDRESS, "USDT", "Tether USD", 6),
+            (DAI_ADDRESS, "DAI", "Dai Stablecoin", 18),
+            (WBTC_ADDRESS, "WBTC", "Wrapped BTC", 8),
+        ] {
+            self.add_token(MockToken::new(
+                Address::from_str(address).unwrap(),
+                symbol,
+                name,
+                decimals,
+            ));
+        }
+    }
+
+    /// add a token at a fresh address
+    pub fn create_token(&self, symbol: &str, name: &str, decimals: u8) -> Address {
+        let mut state = self.state.lock().unwrap();
+        let address = state.new_address();
+        state
+            .tokens
+            .insert(address, MockToken::new(address, symbol, name, decimals));
+        address
+    }
+
+    pub fn token(&self, address: Address) -> Option<MockToken> {
+        self.state.lock().unwrap().tokens.get(&address).cloned()
+    }
+
+    /// change a token, for example to remove its metadata
+    pub fn update_token<F: FnOnce(&mut MockToken)>(&self, address: Address, update: F) {
+        if let Some(token) = self.state.lock().unwrap().tokens.get_mut(&address) {
+            update(token);
+        }
+    }
+
+    pub fn set_balance(&self, token: Address, owner: Address, amount: U256) {
+        self.state.lock().unwrap().set_balance(token, owner, amount);
+    }
+
+    /// add a V2 pair registered with the factory, reserves in the given token order
+    pub fn add_v2_pair(
+        &self,
+        token_a: Address,
+        token_b: Address,
+        reserve_a: U256,
+        reserve_b: U256,
+    ) -> Address {
+        let mut state = self.state.lock().unwrap();
+        let address = state.new_address();
+        let (token0, token1, flipped) = sort_tokens(token_a, token_b);
+        let (reserve0, reserve1) = if flipped {
+            (reserve_b, reserve_a)
+        } else {
+            (reserve_a, reserve_b)
+        };
+        let block_timestamp_last = state.timestamp as u32;
+        state.v2_pairs.insert(
+            address,
+            MockV2Pair {
+                address,
+                token0,
+                token1,
+                reserve0,
+                reserve1,
+                total_supply: crate::math::full::sqrt(reserve0 * reserve1),
+                block_timestamp_last,
+                price0_cumulative_last: U256::zero(),
+                price1_cumulative_last: U256::zero(),
+            },
+        );
+        state.set_balance(token0, address, reserve0);
+        state.set_balance(token1, address, reserve1);
+        address
+    }
+
+    /// set the reserves of a pair, accruing its price cumulatives at the old reserves
+    pub fn set_v2_reserves(&self, pair: Address, reserve0: U256, reserve1: U256) {
+        let mut state = self.state.lock().unwrap();
+        let timestamp = state.timestamp;
+        let Some(pair_state) = state.v2_pairs.get_mut(&pair) else {
+            return;
+        };
+        (
+            pair_state.price0_cumulative_last,
+            pair_state.price1_cumulative_last,
+        ) = current_cumulative_prices(
+            pair_state.price0_cumulative_last,
+            pair_state.price1_cumulative_last,
+            pair_state.reserve0,
+            pair_state.reserve1,
+            pair_state.block_timestamp_last,
+            timestamp,
+        );
+        pair_state.reserve0 = reserve0;
+        pair_state.reserve1 = reserve1;
+        pair_state.block_timestamp_last = timestamp as u32;
+        let (token0, token1) = (pair_state.token0, pair_state.token1);
+        state.set_balance(token0, pair, reserve0);
+        state.set_balance(token1, pair, reserve1);
+    }
+
+    /// add a V3 pool registered with the factory. `ticks` hold the net liquidity of the
+    /// initialized ticks, balances are the token amounts the pool holds.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_v3_pool(
+        &self,
+        token_a: Address,
+        token_b: Address,
+        fee: u32,
+        sqrt_price_x96: U256,
+        liquidity: u128,
+        mut ticks: Vec<TickLiquidity>,
+        balance0: U256,
+        balance1: U256,
+    ) -> Result<Address, UniswapError> {
+        let tick = get_tick_at_sqrt_ratio(sqrt_price_x96)?;
+        let mut state = self.state.lock().unwrap();
+        let address = state.new_address();
+        let (token0, token1, _) = sort_tokens(token_a, token_b);
+        ticks.sort_by_key(|entry| entry.tick);
+        state.v3_pools.insert(
+            address,
+            V3PoolState {
+                pool: address,
+                token0,
+                token1,
+                fee,
+                tick_spacing: tick_spacing_for_fee(fee),
+                sqrt_price_x96,
+                tick,
+                liquidity,
+                ticks,
+                tick_lower: MIN_TICK,
+                tick_upper: MAX_TICK,
+            },
+        );
+        state.set_balance(token0, address, balance0);
+        state.set_balance(token1, address, balance1);
+        Ok(address)
+    }
+
+    /// replace the state of a V3 pool
+    pub fn set_v3_state(&self, state: V3PoolState) {
+        self.state
+            .lock()
+            .unwrap()
+            .v3_pools
+            .insert(state.pool, state);
+    }
+
+    pub fn v3_state(&self, pool: Address) -> Option<V3PoolState> {
+        self.state.lock().unwrap().v3_pools.get(&pool).cloned()
+    }
+
+    pub fn v2_pair(&self, pair: Address) -> Option<MockV2Pair> {
+        self.state.lock().unwrap().v2_pairs.get(&pair).cloned()
+    }
+
+    /// mine `blocks` empty blocks
+    pub fn advance_blocks(&self, blocks: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.block_number += blocks;
+        state.timestamp += blocks * MOCK_BLOCK_TIME;
+    }
+
+    pub fn block_number(&self) -> u64 {
+        self.state.lock().unwrap().block_number
+    }
+
+    fn block(&self, tag: &Value) -> Value {
+        let state = self.state.lock().unwrap();
+        let number = match tag.as_str() {
+            Some("earliest") => 0,
+            Some(tag) if tag.starts_with("0x") => {
+                u64::from_str_radix(tag.trim_start_matches("0x"), 16).unwrap_or(u64::MAX)
+            }
+            _ => state.block_number,
+        };
+        if number > state.block_number {
+            return Value::Null;
+        }
+        let block = Block::<H256> {
+            hash: Some(H256::from_low_u64_be(number + 1)),
+            parent_hash: H256::from_low_u64_be(number),
+            number: Some(number.into()),
+            timestamp: (state.timestamp - (state.block_number - number) * MOCK_BLOCK_TIME).into(),
+            gas_limit: 30_000_000u64.into(),
+            base_fee_per_gas: Some(U256::zero()),
+            ..Default::default()
+        };
+        serde_json::to_value(block).unwrap_or(Value::Null)
+    }
+
+    fn call(&self, to: Address, data: &[u8]) -> Result<Value, RpcErrorResponse> {
+        let state = self.state.lock().unwrap();
+        if let Some(token) = state.tokens.get(&to) {
+            return match IERC20Calls::decode(data).map_err(|_| revert())? {
+                IERC20Calls::Symbol(_) => match (&token.symbol, token.bytes32_metadata) {
+                    (Some(symbol), true) => Ok(encode_return(to_bytes32(symbol))),
+                    (Some(symbol), false) => Ok(encode_return(symbol.clone())),
+                    (None, _) => Err(revert()),
+                },
+                IERC20Calls::Name(_) => match (&token.name, token.bytes32_metadata) {
+                    (Some(name), true) => Ok(encode_return(to_bytes32(name))),
+                    (Some(name), false) => Ok(encode_return(name.clone())),
+                    (None, _) => Err(revert()),
+                },
+                IERC20Calls::Decimals(_) => token.decimals.map(encode_return).ok_or_else(revert),
+                IERC20Calls::TotalSupply(_) => Ok(encode_return(token.total_supply)),
+                IERC20Calls::BalanceOf(call) => Ok(encode_return(
+                    token
+                        .balances
+                        .get(&call.account)
+                        .copied()
+                        .unwrap_or_default(),
+                )),
+                IERC20Calls::Allowance(_) => Ok(encode_return(U256::MAX)),
+                IERC20Calls::Transfer(_) | IERC20Calls::Approve(_) => Ok(encode_return(true)),
+            };
+        }
+        if let Some(pair) = state.v2_pairs.get(&to) {
+            return match IUniswapV2PairCalls::decode(data).map_err(|_| revert())? {
+                IUniswapV2PairCalls::GetReserves(_) => Ok(encode_return((
+                    pair.reserve0.as_u128(),
+                    pair.reserve1.as_u128(),
+                    pair.block_timestamp_last,
+                ))),
+                IUniswapV2PairCalls::Token0(_) => Ok(encode_return(pair.token0)),
+                IUniswapV2PairCalls::Token1(_) => Ok(encode_return(pair.token1)),
+                IUniswapV2PairCalls::TotalSupply(_) => Ok(encode_return(pair.total_supply)),
+                IUniswapV2PairCalls::BalanceOf(_)
+                | IUniswapV2PairCalls::Klast(_)
+                | IUniswapV2PairCalls::Nonces(_) => Ok(encode_return(U256::zero())),
+                IUniswapV2PairCalls::Price0CumulativeLast(_) => {
+                    Ok(encode_return(pair.price0_cumulative_last))
+                }
+                IUniswapV2PairCalls::Price1CumulativeLast(_) => {
+                    Ok(encode_return(pair.price1_cumulative_last))
+                }
+                _ => Err(revert()),
+            };
+        }
+        if let Some(pool) = state.v3_pools.get(&to) {
+            return match IUniswapV3PoolCalls::decode(data).map_err(|_| revert())? {
+                IUniswapV3PoolCalls::Slot0(_) => Ok(encode_return((
+                    pool.sqrt_price_x96,
+                    pool.tick,
+                    0u16,
+                    1u16,
+                    1u16,
+                    0u8,
+                    true,
+                ))),
+                IUniswapV3PoolCalls::Liquidity(_) => Ok(encode_return(pool.liquidity)),
+                IUniswapV3PoolCalls::Token0(_) => Ok(encode_return(pool.token0)),
+                IUniswapV3PoolCalls::Token1(_) => Ok(encode_return(pool.token1)),
+                IUniswapV3PoolCalls::Fee(_) => Ok(encode_return(pool.fee)),
+                IUniswapV3PoolCalls::TickSpacing(_) => Ok(encode_return(pool.tick_spacing)),
+                IUniswapV3PoolCalls::TickBitmap(call) => {
+                    let mut word = U256::zero();
+                    for entry in pool.ticks.iter() {
+                        let compressed = entry.tick.div_euclid(pool.tick_spacing);
+                        if (compressed >> 8) as i16 == call.word_position {
+                            word |= U256::one() << (compressed & 255) as usize;
+                        }
+                    }
+                    Ok(encode_return(word))
+                }
+                IUniswapV3PoolCalls::Ticks(call) => {
+                    let liquidity_net = pool
+                        .ticks
+                        .iter()
+                        .find(|entry| entry.tick == call.tick)
+                        .map(|entry| entry.liquidity_net)
+                        .unwrap_or_default();
+                    Ok(encode_return((
+                        liquidity_net.unsigned_abs(),
+                        liquidity_net,
+                        U256::zero(),
+                        U256::zero(),
+                        0i64,
+                        U256::zero(),
+                        0u32,
+                        liquidity_net != 0,
+                    )))
+                }
+                // tick and liquidity have been constant since the chain started, with a
+                // single observation written at the start. older targets revert like the pool.
+                IUniswapV3PoolCalls::Observe(call) => {
+                    let mut tick_cumulatives = Vec::new();
+                    let mut seconds_per_liquidity = Vec::new();
+                    for seconds_ago in call.seconds_agos.iter() {
+                        let target = state
+                            .timestamp
+                            .checked_sub(*seconds_ago as u64)
+                            .filter(|target| *target >= MOCK_START_TIMESTAMP)
+                            .ok_or_else(|| revert_with_reason("OLD"))?;
+                        let (tick_cumulative, seconds_per_liquidity_cumulative) =
+                            v3_cumulatives(pool, target);
+                        tick_cumulatives.push(tick_cumulative);
+                        seconds_per_liquidity.push(seconds_per_liquidity_cumulative);
+                    }
+                    Ok(encode_return((tick_cumulatives, seconds_per_liquidity)))
+                }
+                IUniswapV3PoolCalls::Observations(call) if call.index.is_zero() => {
+                    let (tick_cumulative, seconds_per_liquidity_cumulative) =
+                        v3_cumulatives(pool, MOCK_START_TIMESTAMP);
+                    Ok(encode_return((
+                        MOCK_START_TIMESTAMP as u32,
+                        tick_cumulative,
+                        seconds_per_liquidity_cumulative,
+                        true,
+                    )))
+                }
+                IUniswapV3PoolCalls::Observations(_) => {
+                    Ok(encode_return((0u32, 0i64, U256::zero(), false)))
+                }
+                _ => Err(revert()),
+            };
+        }
+        if to == state.factory_v2 {
+            // addresses are handed out in order, so sorting them gives the creation order
+            let mut pairs: Vec<Address> = state.v2_pairs.keys().copied().collect();
+            pairs.sort();
+            return match IUniswapV2FactoryCalls::decode(data).map_err(|_| revert())? {
+                IUniswapV2FactoryCalls::GetPair(call) => {
+                    let (token0, token1, _) = sort_tokens(call.token_a, call.token_b);
+                    let pair = state
+                        .v2_pairs
+                        .values()
+                        .find(|pair| pair.token0 == token0 && pair.token1 == token1)
+                        .map(|pair| pair.address)
+                        .unwrap_or_default();
+                    Ok(encode_return(pair))
+                }
+                IUniswapV2FactoryCalls::AllPairsLength(_) => {
+                    Ok(encode_return(U256::from(pairs.len())))
+                }
+                IUniswapV2FactoryCalls::AllPairs(call) if call.index < U256::from(pairs.len()) => {
+                    Ok(encode_return(pairs[call.index.as_usize()]))
+                }
+                _ => Err(revert()),
+            };
+        }
+        if to == state.factory_v3
+            && let Ok(call) = GetPoolCall::decode(data)
+        {
+            let (token0, token1, _) = sort_tokens(call.token_a, call.token_b);
+            let pool = state
+                .v3_pools
+                .values()
+                .find(|pool| pool.token0 == token0 && pool.token1 == token1 && pool.fee == call.fee)
+                .map(|pool| pool.pool)
+                .unwrap_or_default();
+            return Ok(encode_return(pool));
+        }
+        // an address without code returns no data
+        Ok(json!("0x"))
+    }
+}
+
+impl RpcHandler for MockEnvironment {
+    async fn handle(&self, method: String, params: Value) -> Result<Value, RpcErrorResponse> {
+        match method.as_str() {
+            "eth_chainId" => Ok(json!(U64::from(MOCK_CHAIN_ID))),
+            "net_version" => Ok(json!(MOCK_CHAIN_ID.to_string())),
+            "eth_blockNumber" => Ok(json!(U64::from(self.block_number()))),
+            "eth_gasPrice" | "eth_maxPriorityFeePerGas" => Ok(json!(U256::zero())),
+            "eth_getBalance" => Ok(json!(U256::zero())),
+            "eth_getLogs" => Ok(json!([])),
+            "eth_getBlockByNumber" => Ok(self.block(&params[0])),
+            "eth_getCode" => {
+                let address: Address =
+                    serde_json::from_value(params[0].clone()).map_err(|_| revert())?;
+                let state = self.state.lock().unwrap();
+                let deployed = state.tokens.contains_key(&address)
+                    || state.v2_pairs.contains_key(&address)
+                    || state.v3_pools.contains_key(&address)
+                    || address == state.factory_v2
+                    || address == state.factory_v3;
+                Ok(json!(if deployed { "0xfe" } else { "0x" }))
+            }
+            "eth_call" => {
+                let request = &params[0];
+                let to: Address =
+                    serde_json::from_value(request["to"].clone()).map_err(|_| revert())?;
+                let data: Bytes = serde_json::from_value(
+                    request
+                        .get("input")
+                        .or_else(|| request.get("data"))
+                        .cloned()
+                        .unwrap_or(json!("0x")),
+                )
+                .map_err(|_| revert())?;
+                self.call(to, &data)
+            }
+            _ => Err(RpcErrorResponse {
+                code: RPC_NOT_FOUND_CODE,
+                message: format!("Method {} is not mocked", method),
+                data: None,
+            }),
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use ethers::prelude::*;
+use std::collections::VecDeque;
+
+use crate::{
+    tool::num::u256_to_f64,
+    types::{UniswapError, V2Observation, V2Twap},
+    v2::UniswapV2,
+};
+
+/// 2^112, the UQ112x112 resolution
+pub fn q112() -> U256 {
+    U256::one() << 112
+}
+
+/// the counterfactual price cumulatives at `timestamp`, as UniswapV2OracleLibrary.currentCumulativePrices.
+/// the pair only updates its cumulatives on the first interaction of a block, so the time elapsed
+/// since `block_timestamp_last` is accrued at the current reserves.
+pub fn current_cumulative_prices(
+    price0_cumulative_last: U256,
+    price1_cumulative_last: U256,
+    reserve0: U256,
+    reserve1: U256,
+    block_timestamp_last: u32,
+    timestamp: u64,
+) -> (U256, U256) {
+    let mut price0_cumulative = price0_cumulative_last;
+    let mut price1_cumulative = price1_cumulative_last;
+    // the pair truncates timestamps to uint32, overflow is desired
+    let time_elapsed = (timestamp as u32).wrapping_sub(block_timestamp_last);
+    if time_elapsed > 0 && !reserve0.is_zero() && !reserve1.is_zero() {
+        let elapsed = U256::from(time_elapsed);
+        let price0 = (reserve1 << 112) / reserve0;
+        let price1 = (reserve0 << 112) / reserve1;
+        price0_cumulative = price0_cumulative
+            .overflowing_add(price0.overflowing_mul(elapsed).0)
+            .0;
+        price1_cumulative = price1_cumulative
+            .overflowing_add(price1.overflowing_mul(elapsed).0)
+            .0;
+    }
+    (price0_cumulative, price1_cumulative)
+}
+
+/// compute the time weighted average price between two observations of the same pair.
+pub fn compute_twap(
+    start: &V2Observation,
+    end: &V2Observation,
+    decimals0: u8,
+    decimals1: u8,
+) -> Result<V2Twap, UniswapError> {
+    if start.pair != end.pair {
+        return Err(UniswapError::OracleError(
+            "Observations belong to different pairs".to_string(),
+        ));
+    }
+    if end.timestamp <= start.timestamp {
+        return Err(UniswapError::OracleError(
+            "TWAP window must span a positive time".to_string(),
+        ));
+    }
+    let elapsed = U256::from(end.timestamp - start.timestamp);
+    // cumulatives are allowed to overflow, subtraction recovers the delta
+    let price0_average_x112 = end
+        .price0_cumulative
+        .overflowing_sub(start.price0_cumulative)
+        .0
+        / elapsed;
+    let price1_average_x112 = end
+        .price1_cumulative
+        .overflowing_sub(start.price1_cumulative)
+        .0
+        / elapsed;
+    let scale = 10f64.powi(decimals0 as i32 - decimals1 as i32);
+    Ok(V2Twap {
+        pair: end.pair,
+        start_timestamp: start.timestamp,
+        end_timestamp: end.timestamp,
+        price0_average_x112,
+        price1_average_x112,
+        price0: u256_to_f64(price0_average_x112) / u256_to_f64(q112()) * scale,
+        price1: u256_to_f64(price1_average_x112) / u256_to_f64(q112()) / scale,
+    })
+}
+
+/// rolling buffer of V2 observations for sliding window TWAPs
+#[derive(Debug, Clone)]
+pub struct V2ObservationBuffer {
+    pub pair: Address,
+    pub capacity: usize,
+    pub observations: VecDeque<V2Observation>,
+}
+
+impl V2ObservationBuffer {
+    pub fn new(pair: Address, capacity: usize) -> Self {
+        Self {
+            pair,
+            capacity: capacity.max(2),
+            observations: VecDeque::with_capacity(capacity.max(2)),
+        }
+    }
+
+    /// append an observation, older observations are evicted once the buffer is full.
+    /// observations that are not newer than the latest one are ignored.
+    pub fn push(&mut self, observation: V2Observation) -> Result<(), UniswapError> {
+        if observation.pair != self.pair {
+            return Err(UniswapError::OracleError(
+                "Observation belongs to a different pair".to_string(),
+            ));
+        }
+        if let Some(latest) = self.observations.back()
+            && observation.timestamp <= latest.timestamp
+        {
+            return Ok(());
+        }
+        if self.observations.len() == self.capacity {
+            self.observations.pop_front();
+        }
+        self.observations.push_back(observation);
+        Ok(())
+    }
+
+    /// fetch the current observation from the chain and append it
+    pub async fn update(&mut self, uniswap_v2: &UniswapV2) -> Result<(), UniswapError> {
+        let observation = uniswap_v2.get_observation(self.pair, None).await?;
+        self.push(observation)
+    }
+
+    /// the latest observation
+    pub fn latest(&self) -> Option<&V2Observation> {
+        self.observations.back()
+    }
+
+    /// TWAP over the most recent `window` seconds, using the newest observation that is at
+    /// least `window` seconds older than the latest one.
+    pub fn twap(&self, window: u64, decimals0: u8, decimals1: u8) -> Result<V2Twap, UniswapError> {
+        let latest = self
+            .latest()
+            .ok_or_else(|| UniswapError::OracleError("No observations".to_string()))?;
+        let start = self
+            .observations
+            .iter()
+            .rev()
+            .find(|observation| latest.timestamp - observation.timestamp >= window)
+            .ok_or_else(|| {
+                UniswapError::OracleError(format!(
+                    "No observation old enough for a {}s window",
+                    window
+                ))
+            })?;
+        compute_twap(start, latest, decimals0, decimals1)
+    }
+}
+
+/// arithmetic mean tick and harmonic mean liquidity between two observe() results,
+/// ported from OracleLibrary.consult. `seconds_ago` is the distance between the observations.
+pub fn consult_cumulatives(
+    tick_cumulatives: [i64; 2],
+    seconds_per_liquidity_cumulatives_x128: [U256; 2],
+    seconds_ago: u32,
+) -> Result<(i32, u128), UniswapError> {
+    if seconds_ago == 0 {
+        return Err(UniswapError::OracleError(
+            "Window must be non-zero".to_string(),
+        ));
+    }
+    let tick_cumulatives_delta = tick_cumulatives[1] - tick_cumulatives[0];
+    let seconds_per_liquidity_delta = seconds_per_liquidity_cumulatives_x128[1]
+        .overflowing_sub(seconds_per_liquidity_cumulatives_x128[0])
+        .0
+        // the delta is a uint160 in the contract
+        & ((U256::one() << 160) - 1);
+    let mut arithmetic_mean_tick = (tick_cumulatives_delta / seconds_ago as i64) as i32;
+    // always round to negative infinity
+    if tick_cumulatives_delta < 0 && tick_cumulatives_delta % seconds_ago as i64 != 0 {
+        arithmetic_mean_tick -= 1;
+    }
+    let seconds_ago_x160 = U256::from(seconds_ago) * ((U256::one() << 160) - 1);
+    let denominator = (seconds_per_liquidity_delta << 32) & ((U256::one() << 192) - 1);
+    let harmonic_mean_liquidity = if denominator.is_zero() {
+        u128::MAX
+    } else {
+        (seconds_ago_x160 / denominator)
+            .min(U256::from(u128::MAX))
+            .as_u128()
+    };
+    Ok((arithmetic_mean_tick, harmonic_mean_liquidity))
+}
+
+/// the observation cardinality needed to cover a window, given the average block time.
+/// a pool writes at most one observation per block.
+pub fn required_cardinality(window: u32, block_time: u32) -> u16 {
+    let blocks = window.div_ceil(block_time.max(1)).saturating_add(1);
+    blocks.min(u16::MAX as u32) as u16
+}
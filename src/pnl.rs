@@ -0,0 +1,432 @@
+use ethers::{contract::parse_log, prelude::*};
+use std::sync::Arc;
+
+use crate::{
+    abi::{IUniswapV2Pair, i_uniswap_v2_pair::MintFilter as V2MintFilter},
+    math::{liquidity, tick},
+    position::PositionManager,
+    price::Price,
+    tool::{
+        block::{block_or_latest, pin_block},
+        num::u256_to_decimal,
+    },
+    types::{LpEntry, LpPnl, LpState, PoolInfo, PoolVersion, PriceFinder, UniswapError},
+    v2::UniswapV2,
+    v3::UniswapV3,
+};
+
+/// impermanent loss of a V2 position versus holding, as a fraction of the hodl value.
+/// prices are token0 in token1.
+pub fn v2_impermanent_loss(entry_price: f64, current_price: f64) -> f64 {
+    let ratio = current_price / entry_price;
+    2.0 * ratio.sqrt() / (1.0 + ratio) - 1.0
+}
+
+/// token amounts of one unit of liquidity at `price` in the range [price_lower, price_upper]
+fn v3_unit_amounts(price: f64, price_lower: f64, price_upper: f64) -> (f64, f64) {
+    let sqrt_lower = price_lower.sqrt();
+    let sqrt_upper = price_upper.sqrt();
+    let sqrt_price = price.sqrt().clamp(sqrt_lower, sqrt_upper);
+    (1.0 / sqrt_price - 1.0 / sqrt_upper, sqrt_price - sqrt_lower)
+}
+
+/// impermanent loss of a V3 position over [price_lower, price_upper] versus holding, as a
+/// fraction of the hodl value. out of range the position is entirely in one token.
+pub fn v3_impermanent_loss(
+    entry_price: f64,
+    current_price: f64,
+    price_lower: f64,
+    price_upper: f64,
+) -> f64 {
+    let (entry0, entry1) = v3_unit_amounts(entry_price, price_lower, price_upper);
+    let (current0, current1) = v3_unit_amounts(current_price, price_lower, price_upper);
+    let hodl_value = entry0 * current_price + entry1;
+    if hodl_value <= 0.0 {
+        return 0.0;
+    }
+    (current0 * current_price + current1) / hodl_value - 1.0
+}
+
+/// value an LP entry and state in a quote token. `entry_quote_rate` and `quote_rate` are the
+/// value of one token1 in the quote token at entry and now.
+pub fn compute_pnl(
+    version: PoolVersion,
+    quote_token: Address,
+    entry: LpEntry,
+    state: LpState,
+    entry_quote_rate: f64,
+    quote_rate: f64,
+) -> LpPnl {
+    let percentage = |value: f64, base: f64| {
+        if base > 0.0 {
+            value / base * 100.0
+        } else {
+            0.0
+        }
+    };
+    let entry_value = (entry.amount0 * entry.price + entry.amount1) * entry_quote_rate;
+    let hodl_value = (entry.amount0 * state.price + entry.amount1) * quote_rate;
+    let position_value = (state.amount0 * state.price + state.amount1) * quote_rate;
+    let fees_value = (state.fees0 * state.price + state.fees1) * quote_rate;
+    let impermanent_loss = position_value - hodl_value;
+    let net_pnl = position_value + fees_value - entry_value;
+    LpPnl {
+        version,
+        quote_token,
+        entry,
+        state,
+        entry_value,
+        hodl_value,
+        position_value,
+        fees_value,
+        impermanent_loss,
+        impermanent_loss_pct: percentage(impermanent_loss, hodl_value),
+        net_pnl,
+        net_pnl_pct: percentage(net_pnl, entry_value),
+    }
+}
+
+/// LP impermanent loss and PnL reports from on-chain data
+pub struct PnlCalculator {
+    provider: Arc<Provider<Http>>,
+    price: Price,
+    position_manager: PositionManager,
+}
+
+impl PnlCalculator {
+    pub fn new(provider: Arc<Provider<Http>>) -> Result<Self, UniswapError> {
+        let price = Price::new_with_finder(provider.clone(), PriceFinder::mainnet())?;
+        let position_manager = PositionManager::new(provider.clone());
+        Ok(Self::new_with(provider, price, position_manager))
+    }
+
+    pub fn new_with(
+        provider: Arc<Provider<Http>>,
+        price: Price,
+        position_manager: PositionManager,
+    ) -> Self {
+        Self {
+            provider,
+            price,
+            position_manager,
+        }
+    }
+
+    /// token0 price in token1 of a V2 pair, decimals adjusted
+    fn v2_price(pool_info: &PoolInfo) -> f64 {
+        let reserve0 = u256_to_decimal(pool_info.reserve0, pool_info.token0.decimals);
+        let reserve1 = u256_to_decimal(pool_info.reserve1, pool_info.token1.decimals);
+        if reserve0 > 0.0 {
+            reserve1 / reserve0
+        } else {
+            0.0
+        }
+    }
+
+    /// resolve a block once so every read of a computation sees the same state
+    async fn pin_block_number(&self, block: Option<BlockId>) -> Result<u64, UniswapError> {
+        match pin_block(&self.provider, block).await? {
+            BlockId::Number(BlockNumber::Number(number)) => Ok(number.as_u64()),
+            block => self
+                .provider
+                .get_block(block)
+                .await
+                .map_err(UniswapError::EthersError)?
+                .and_then(|block| block.number)
+                .map(|number| number.as_u64())
+                .ok_or_else(|| UniswapError::ContractError("Block not found".to_string())),
+        }
+    }
+
+    /// get the entry of a V2 deposit from the pair's Mint event in the add liquidity transaction
+    pub async fn get_v2_entry(
+        &self,
+        pair_address: Address,
+        tx_hash: TxHash,
+    ) -> Result<LpEntry, UniswapError> {
+        let receipt = self
+            .provider
+            .get_transaction_receipt(tx_hash)
+            .await
+            .map_err(UniswapError::EthersError)?
+            .ok_or_else(|| UniswapError::ContractError("Receipt not found".to_string()))?;
+        let event = receipt
+            .logs
+            .iter()
+            .filter(|log| log.address == pair_address)
+            .find_map(|log| parse_log::<V2MintFilter>(log.clone()).ok())
+            .ok_or_else(|| UniswapError::ContractError("Mint event not found".to_string()))?;
+        let block_number = receipt.block_number.map(|number| number.as_u64());
+        let pool_info = UniswapV2::new_with_provider(self.provider.clone())
+            .get_pool_info(pair_address, block_number.map(BlockId::from))
+            .await?;
+        Ok(LpEntry {
+            amount0: u256_to_decimal(event.amount_0, pool_info.token0.decimals),
+            amount1: u256_to_decimal(event.amount_1, pool_info.token1.decimals),
+            price: Self::v2_price(&pool_info),
+            block_number,
+        })
+    }
+
+    /// get the state of a V2 position at a block from the owner's LP balance, defaulting to
+    /// the latest block. fees are separated from principal through the growth of the share's sqrt(k).
+    pub async fn get_v2_state(
+        &self,
+        pair_address: Address,
+        owner: Address,
+        entry: &LpEntry,
+        block: Option<BlockId>,
+    ) -> Result<LpState, UniswapError> {
+        let block = Some(pin_block(&self.provider, block).await?);
+        let pool_info = UniswapV2::new_with_provider(self.provider.clone())
+            .get_pool_info(pair_address, block)
+            .await?;
+        let pair_contract = IUniswapV2Pair::new(pair_address, self.provider.clone());
+        let balance = pair_contract
+            .balance_of(owner)
+            .block(block_or_latest(block))
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("Balance error: {}", e)))?;
+        if pool_info.liquidity.is_zero() {
+            return Err(UniswapError::PoolNotFound);
+        }
+        let share = u256_to_decimal(balance, 0) / u256_to_decimal(pool_info.liquidity, 0);
+        let amount0 = u256_to_decimal(pool_info.reserve0, pool_info.token0.decimals) * share;
+        let amount1 = u256_to_decimal(pool_info.reserve1, pool_info.token1.decimals) * share;
+        // without fees sqrt(x * y) of the share is constant
+        let entry_k = (entry.amount0 * entry.amount1).sqrt();
+        let growth = if entry_k > 0.0 {
+            ((amount0 * amount1).sqrt() / entry_k).max(1.0)
+        } else {
+            1.0
+        };
+        Ok(LpState {
+            price: Self::v2_price(&pool_info),
+            amount0: amount0 / growth,
+            amount1: amount1 / growth,
+            fees0: amount0 - amount0 / growth,
+            fees1: amount1 - amount1 / growth,
+        })
+    }
+
+    /// get the impermanent loss and PnL of a V2 position in `quote_token`
+    pub async fn get_v2_pnl(
+        &self,
+        pair_address: Address,
+        owner: Address,
+        entry: LpEntry,
+        quote_token: Address,
+    ) -> Result<LpPnl, UniswapError> {
+        // the state and the quote rate are read at the same block
+        let block = Some(pin_block(&self.provider, None).await?);
+        let pool_info = UniswapV2::new_with_provider(self.provider.clone())
+            .get_pool_info(pair_address, block)
+            .await?;
+        let state = self
+            .get_v2_state(pair_address, owner, &entry, block)
+            .await?;
+        let (entry_quote_rate, quote_rate) = self
+            .get_quote_rates(&pool_info, &entry, &state, quote_token, block)
+            .await?;
+        Ok(compute_pnl(
+            PoolVersion::V2,
+            quote_token,
+            entry,
+            state,
+            entry_quote_rate,
+            quote_rate,
+        ))
+    }
+
+    /// get the pool info of the pool a V3 position belongs to at a block
+    async fn get_v3_pool(
+        &self,
+        token_id: U256,
+        block: Option<BlockId>,
+    ) -> Result<(Address, PoolInfo, i32, i32, u128), UniswapError> {
+        let position = self.position_manager.get_position(token_id, block).await?;
+        let pool_address = self
+            .position_manager
+            .get_pool_address(position.token0, position.token1, position.fee)
+            .await?;
+        let pool_info = UniswapV3::new_with_provider(self.provider.clone())
+            .get_pool_info(pool_address, block)
+            .await?;
+        Ok((
+            pool_address,
+            pool_info,
+            position.tick_lower,
+            position.tick_upper,
+            position.liquidity,
+        ))
+    }
+
+    /// get the entry of a V3 position from its IncreaseLiquidity events since `from_block`,
+    /// priced at the block of the first deposit
+    pub async fn get_v3_entry(
+        &self,
+        token_id: U256,
+        from_block: u64,
+    ) -> Result<LpEntry, UniswapError> {
+        let to_block = self.pin_block_number(None).await?;
+        let (pool_address, pool_info, _, _, _) =
+            self.get_v3_pool(token_id, Some(to_block.into())).await?;
+        let history = self
+            .position_manager
+            .get_position_history(token_id, from_block, to_block)
+            .await?;
+        let first_block = history
+            .first_block
+            .ok_or_else(|| UniswapError::ContractError("Position has no deposits".to_string()))?;
+        let (sqrt_price_x96, _, _) = UniswapV3::new_with_provider(self.provider.clone())
+            .get_pool_state(pool_address, Some(first_block.into()))
+            .await?;
+        let (amount0, amount1) = history.increase_liquidity.iter().fold(
+            (U256::zero(), U256::zero()),
+            |(amount0, amount1), change| (amount0 + change.amount0, amount1 + change.amount1),
+        );
+        Ok(LpEntry {
+            amount0: u256_to_decimal(amount0, pool_info.token0.decimals),
+            amount1: u256_to_decimal(amount1, pool_info.token1.decimals),
+            price: tick::sqrt_price_x96_to_price(
+                sqrt_price_x96,
+                pool_info.token0.decimals,
+                pool_info.token1.decimals,
+            ),
+            block_number: Some(first_block),
+        })
+    }
+
+    /// get the state of a V3 position at a block, defaulting to the latest block. withdrawn
+    /// principal counts towards the position, fees are the collected and collectable amounts
+    /// beyond withdrawn principal.
+    pub async fn get_v3_state(
+        &self,
+        token_id: U256,
+        owner: Address,
+        from_block: u64,
+        block: Option<BlockId>,
+    ) -> Result<LpState, UniswapError> {
+        let to_block = self.pin_block_number(block).await?;
+        let block = Some(BlockId::from(to_block));
+        let (pool_address, pool_info, tick_lower, tick_upper, position_liquidity) =
+            self.get_v3_pool(token_id, block).await?;
+        let (sqrt_price_x96, _, _) = UniswapV3::new_with_provider(self.provider.clone())
+            .get_pool_state(pool_address, block)
+            .await?;
+        let (amount0, amount1) = liquidity::get_amounts_for_liquidity(
+            sqrt_price_x96,
+            tick::get_sqrt_ratio_at_tick(tick_lower)?,
+            tick::get_sqrt_ratio_at_tick(tick_upper)?,
+            position_liquidity,
+        );
+        let history = self
+            .position_manager
+            .get_position_history(token_id, from_block, to_block)
+            .await?;
+        let (withdrawn0, withdrawn1) = history.decrease_liquidity.iter().fold(
+            (U256::zero(), U256::zero()),
+            |(amount0, amount1), change| (amount0 + change.amount0, amount1 + change.amount1),
+        );
+        let (collected0, collected1) = history.collect.iter().fold(
+            (U256::zero(), U256::zero()),
+            |(amount0, amount1), collect| (amount0 + collect.amount0, amount1 + collect.amount1),
+        );
+        let (collectable0, collectable1) = self
+            .position_manager
+            .get_collectable(token_id, owner, block)
+            .await?;
+        let decimals0 = pool_info.token0.decimals;
+        let decimals1 = pool_info.token1.decimals;
+        Ok(LpState {
+            price: tick::sqrt_price_x96_to_price(sqrt_price_x96, decimals0, decimals1),
+            amount0: u256_to_decimal(amount0 + withdrawn0, decimals0),
+            amount1: u256_to_decimal(amount1 + withdrawn1, decimals1),
+            fees0: u256_to_decimal(
+                (collected0 + collectable0).saturating_sub(withdrawn0),
+                decimals0,
+            ),
+            fees1: u256_to_decimal(
+                (collected1 + collectable1).saturating_sub(withdrawn1),
+                decimals1,
+            ),
+        })
+    }
+
+    /// get the impermanent loss and PnL of a V3 position in `quote_token`
+    pub async fn get_v3_pnl(
+        &self,
+        token_id: U256,
+        owner: Address,
+        from_block: u64,
+        entry: LpEntry,
+        quote_token: Address,
+    ) -> Result<LpPnl, UniswapError> {
+        // the state and the quote rate are read at the same block
+        let block = Some(BlockId::from(self.pin_block_number(None).await?));
+        let (_, pool_info, _, _, _) = self.get_v3_pool(token_id, block).await?;
+        let state = self
+            .get_v3_state(token_id, owner, from_block, block)
+            .await?;
+        let (entry_quote_rate, quote_rate) = self
+            .get_quote_rates(&pool_info, &entry, &state, quote_token, block)
+            .await?;
+        Ok(compute_pnl(
+            PoolVersion::V3,
+            quote_token,
+            entry,
+            state,
+            entry_quote_rate,
+            quote_rate,
+        ))
+    }
+
+    /// value of one token1 in the quote token at entry and at `block`. quote tokens outside
+    /// the pool are priced through `Price`, at the entry block when it is known.
+    async fn get_quote_rates(
+        &self,
+        pool_info: &PoolInfo,
+        entry: &LpEntry,
+        state: &LpState,
+        quote_token: Address,
+        block: Option<BlockId>,
+    ) -> Result<(f64, f64), UniswapError> {
+        if quote_token == pool_info.token1.address {
+            return Ok((1.0, 1.0));
+        }
+        if quote_token == pool_info.token0.address {
+            if entry.price <= 0.0 || state.price <= 0.0 {
+                return Err(UniswapError::MathError("Pool has no price".to_string()));
+            }
+            return Ok((1.0 / entry.price, 1.0 / state.price));
+        }
+        let quote_rate = self.get_quote_rate(pool_info, quote_token, block).await?;
+        let entry_quote_rate = match entry.block_number {
+            Some(block_number) => self
+                .get_quote_rate(pool_info, quote_token, Some(block_number.into()))
+                .await
+                .unwrap_or(quote_rate),
+            None => quote_rate,
+        };
+        Ok((entry_quote_rate, quote_rate))
+    }
+
+    /// value of one token1 in a quote token outside the pool at a block
+    async fn get_quote_rate(
+        &self,
+        pool_info: &PoolInfo,
+        quote_token: Address,
+        block: Option<BlockId>,
+    ) -> Result<f64, UniswapError> {
+        let price_info = self
+            .price
+            .get_token_prices_by_token_address(pool_info.token1.address, block)
+            .await?;
+        price_info
+            .quote_prices
+            .get(&quote_token)
+            .copied()
+            .ok_or(UniswapError::PoolNotFound)
+    }
+}
@@ -0,0 +1,614 @@
+use ethers::{
+    contract::parse_log,
+    prelude::*,
+    types::{Filter, transaction::eip2718::TypedTransaction},
+};
+use std::sync::Arc;
+
+use crate::{
+    abi::{
+        CollectFilter, CollectParams, DecreaseLiquidityFilter, DecreaseLiquidityParams,
+        INonfungiblePositionManager, IUniswapV3Factory, IUniswapV3Pool, IncreaseLiquidityFilter,
+        IncreaseLiquidityParams, MintParams,
+    },
+    events::get_logs_in_chunks,
+    global::mainnet::{
+        MAINNET_UNISWAP_V3_FACTORY_ADDRESS, MAINNET_UNISWAP_V3_POSITION_MANAGER_ADDRESS,
+    },
+    math::{liquidity, tick},
+    tool::{
+        block::block_or_latest,
+        tx::{ensure_allowance, send_transaction, wallet_client},
+    },
+    types::{
+        CollectRequest, CollectedFees, DecreaseLiquidityRequest, IncreaseLiquidityRequest,
+        LiquidityChange, MintRequest, PositionHistory, PositionInfo, PositionTransactionResult,
+        UniswapError,
+    },
+};
+
+/// uniswap v3 NonfungiblePositionManager client
+pub struct PositionManager {
+    pub provider: Arc<Provider<Http>>,
+    pub address: Address,
+    pub v3_factory: Address,
+}
+
+impl PositionManager {
+    pub fn new(provider: Arc<Provider<Http>>) -> Self {
+        Self {
+            provider,
+            address: MAINNET_UNISWAP_V3_POSITION_MANAGER_ADDRESS.parse().unwrap(),
+            v3_factory: MAINNET_UNISWAP_V3_FACTORY_ADDRESS.parse().unwrap(),
+        }
+    }
+
+    pub fn new_with_address(
+        provider: Arc<Provider<Http>>,
+        address: Address,
+        v3_factory: Address,
+    ) -> Self {
+        Self {
+            provider,
+            address,
+            v3_factory,
+        }
+    }
+
+    fn contract(&self) -> INonfungiblePositionManager<Provider<Http>> {
+        INonfungiblePositionManager::new(self.address, self.provider.clone())
+    }
+
+    /// get position info by token id at a block, defaulting to the latest block
+    pub async fn get_position(
+        &self,
+        token_id: U256,
+        block: Option<BlockId>,
+    ) -> Result<PositionInfo, UniswapError> {
+        let (
+            nonce,
+            operator,
+            token0,
+            token1,
+            fee,
+            tick_lower,
+            tick_upper,
+            liquidity,
+            fee_growth_inside0_last_x128,
+            fee_growth_inside1_last_x128,
+            tokens_owed0,
+            tokens_owed1,
+        ) = self
+            .contract()
+            .positions(token_id)
+            .block(block_or_latest(block))
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("Positions error: {}", e)))?;
+        Ok(PositionInfo {
+            token_id,
+            nonce,
+            operator,
+            token0,
+            token1,
+            fee,
+            tick_lower,
+            tick_upper,
+            liquidity,
+            fee_growth_inside0_last_x128,
+            fee_growth_inside1_last_x128,
+            tokens_owed0,
+            tokens_owed1,
+        })
+    }
+
+    /// get all positions held by an owner
+    pub async fn get_positions_by_owner(
+        &self,
+        owner: Address,
+    ) -> Result<Vec<PositionInfo>, UniswapError> {
+        let contract = self.contract();
+        let count = contract
+            .balance_of(owner)
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("Balance error: {}", e)))?;
+        let mut positions = Vec::new();
+        for index in 0..count.as_u64() {
+            let token_id = contract
+                .token_of_owner_by_index(owner, index.into())
+                .call()
+                .await
+                .map_err(|e| UniswapError::ContractError(format!("Token id error: {}", e)))?;
+            positions.push(self.get_position(token_id, None).await?);
+        }
+        Ok(positions)
+    }
+
+    /// get the address of the pool a position belongs to
+    pub async fn get_pool_address(
+        &self,
+        token0: Address,
+        token1: Address,
+        fee: u32,
+    ) -> Result<Address, UniswapError> {
+        let factory = IUniswapV3Factory::new(self.v3_factory, self.provider.clone());
+        let pool_address = factory
+            .get_pool(token0, token1, fee)
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("V3 Factory error: {}", e)))?;
+        if pool_address == Address::zero() {
+            return Err(UniswapError::PoolNotFound);
+        }
+        Ok(pool_address)
+    }
+
+    /// get the amounts the owner could collect now, uncollected fees plus withdrawn principal
+    /// not yet collected. simulated with a static collect call from the owner at `block`.
+    pub async fn get_collectable(
+        &self,
+        token_id: U256,
+        owner: Address,
+        block: Option<BlockId>,
+    ) -> Result<(U256, U256), UniswapError> {
+        self.contract()
+            .collect(CollectParams {
+                token_id,
+                recipient: owner,
+                amount_0_max: u128::MAX,
+                amount_1_max: u128::MAX,
+            })
+            .from(owner)
+            .block(block_or_latest(block))
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("Collect error: {}", e)))
+    }
+
+    /// get the IncreaseLiquidity, DecreaseLiquidity and Collect events of a position between two blocks
+    pub async fn get_position_history(
+        &self,
+        token_id: U256,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<PositionHistory, UniswapError> {
+        let filter = Filter::new()
+            .address(self.address)
+            .topic1(H256::from_uint(&token_id));
+        let logs =
+            get_logs_in_chunks(self.provider.as_ref(), &filter, from_block, to_block).await?;
+        let mut history = PositionHistory {
+            token_id,
+            first_block: logs
+                .first()
+                .and_then(|log| log.block_number)
+                .map(|block_number| block_number.as_u64()),
+            increase_liquidity: Vec::new(),
+            decrease_liquidity: Vec::new(),
+            collect: Vec::new(),
+        };
+        for log in logs {
+            if let Ok(event) = parse_log::<IncreaseLiquidityFilter>(log.clone()) {
+                history.increase_liquidity.push(LiquidityChange {
+                    token_id: event.token_id,
+                    liquidity: event.liquidity,
+                    amount0: event.amount_0,
+                    amount1: event.amount_1,
+                });
+            } else if let Ok(event) = parse_log::<DecreaseLiquidityFilter>(log.clone()) {
+                history.decrease_liquidity.push(LiquidityChange {
+                    token_id: event.token_id,
+                    liquidity: event.liquidity,
+                    amount0: event.amount_0,
+                    amount1: event.amount_1,
+                });
+            } else if let Ok(event) = parse_log::<CollectFilter>(log) {
+                history.collect.push(CollectedFees {
+                    token_id: event.token_id,
+                    recipient: event.recipient,
+                    amount0: event.amount_0,
+                    amount1: event.amount_1,
+                });
+            }
+        }
+        Ok(history)
+    }
+
+    /// get the pool a position belongs to
+    async fn get_pool(
+        &self,
+        token0: Address,
+        token1: Address,
+        fee: u32,
+    ) -> Result<IUniswapV3Pool<Provider<Http>>, UniswapError> {
+        let pool_address = self.get_pool_address(token0, token1, fee).await?;
+        Ok(IUniswapV3Pool::new(pool_address, self.provider.clone()))
+    }
+
+    /// get the current sqrt price of a pool
+    async fn get_sqrt_price(
+        pool_contract: &IUniswapV3Pool<Provider<Http>>,
+    ) -> Result<U256, UniswapError> {
+        let (sqrt_price_x96, _, _, _, _, _, _) = pool_contract
+            .slot_0()
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("Slot0 error: {}", e)))?;
+        Ok(sqrt_price_x96)
+    }
+
+    /// get the current sqrt price of the pool a position belongs to
+    async fn get_pool_sqrt_price(
+        &self,
+        token0: Address,
+        token1: Address,
+        fee: u32,
+    ) -> Result<U256, UniswapError> {
+        let pool_contract = self.get_pool(token0, token1, fee).await?;
+        Self::get_sqrt_price(&pool_contract).await
+    }
+
+    /// compute the liquidity and slippage protected minimum amounts for the desired amounts.
+    fn quote_liquidity(
+        sqrt_price_x96: U256,
+        tick_lower: i32,
+        tick_upper: i32,
+        amount0_desired: U256,
+        amount1_desired: U256,
+        slippage: f64,
+    ) -> Result<(u128, U256, U256), UniswapError> {
+        let sqrt_lower = tick::get_sqrt_ratio_at_tick(tick_lower)?;
+        let sqrt_upper = tick::get_sqrt_ratio_at_tick(tick_upper)?;
+        let liquidity = liquidity::get_liquidity_for_amounts(
+            sqrt_price_x96,
+            sqrt_lower,
+            sqrt_upper,
+            amount0_desired,
+            amount1_desired,
+        )?;
+        let (amount0, amount1) =
+            liquidity::get_amounts_for_liquidity(sqrt_price_x96, sqrt_lower, sqrt_upper, liquidity);
+        Ok((
+            liquidity,
+            liquidity::apply_slippage(amount0, slippage),
+            liquidity::apply_slippage(amount1, slippage),
+        ))
+    }
+
+    /// build mint params, returns the params and the expected liquidity
+    pub async fn build_mint_params(
+        &self,
+        request: &MintRequest,
+    ) -> Result<(MintParams, u128), UniswapError> {
+        if request.tick_lower >= request.tick_upper {
+            return Err(UniswapError::MathError(format!(
+                "Invalid tick range: {} >= {}",
+                request.tick_lower, request.tick_upper
+            )));
+        }
+        // the position manager requires token0 < token1. the request ticks price token0 in
+        // token1, so for a reversed pair the range is mirrored into the sorted orientation.
+        let (token0, token1, amount0_desired, amount1_desired, tick_lower, tick_upper) =
+            if request.token0 < request.token1 {
+                (
+                    request.token0,
+                    request.token1,
+                    request.amount0_desired,
+                    request.amount1_desired,
+                    request.tick_lower,
+                    request.tick_upper,
+                )
+            } else {
+                (
+                    request.token1,
+                    request.token0,
+                    request.amount1_desired,
+                    request.amount0_desired,
+                    -request.tick_upper,
+                    -request.tick_lower,
+                )
+            };
+        let pool_contract = self.get_pool(token0, token1, request.fee).await?;
+        let tick_spacing = pool_contract
+            .tick_spacing()
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("Tick spacing error: {}", e)))?;
+        if tick_lower % tick_spacing != 0 || tick_upper % tick_spacing != 0 {
+            return Err(UniswapError::MathError(format!(
+                "Ticks {} and {} must be multiples of the tick spacing {}",
+                tick_lower, tick_upper, tick_spacing
+            )));
+        }
+        let sqrt_price_x96 = Self::get_sqrt_price(&pool_contract).await?;
+        let (liquidity, amount0_min, amount1_min) = Self::quote_liquidity(
+            sqrt_price_x96,
+            tick_lower,
+            tick_upper,
+            amount0_desired,
+            amount1_desired,
+            request.slippage,
+        )?;
+        let params = MintParams {
+            token_0: token0,
+            token_1: token1,
+            fee: request.fee,
+            tick_lower,
+            tick_upper,
+            amount_0_desired: amount0_desired,
+            amount_1_desired: amount1_desired,
+            amount_0_min: amount0_min,
+            amount_1_min: amount1_min,
+            recipient: request.recipient,
+            deadline: request.deadline.into(),
+        };
+        Ok((params, liquidity))
+    }
+
+    /// build increase liquidity params, returns the params and the expected liquidity
+    pub async fn build_increase_liquidity_params(
+        &self,
+        request: &IncreaseLiquidityRequest,
+    ) -> Result<(IncreaseLiquidityParams, u128), UniswapError> {
+        let position = self.get_position(request.token_id, None).await?;
+        let sqrt_price_x96 = self
+            .get_pool_sqrt_price(position.token0, position.token1, position.fee)
+            .await?;
+        let (liquidity, amount0_min, amount1_min) = Self::quote_liquidity(
+            sqrt_price_x96,
+            position.tick_lower,
+            position.tick_upper,
+            request.amount0_desired,
+            request.amount1_desired,
+            request.slippage,
+        )?;
+        let params = IncreaseLiquidityParams {
+            token_id: request.token_id,
+            amount_0_desired: request.amount0_desired,
+            amount_1_desired: request.amount1_desired,
+            amount_0_min: amount0_min,
+            amount_1_min: amount1_min,
+            deadline: request.deadline.into(),
+        };
+        Ok((params, liquidity))
+    }
+
+    /// build decrease liquidity params with slippage protected minimum amounts
+    pub async fn build_decrease_liquidity_params(
+        &self,
+        request: &DecreaseLiquidityRequest,
+    ) -> Result<DecreaseLiquidityParams, UniswapError> {
+        let position = self.get_position(request.token_id, None).await?;
+        if request.liquidity > position.liquidity {
+            return Err(UniswapError::InsufficientBalance);
+        }
+        let sqrt_price_x96 = self
+            .get_pool_sqrt_price(position.token0, position.token1, position.fee)
+            .await?;
+        let (amount0, amount1) = liquidity::get_amounts_for_liquidity(
+            sqrt_price_x96,
+            tick::get_sqrt_ratio_at_tick(position.tick_lower)?,
+            tick::get_sqrt_ratio_at_tick(position.tick_upper)?,
+            request.liquidity,
+        );
+        Ok(DecreaseLiquidityParams {
+            token_id: request.token_id,
+            liquidity: request.liquidity,
+            amount_0_min: liquidity::apply_slippage(amount0, request.slippage),
+            amount_1_min: liquidity::apply_slippage(amount1, request.slippage),
+            deadline: request.deadline.into(),
+        })
+    }
+
+    /// build collect params
+    pub fn build_collect_params(&self, request: &CollectRequest) -> CollectParams {
+        CollectParams {
+            token_id: request.token_id,
+            recipient: request.recipient,
+            amount_0_max: request.amount0_max,
+            amount_1_max: request.amount1_max,
+        }
+    }
+
+    /// build mint transaction
+    pub fn build_mint_tx(&self, params: MintParams) -> TypedTransaction {
+        self.contract().mint(params).tx
+    }
+
+    /// build increase liquidity transaction
+    pub fn build_increase_liquidity_tx(&self, params: IncreaseLiquidityParams) -> TypedTransaction {
+        self.contract().increase_liquidity(params).tx
+    }
+
+    /// build decrease liquidity transaction
+    pub fn build_decrease_liquidity_tx(&self, params: DecreaseLiquidityParams) -> TypedTransaction {
+        self.contract().decrease_liquidity(params).tx
+    }
+
+    /// build collect transaction
+    pub fn build_collect_tx(&self, params: CollectParams) -> TypedTransaction {
+        self.contract().collect(params).tx
+    }
+
+    /// build burn transaction, the position must have no liquidity and no owed tokens
+    pub fn build_burn_tx(&self, token_id: U256) -> TypedTransaction {
+        self.contract().burn(token_id).tx
+    }
+
+    /// build a multicall transaction combining several encoded position manager calls
+    pub fn build_multicall_tx(&self, calls: Vec<Bytes>) -> TypedTransaction {
+        self.contract().multicall(calls).tx
+    }
+
+    /// build a multicall that decreases liquidity, collects everything owed and optionally burns
+    pub fn build_remove_position_tx(
+        &self,
+        decrease: DecreaseLiquidityParams,
+        recipient: Address,
+        burn: bool,
+    ) -> Result<TypedTransaction, UniswapError> {
+        let contract = self.contract();
+        let token_id = decrease.token_id;
+        let mut calls = vec![
+            contract
+                .decrease_liquidity(decrease)
+                .calldata()
+                .ok_or_else(|| UniswapError::ContractError("Encode error".to_string()))?,
+            contract
+                .collect(CollectParams {
+                    token_id,
+                    recipient,
+                    amount_0_max: u128::MAX,
+                    amount_1_max: u128::MAX,
+                })
+                .calldata()
+                .ok_or_else(|| UniswapError::ContractError("Encode error".to_string()))?,
+        ];
+        if burn {
+            calls.push(
+                contract
+                    .burn(token_id)
+                    .calldata()
+                    .ok_or_else(|| UniswapError::ContractError("Encode error".to_string()))?,
+            );
+        }
+        Ok(self.build_multicall_tx(calls))
+    }
+
+    /// decode position manager events from a transaction receipt
+    pub fn decode_receipt(
+        &self,
+        transaction: crate::types::TransactionResult,
+        receipt: &TransactionReceipt,
+    ) -> PositionTransactionResult {
+        let mut result = PositionTransactionResult {
+            transaction,
+            increase_liquidity: Vec::new(),
+            decrease_liquidity: Vec::new(),
+            collect: Vec::new(),
+        };
+        for log in receipt
+            .logs
+            .iter()
+            .filter(|log| log.address == self.address)
+        {
+            if let Ok(event) = parse_log::<IncreaseLiquidityFilter>(log.clone()) {
+                result.increase_liquidity.push(LiquidityChange {
+                    token_id: event.token_id,
+                    liquidity: event.liquidity,
+                    amount0: event.amount_0,
+                    amount1: event.amount_1,
+                });
+            } else if let Ok(event) = parse_log::<DecreaseLiquidityFilter>(log.clone()) {
+                result.decrease_liquidity.push(LiquidityChange {
+                    token_id: event.token_id,
+                    liquidity: event.liquidity,
+                    amount0: event.amount_0,
+                    amount1: event.amount_1,
+                });
+            } else if let Ok(event) = parse_log::<CollectFilter>(log.clone()) {
+                result.collect.push(CollectedFees {
+                    token_id: event.token_id,
+                    recipient: event.recipient,
+                    amount0: event.amount_0,
+                    amount1: event.amount_1,
+                });
+            }
+        }
+        result
+    }
+
+    /// send a position manager transaction and decode its events
+    async fn execute(
+        &self,
+        wallet: LocalWallet,
+        tx: TypedTransaction,
+        approvals: &[(Address, U256)],
+    ) -> Result<PositionTransactionResult, UniswapError> {
+        let client = wallet_client(self.provider.clone(), wallet).await?;
+        for (token, amount) in approvals {
+            if !amount.is_zero() {
+                ensure_allowance(client.clone(), *token, self.address, *amount).await?;
+            }
+        }
+        let (transaction, receipt) = send_transaction(client, tx).await?;
+        Ok(self.decode_receipt(transaction, &receipt))
+    }
+
+    /// mint a new position
+    pub async fn mint(
+        &self,
+        wallet: LocalWallet,
+        request: &MintRequest,
+    ) -> Result<PositionTransactionResult, UniswapError> {
+        let (params, _) = self.build_mint_params(request).await?;
+        let approvals = [
+            (params.token_0, params.amount_0_desired),
+            (params.token_1, params.amount_1_desired),
+        ];
+        self.execute(wallet, self.build_mint_tx(params), &approvals)
+            .await
+    }
+
+    /// add liquidity to an existing position
+    pub async fn increase_liquidity(
+        &self,
+        wallet: LocalWallet,
+        request: &IncreaseLiquidityRequest,
+    ) -> Result<PositionTransactionResult, UniswapError> {
+        let position = self.get_position(request.token_id, None).await?;
+        let (params, _) = self.build_increase_liquidity_params(request).await?;
+        let approvals = [
+            (position.token0, params.amount_0_desired),
+            (position.token1, params.amount_1_desired),
+        ];
+        self.execute(wallet, self.build_increase_liquidity_tx(params), &approvals)
+            .await
+    }
+
+    /// remove liquidity from a position, the tokens stay owed until collected
+    pub async fn decrease_liquidity(
+        &self,
+        wallet: LocalWallet,
+        request: &DecreaseLiquidityRequest,
+    ) -> Result<PositionTransactionResult, UniswapError> {
+        let params = self.build_decrease_liquidity_params(request).await?;
+        self.execute(wallet, self.build_decrease_liquidity_tx(params), &[])
+            .await
+    }
+
+    /// collect owed tokens and fees from a position
+    pub async fn collect(
+        &self,
+        wallet: LocalWallet,
+        request: &CollectRequest,
+    ) -> Result<PositionTransactionResult, UniswapError> {
+        let params = self.build_collect_params(request);
+        self.execute(wallet, self.build_collect_tx(params), &[])
+            .await
+    }
+
+    /// burn an empty position
+    pub async fn burn(
+        &self,
+        wallet: LocalWallet,
+        token_id: U256,
+    ) -> Result<PositionTransactionResult, UniswapError> {
+        self.execute(wallet, self.build_burn_tx(token_id), &[])
+            .await
+    }
+
+    /// decrease liquidity, collect and optionally burn in a single multicall
+    pub async fn remove_position(
+        &self,
+        wallet: LocalWallet,
+        request: &DecreaseLiquidityRequest,
+        recipient: Address,
+        burn: bool,
+    ) -> Result<PositionTransactionResult, UniswapError> {
+        let params = self.build_decrease_liquidity_params(request).await?;
+        let tx = self.build_remove_position_tx(params, recipient, burn)?;
+        self.execute(wallet, tx, &[]).await
+    }
+}
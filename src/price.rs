@@ -1,42 +1,122 @@
 use ethers::prelude::*;
 use ethers_providers::{Http, Provider};
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 use crate::{
-    abi::{IUniswapV2Factory, IUniswapV3Factory},
-    global::mainnet::{
-        DAI_ADDRESS, MAINNET_UNISWAP_V2_FACTORY_ADDRESS, MAINNET_UNISWAP_V3_FACTORY_ADDRESS,
-        USDC_ADDRESS, USDT_ADDRESS, WETH_ADDRESS,
+    abi::{IERC20, IUniswapV2Factory, IUniswapV3Factory},
+    depth::amounts_to_price_impact,
+    global::mainnet::{DAI_ADDRESS, USDC_ADDRESS, USDT_ADDRESS},
+    math::tick::sqrt_price_x96_to_price,
+    registry::PoolRegistry,
+    token::TokenCache,
+    tool::block::block_or_latest,
+    types::{
+        BlockSnapshot, PoolInfo, PoolLiquidity, PoolPrice, PoolVersion, PriceAggregation,
+        PriceFinder, PriceHop, PriceMode, PriceProvenance, PricingPolicy, QuoteAsset, TokenInfo,
+        TokenPriceInfo, UniswapError,
     },
-    tool::address::str_to_h160_1,
-    types::{PoolInfo, TokenInfo, TokenPriceInfo, UniswapError},
     v2::UniswapV2,
     v3::UniswapV3,
 };
 
+/// V3 fee tiers searched for pools
+pub const V3_FEE_TIERS: [u32; 4] = [100, 500, 3000, 10000];
+/// average block time in seconds, used to find the start block of a V2 TWAP window
+pub const AVERAGE_BLOCK_TIME: u64 = 12;
+
 pub struct Price {
     provider: Arc<Provider<Http>>,
-    v2_factory: Address,
-    v3_factory: Address,
+    finder: PriceFinder,
+    policy: PricingPolicy,
+    tokens: Arc<TokenCache>,
+    registry: Option<Arc<PoolRegistry>>,
 }
 
 impl Price {
-    pub fn new(provider: Arc<Provider<Http>>) -> Result<Self, ()> {
-        let v2_factory = MAINNET_UNISWAP_V2_FACTORY_ADDRESS.parse().unwrap();
-        let v3_factory = MAINNET_UNISWAP_V3_FACTORY_ADDRESS.parse().unwrap();
+    pub fn new(provider: Arc<Provider<Http>>) -> Result<Self, UniswapError> {
+        Self::new_with_finder(provider, PriceFinder::mainnet())
+    }
+
+    pub fn new_with_finder(
+        provider: Arc<Provider<Http>>,
+        finder: PriceFinder,
+    ) -> Result<Self, UniswapError> {
         Ok(Self {
-            provider: provider,
-            v2_factory,
-            v3_factory,
+            provider,
+            finder,
+            policy: PricingPolicy::default(),
+            tokens: TokenCache::shared(),
+            registry: None,
         })
     }
 
-    /// get token prices by token address
+    /// price with `policy` instead of the default pricing policy
+    pub fn with_policy(mut self, policy: PricingPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// use `tokens` instead of the shared token cache
+    pub fn with_cache(mut self, tokens: Arc<TokenCache>) -> Self {
+        self.tokens = tokens;
+        self
+    }
+
+    /// search liquid pools among the pairs of `registry` instead of the base token pairs
+    pub fn with_registry(mut self, registry: Arc<PoolRegistry>) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    /// resolve a block into a snapshot every read of a price computation is pinned to,
+    /// defaulting to the latest block. historical blocks require an archive node.
+    pub async fn snapshot(&self, block: Option<BlockId>) -> Result<BlockSnapshot, UniswapError> {
+        let block = self
+            .provider
+            .get_block(block_or_latest(block))
+            .await
+            .map_err(UniswapError::EthersError)?
+            .ok_or_else(|| UniswapError::ContractError("Block not found".to_string()))?;
+        Ok(BlockSnapshot::new(
+            block.number.unwrap_or_default().as_u64(),
+            block.timestamp.as_u64(),
+        ))
+    }
+
+    /// get token prices by token address at a block, defaulting to the latest block
     pub async fn get_token_prices_by_token_address(
         &self,
         token_address: Address,
+        block: Option<BlockId>,
     ) -> Result<TokenPriceInfo, UniswapError> {
-        let token_info = self.get_token_info(token_address).await?;
+        let snapshot = self.snapshot(block).await?;
+        self.get_token_prices_at_snapshot(token_address, &snapshot)
+            .await
+    }
+
+    /// get token prices by token address with every read pinned to the snapshot block
+    pub async fn get_token_prices_at_snapshot(
+        &self,
+        token_address: Address,
+        snapshot: &BlockSnapshot,
+    ) -> Result<TokenPriceInfo, UniswapError> {
+        let token_info = self.get_token_info(token_address, snapshot).await?;
+        let block_number = snapshot.block_number;
+        let block_timestamp = snapshot.block_timestamp;
+        let provenance = |quote_symbol: &str, hops: Vec<PriceHop>| {
+            let mut route = vec![token_address];
+            route.extend(hops.iter().map(|hop| hop.token_out));
+            route.dedup();
+            PriceProvenance {
+                quote_symbol: quote_symbol.to_string(),
+                quote_token: *route.last().unwrap(),
+                price: hops.iter().map(|hop| hop.price).product(),
+                route,
+                hops,
+                block_number,
+                block_timestamp,
+            }
+        };
         let mut price_info = TokenPriceInfo {
             token_address,
             token_symbol: token_info.symbol.clone(),
@@ -47,56 +127,393 @@ impl Price {
             usdc_price: None,
             usdt_price: None,
             dai_price: None,
+            quote_prices: HashMap::new(),
             liquidity: U256::zero(),
             price_source: "Unknown".to_string(),
             last_updated: chrono::Utc::now().timestamp() as u64,
+            policy: self.policy.clone(),
+            provenance: Vec::new(),
         };
+        let weth_info = self
+            .get_token_info(self.finder.weth_address, snapshot)
+            .await?;
+        let eth_usd_hop = self.get_usd_hop(&weth_info, snapshot).await.ok();
         // get eth price
-        if let Ok(eth_price) = self.get_eth_price_with_decimals(&token_info).await {
-            price_info.eth_price = Some(eth_price);
-            price_info.price_source = "Uniswap V2".to_string();
-        } else if let Ok(eth_price) = self.get_eth_price_by_v3(&token_info).await {
-            price_info.eth_price = Some(eth_price);
-            price_info.price_source = "Uniswap V3".to_string();
-        }
-        // get stablecoin prices
-        // usdc
-        if let Ok(usdc_price) = self.get_stablecoin_price(&token_info, "USDC").await {
-            price_info.usdc_price = Some(usdc_price);
-        }
-        // usdt
-        if let Ok(usdt_price) = self.get_stablecoin_price(&token_info, "USDT").await {
-            price_info.usdt_price = Some(usdt_price);
+        let eth_hop = self
+            .get_quote_hop(
+                &token_info,
+                &weth_info,
+                eth_usd_hop.as_ref().map(|hop| hop.price),
+                snapshot,
+            )
+            .await
+            .ok();
+        if let Some(eth_hop) = &eth_hop {
+            price_info.eth_price = Some(eth_hop.price);
+            price_info
+                .quote_prices
+                .insert(weth_info.address, eth_hop.price);
+            price_info
+                .provenance
+                .push(provenance("ETH", vec![eth_hop.clone()]));
         }
-        // dai
-        if let Ok(dai_price) = self.get_stablecoin_price(&token_info, "DAI").await {
-            price_info.dai_price = Some(dai_price);
+        // get quote asset prices
+        let mut quote_hops = Vec::new();
+        for quote_asset in self.finder.quote_assets.iter() {
+            let Ok(quote_info) = self.get_token_info(quote_asset.address, snapshot).await else {
+                continue;
+            };
+            // non pegged intermediates are valued through a USD pegged asset
+            let usd_hop = if quote_asset.intermediate && !quote_asset.usd_pegged {
+                self.get_usd_hop(&quote_info, snapshot).await.ok()
+            } else {
+                None
+            };
+            let quote_usd_price = if quote_asset.usd_pegged {
+                Some(1.0)
+            } else {
+                usd_hop.as_ref().map(|hop| hop.price)
+            };
+            let Ok(hop) = self
+                .get_quote_hop(&token_info, &quote_info, quote_usd_price, snapshot)
+                .await
+            else {
+                continue;
+            };
+            // by address, as a custom quote asset may reuse a stablecoin symbol
+            let address = quote_asset.address;
+            if address == USDC_ADDRESS.parse::<Address>().unwrap() {
+                price_info.usdc_price = Some(hop.price);
+            } else if address == USDT_ADDRESS.parse::<Address>().unwrap() {
+                price_info.usdt_price = Some(hop.price);
+            } else if address == DAI_ADDRESS.parse::<Address>().unwrap() {
+                price_info.dai_price = Some(hop.price);
+            }
+            price_info
+                .quote_prices
+                .insert(quote_asset.address, hop.price);
+            price_info
+                .provenance
+                .push(provenance(&quote_asset.symbol, vec![hop.clone()]));
+            quote_hops.push((quote_asset.clone(), hop, usd_hop));
         }
         // calculate USD price
-        price_info.usd_price = self.calc_usd_price(&price_info).await;
+        if let Some(usd_hops) = self.calc_usd_price(quote_hops, eth_hop, eth_usd_hop) {
+            let usd_provenance = provenance("USD", usd_hops);
+            price_info.usd_price = Some(usd_provenance.price);
+            price_info.price_source = Self::price_source(&usd_provenance.hops);
+            price_info.provenance.push(usd_provenance);
+        } else if let Some(first_provenance) = price_info.provenance.first() {
+            price_info.price_source = Self::price_source(&first_provenance.hops);
+        }
         Ok(price_info)
     }
 
-    /// get eth price from uniswap V2
-    async fn get_eth_price_with_decimals(
+    /// describe the protocol versions of the accepted pools along a route
+    fn price_source(hops: &[PriceHop]) -> String {
+        let accepted = hops
+            .iter()
+            .flat_map(|hop| hop.pools.iter())
+            .filter(|pool| pool.accepted);
+        let has_v2 = accepted.clone().any(|pool| pool.version == PoolVersion::V2);
+        let has_v3 = accepted.clone().any(|pool| pool.version == PoolVersion::V3);
+        match (has_v2, has_v3) {
+            (true, true) => "Uniswap V2+V3".to_string(),
+            (true, false) => "Uniswap V2".to_string(),
+            (false, true) => "Uniswap V3".to_string(),
+            (false, false) => "Unknown".to_string(),
+        }
+    }
+
+    /// a hop from a token to itself
+    fn identity_hop(token: Address) -> PriceHop {
+        PriceHop {
+            token_in: token,
+            token_out: token,
+            price: 1.0,
+            selected_pool: None,
+            pools: Vec::new(),
+        }
+    }
+
+    /// price a token in a quote token from its V2 pair and every V3 fee tier
+    async fn get_quote_hop(
         &self,
         token_info: &TokenInfo,
-    ) -> Result<f64, UniswapError> {
-        let weth_address = str_to_h160_1(WETH_ADDRESS).unwrap();
-        let weth_info = self.get_token_info(weth_address).await.unwrap();
-        let factory = IUniswapV2Factory::new(self.v2_factory, self.provider.clone());
+        quote_info: &TokenInfo,
+        quote_usd_price: Option<f64>,
+        snapshot: &BlockSnapshot,
+    ) -> Result<PriceHop, UniswapError> {
+        if token_info.address == quote_info.address {
+            return Ok(Self::identity_hop(quote_info.address));
+        }
+        let mut pools = Vec::new();
+        if let Ok(pool_price) = self
+            .get_v2_pool_price(token_info, quote_info, quote_usd_price, snapshot)
+            .await
+        {
+            pools.push(pool_price);
+        }
+        pools.extend(
+            self.get_v3_pool_prices(token_info, quote_info, quote_usd_price, snapshot)
+                .await?,
+        );
+        let (price, selected_pool) = self.aggregate(&pools)?;
+        Ok(PriceHop {
+            token_in: token_info.address,
+            token_out: quote_info.address,
+            price,
+            selected_pool: Some(selected_pool),
+            pools,
+        })
+    }
+
+    /// price a token in a quote token from their V2 pair
+    async fn get_v2_pool_price(
+        &self,
+        token_info: &TokenInfo,
+        quote_info: &TokenInfo,
+        quote_usd_price: Option<f64>,
+        snapshot: &BlockSnapshot,
+    ) -> Result<PoolPrice, UniswapError> {
+        let factory = IUniswapV2Factory::new(self.finder.factory_v2, self.provider.clone());
         let pair_address = factory
-            .get_pair(token_info.address, weth_address)
+            .get_pair(token_info.address, quote_info.address)
+            .block(snapshot.block_id())
             .call()
             .await
             .map_err(|e| UniswapError::ContractError(format!("Factory error: {}", e)))?;
         if pair_address == Address::zero() {
             return Err(UniswapError::PoolNotFound);
         }
-        let uniswap_v2 = UniswapV2::new_with_provider(self.provider.clone());
-        let pool_info = uniswap_v2.get_pool_info(pair_address).await?;
-        let price = self.calc_price(&pool_info, token_info, &weth_info)?;
-        Ok(price)
+        let uniswap_v2 = UniswapV2::new_with_cache(self.provider.clone(), self.tokens.clone());
+        let pool_info = uniswap_v2
+            .get_pool_info(pair_address, snapshot.block())
+            .await?;
+        let spot_price = self.calc_price(&pool_info, token_info, quote_info)?;
+        let quote_reserve = if pool_info.token0.address == quote_info.address {
+            pool_info.reserve0
+        } else {
+            pool_info.reserve1
+        };
+        let twap_price = if self.policy.needs_twap() {
+            self.get_v2_twap(&uniswap_v2, &pool_info, token_info, snapshot)
+                .await
+                .ok()
+        } else {
+            None
+        };
+        let mut pool_price = PoolPrice {
+            pool: pair_address,
+            version: PoolVersion::V2,
+            fee: None,
+            token0: pool_info.token0.address,
+            token1: pool_info.token1.address,
+            reserve0: Some(pool_info.reserve0),
+            reserve1: Some(pool_info.reserve1),
+            liquidity: pool_info.liquidity,
+            sqrt_price_x96: None,
+            spot_price,
+            twap_price,
+            price: spot_price,
+            quote_liquidity: self.to_decimal(quote_reserve, quote_info.decimals),
+            liquidity_usd: None,
+            accepted: false,
+            reject_reason: None,
+        };
+        self.apply_policy(&mut pool_price, quote_usd_price);
+        Ok(pool_price)
+    }
+
+    /// V2 TWAP of a token over the policy window ending at the snapshot block, in the other token of the pair
+    async fn get_v2_twap(
+        &self,
+        uniswap_v2: &UniswapV2,
+        pool_info: &PoolInfo,
+        token_info: &TokenInfo,
+        snapshot: &BlockSnapshot,
+    ) -> Result<f64, UniswapError> {
+        let end_block = snapshot.block_number;
+        let window_blocks = (self.policy.twap_window as u64).div_ceil(AVERAGE_BLOCK_TIME);
+        let start_block = end_block.saturating_sub(window_blocks.max(1));
+        let twap = uniswap_v2
+            .get_twap_between_blocks(pool_info.address, start_block, end_block)
+            .await?;
+        if pool_info.token0.address == token_info.address {
+            Ok(twap.price0)
+        } else {
+            Ok(twap.price1)
+        }
+    }
+
+    /// price a token in a quote token from every V3 fee tier
+    async fn get_v3_pool_prices(
+        &self,
+        token_info: &TokenInfo,
+        quote_info: &TokenInfo,
+        quote_usd_price: Option<f64>,
+        snapshot: &BlockSnapshot,
+    ) -> Result<Vec<PoolPrice>, UniswapError> {
+        let mut pools = Vec::new();
+        let factory = IUniswapV3Factory::new(self.finder.factory_v3, self.provider.clone());
+        let uniswap_v3 = UniswapV3::new_with_cache(self.provider.clone(), self.tokens.clone());
+        let quote_contract = IERC20::new(quote_info.address, self.provider.clone());
+        for fee in V3_FEE_TIERS.iter() {
+            let pool_address = factory
+                .get_pool(token_info.address, quote_info.address, *fee)
+                .block(snapshot.block_id())
+                .call()
+                .await
+                .map_err(|e| UniswapError::ContractError(format!("V3 Factory error: {}", e)))?;
+            if pool_address == Address::zero() {
+                continue;
+            }
+            let Ok((sqrt_price_x96, _, liquidity)) = uniswap_v3
+                .get_pool_state(pool_address, snapshot.block())
+                .await
+            else {
+                continue;
+            };
+            let (token0, token1) = if token_info.address < quote_info.address {
+                (token_info, quote_info)
+            } else {
+                (quote_info, token_info)
+            };
+            let price0 = sqrt_price_x96_to_price(sqrt_price_x96, token0.decimals, token1.decimals);
+            let spot_price = if token0.address == token_info.address {
+                price0
+            } else {
+                1.0 / price0
+            };
+            let Ok(quote_balance) = quote_contract
+                .balance_of(pool_address)
+                .block(snapshot.block_id())
+                .call()
+                .await
+            else {
+                continue;
+            };
+            let twap_price = if self.policy.needs_twap() {
+                uniswap_v3
+                    .consult(pool_address, self.policy.twap_window, snapshot.block())
+                    .await
+                    .ok()
+                    .map(|twap| {
+                        if token_info.address < quote_info.address {
+                            twap.price0
+                        } else {
+                            twap.price1
+                        }
+                    })
+            } else {
+                None
+            };
+            let mut pool_price = PoolPrice {
+                pool: pool_address,
+                version: PoolVersion::V3,
+                fee: Some(*fee),
+                token0: token0.address,
+                token1: token1.address,
+                reserve0: None,
+                reserve1: None,
+                liquidity: liquidity.into(),
+                sqrt_price_x96: Some(sqrt_price_x96),
+                spot_price,
+                twap_price,
+                price: spot_price,
+                quote_liquidity: self.to_decimal(quote_balance, quote_info.decimals),
+                liquidity_usd: None,
+                accepted: false,
+                reject_reason: None,
+            };
+            self.apply_policy(&mut pool_price, quote_usd_price);
+            pools.push(pool_price);
+        }
+        Ok(pools)
+    }
+
+    /// evaluate a pool against the pricing policy, setting the reported price or the reject reason
+    fn apply_policy(&self, pool_price: &mut PoolPrice, quote_usd_price: Option<f64>) {
+        // both sides of a pool hold roughly equal value
+        pool_price.liquidity_usd =
+            quote_usd_price.map(|usd| pool_price.quote_liquidity * usd * 2.0);
+        let reject_reason = if !pool_price.spot_price.is_finite() || pool_price.spot_price <= 0.0 {
+            Some("No spot price".to_string())
+        } else if self.policy.min_liquidity_usd > 0.0
+            && pool_price.liquidity_usd.unwrap_or(0.0) < self.policy.min_liquidity_usd
+        {
+            Some(format!(
+                "Liquidity {:.2} USD below minimum {:.2} USD",
+                pool_price.liquidity_usd.unwrap_or(0.0),
+                self.policy.min_liquidity_usd
+            ))
+        } else if self.policy.needs_twap() && pool_price.twap_price.is_none() {
+            Some("TWAP unavailable".to_string())
+        } else {
+            None
+        };
+        let reject_reason = reject_reason.or_else(|| {
+            let max_deviation = self.policy.max_twap_deviation?;
+            let twap_price = pool_price.twap_price?;
+            let deviation = (pool_price.spot_price - twap_price).abs() / twap_price * 100.0;
+            (deviation > max_deviation).then(|| {
+                format!(
+                    "Spot deviates {:.2}% from TWAP, maximum {:.2}%",
+                    deviation, max_deviation
+                )
+            })
+        });
+        if self.policy.mode == PriceMode::Twap
+            && let Some(twap_price) = pool_price.twap_price
+        {
+            pool_price.price = twap_price;
+        }
+        pool_price.accepted = reject_reason.is_none();
+        pool_price.reject_reason = reject_reason;
+    }
+
+    /// combine the accepted pools as configured by the policy, returns the price and the pool it came from.
+    /// only pools with a USD liquidity estimate are weighted, as quote balances in different assets
+    /// are not comparable. without any estimate the plain median of the accepted pools is used.
+    fn aggregate(&self, pools: &[PoolPrice]) -> Result<(f64, Address), UniswapError> {
+        let accepted: Vec<&PoolPrice> = pools.iter().filter(|pool| pool.accepted).collect();
+        if accepted.is_empty() {
+            return Err(UniswapError::PoolNotFound);
+        }
+        let mut weighted: Vec<(f64, f64, Address)> = accepted
+            .iter()
+            .filter_map(|pool| {
+                pool.liquidity_usd
+                    .map(|liquidity_usd| (pool.price, liquidity_usd, pool.pool))
+            })
+            .collect();
+        if weighted.is_empty() {
+            let mut prices: Vec<(f64, Address)> = accepted
+                .iter()
+                .map(|pool| (pool.price, pool.pool))
+                .collect();
+            prices.sort_by(|a, b| a.0.total_cmp(&b.0));
+            return Ok(prices[prices.len() / 2]);
+        }
+        if self.policy.aggregation == PriceAggregation::DeepestPool {
+            let (price, _, pool) = weighted.iter().max_by(|a, b| a.1.total_cmp(&b.1)).unwrap();
+            return Ok((*price, *pool));
+        }
+        weighted.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let total_weight: f64 = weighted.iter().map(|(_, weight, _)| weight).sum();
+        if total_weight <= 0.0 {
+            let (price, _, pool) = weighted[weighted.len() / 2];
+            return Ok((price, pool));
+        }
+        let mut cumulative = 0.0;
+        for (price, weight, pool) in weighted.iter() {
+            cumulative += weight;
+            if cumulative >= total_weight / 2.0 {
+                return Ok((*price, *pool));
+            }
+        }
+        let (price, _, pool) = weighted[weighted.len() - 1];
+        Ok((price, pool))
     }
 
     /// calculate price
@@ -147,124 +564,76 @@ impl Price {
         }
     }
 
-    /// get het price by uniswap v3
-    async fn get_eth_price_by_v3(&self, token_info: &TokenInfo) -> Result<f64, UniswapError> {
-        let weth_address = str_to_h160_1(WETH_ADDRESS).unwrap();
-        let weth_info = self.get_token_info(weth_address).await?;
-        let fees = [500, 3000, 10000];
-        for fee in fees.iter() {
-            let factory = IUniswapV3Factory::new(self.v3_factory, self.provider.clone());
-            let pool_address = factory
-                .get_pool(token_info.address, weth_address, *fee)
-                .call()
-                .await
-                .map_err(|e| UniswapError::ContractError(format!("V3 Factory error: {}", e)))?;
-
-            if pool_address != Address::zero() {
-                let uniswap_v3 = UniswapV3::new_with_provider(self.provider.clone());
-                if let Ok(price_data) = uniswap_v3
-                    .get_price_by_token0_token1(pool_address, token_info, &weth_info)
-                    .await
-                {
-                    return Ok(price_data);
-                }
-            }
-        }
-
-        Err(UniswapError::PoolNotFound)
-    }
-
-    /// get stable coin price
-    async fn get_stablecoin_price(
+    /// calculate USD price, returns the hops of the route used
+    fn calc_usd_price(
         &self,
-        token_info: &TokenInfo,
-        stablecoin: &str,
-    ) -> Result<f64, UniswapError> {
-        let stablecoin_address: Address;
-        match stablecoin {
-            "USDC" => stablecoin_address = str_to_h160_1(USDC_ADDRESS).unwrap(),
-            "USDT" => stablecoin_address = str_to_h160_1(USDT_ADDRESS).unwrap(),
-            "DAI" => stablecoin_address = str_to_h160_1(DAI_ADDRESS).unwrap(),
-            _ => stablecoin_address = str_to_h160_1(USDT_ADDRESS).unwrap(),
-        }
-        if token_info.address == stablecoin_address {
-            return Ok(1.0);
+        quote_hops: Vec<(QuoteAsset, PriceHop, Option<PriceHop>)>,
+        eth_hop: Option<PriceHop>,
+        eth_usd_hop: Option<PriceHop>,
+    ) -> Option<Vec<PriceHop>> {
+        // a direct pair with a USD pegged asset, in configured order
+        if let Some((_, hop, _)) = quote_hops
+            .iter()
+            .find(|(quote_asset, _, _)| quote_asset.usd_pegged)
+        {
+            return Some(vec![hop.clone()]);
         }
-        let stablecoin_info = self.get_token_info(stablecoin_address).await.unwrap();
-        let factory = IUniswapV2Factory::new(self.v2_factory, self.provider.clone());
-        let pair_address = factory
-            .get_pair(token_info.address, stablecoin_address)
-            .call()
-            .await
-            .map_err(|e| UniswapError::ContractError(format!("Factory error: {}", e)))?;
-        if pair_address == Address::zero() {
-            return Err(UniswapError::PoolNotFound);
+        // through the wrapped native token
+        if let (Some(eth_hop), Some(eth_usd_hop)) = (eth_hop, eth_usd_hop) {
+            return Some(vec![eth_hop, eth_usd_hop]);
         }
-        let uniswap_v2 = UniswapV2::new_with_provider(self.provider.clone());
-        let pool_info = uniswap_v2.get_pool_info(pair_address).await?;
-        let price = self.calc_price(&pool_info, token_info, &stablecoin_info)?;
-        Ok(price)
+        // through another intermediate
+        quote_hops
+            .into_iter()
+            .find_map(|(_, hop, usd_hop)| usd_hop.map(|usd_hop| vec![hop, usd_hop]))
     }
 
-    /// calculate USD price
-    async fn calc_usd_price(&self, price_info: &TokenPriceInfo) -> Option<f64> {
-        // usdc
-        if let Some(usdc_price) = price_info.usdc_price {
-            return Some(usdc_price);
-        }
-        // usdt
-        if let Some(usdt_price) = price_info.usdt_price {
-            return Some(usdt_price);
-        }
-        //dai
-        if let Some(dai_price) = price_info.dai_price {
-            return Some(dai_price);
+    /// price a token in the first USD pegged quote asset it has a pool with
+    async fn get_usd_hop(
+        &self,
+        token_info: &TokenInfo,
+        snapshot: &BlockSnapshot,
+    ) -> Result<PriceHop, UniswapError> {
+        if let Some(quote_asset) = self.finder.quote_asset(token_info.address)
+            && quote_asset.usd_pegged
+        {
+            return Ok(Self::identity_hop(token_info.address));
         }
-        if let Some(eth_price) = price_info.eth_price {
-            if let Ok(eth_usd_price) = self.get_eth_usd_price().await {
-                return Some(eth_price * eth_usd_price);
+        for quote_asset in self.finder.usd_pegged() {
+            let Ok(quote_info) = self.get_token_info(quote_asset.address, snapshot).await else {
+                continue;
+            };
+            if let Ok(hop) = self
+                .get_quote_hop(token_info, &quote_info, Some(1.0), snapshot)
+                .await
+            {
+                return Ok(hop);
             }
         }
-        None
-    }
-
-    /// get ETH/USD price
-    async fn get_eth_usd_price(&self) -> Result<f64, UniswapError> {
-        let weth_address = str_to_h160_1(WETH_ADDRESS).unwrap();
-        let usdc_address = str_to_h160_1(USDC_ADDRESS).unwrap();
-        let weth_info = self.get_token_info(weth_address).await.unwrap();
-        let usdc_info = self.get_token_info(usdc_address).await.unwrap();
-        let factory = IUniswapV2Factory::new(self.v2_factory, self.provider.clone());
-        let pair_address = factory
-            .get_pair(weth_address, usdc_address)
-            .call()
-            .await
-            .map_err(|e| UniswapError::ContractError(format!("Factory error: {}", e)))?;
-        if pair_address == Address::zero() {
-            return Err(UniswapError::PoolNotFound);
-        }
-        let uniswap_v2 = UniswapV2::new_with_provider(self.provider.clone());
-        let pool_info = uniswap_v2.get_pool_info(pair_address).await?;
-        // 1 WETH price
-        let price = self.calc_price(&pool_info, &weth_info, &usdc_info)?;
-        Ok(price)
+        Err(UniswapError::PoolNotFound)
     }
 
     /// get token info
-    async fn get_token_info(&self, address: Address) -> Result<TokenInfo, UniswapError> {
-        let uniswap_v2 = UniswapV2::new_with_provider(self.provider.clone());
-        uniswap_v2.get_token_info(address).await
+    async fn get_token_info(
+        &self,
+        address: Address,
+        snapshot: &BlockSnapshot,
+    ) -> Result<TokenInfo, UniswapError> {
+        self.tokens
+            .get_token_info(self.provider.as_ref(), address, snapshot.block())
+            .await
     }
 
-    /// get multiple prices by token addres
+    /// get multiple prices by token addres, all tokens are priced at the same block
     pub async fn get_multiple_prices_by_token_address_vec(
         &self,
         token_addresses: Vec<Address>,
+        block: Option<BlockId>,
     ) -> Result<Vec<TokenPriceInfo>, UniswapError> {
         let mut results = Vec::new();
-
+        let snapshot = self.snapshot(block).await?;
         for address in token_addresses {
-            match self.get_token_prices_by_token_address(address).await {
+            match self.get_token_prices_at_snapshot(address, &snapshot).await {
                 Ok(price_info) => results.push(price_info),
                 Err(e) => {
                     eprintln!("Failed to get price for {:?}: {}", address, e);
@@ -275,50 +644,96 @@ impl Price {
         Ok(results)
     }
 
-    /// search for liquid trading pairs
-    pub async fn search_liquid_pools(
+    /// V2 pairs of a token, every registered pair when a registry is set, otherwise the
+    /// pairs with the base tokens
+    async fn get_v2_pairs(
         &self,
         token_address: Address,
-    ) -> Result<Vec<PoolInfo>, UniswapError> {
-        let mut pools = Vec::new();
-        let token_info = self.get_token_info(token_address).await?;
-        // check trading pairs with major coins
-        let base_tokens = vec![
-            str_to_h160_1(WETH_ADDRESS).unwrap(),
-            str_to_h160_1(USDC_ADDRESS).unwrap(),
-            str_to_h160_1(USDT_ADDRESS).unwrap(),
-            str_to_h160_1(DAI_ADDRESS).unwrap(),
-        ];
-
+        snapshot: &BlockSnapshot,
+    ) -> Result<Vec<Address>, UniswapError> {
+        if let Some(registry) = &self.registry {
+            return Ok(registry
+                .pools_for_token(token_address)
+                .into_iter()
+                .filter(|pool| pool.version == PoolVersion::V2)
+                .map(|pool| pool.address)
+                .collect());
+        }
+        let mut base_tokens = vec![self.finder.weth_address];
+        base_tokens.extend(
+            self.finder
+                .quote_assets
+                .iter()
+                .map(|quote_asset| quote_asset.address),
+        );
+        let factory = IUniswapV2Factory::new(self.finder.factory_v2, self.provider.clone());
+        let mut pairs = Vec::new();
         for base_token in base_tokens {
-            let factory = IUniswapV2Factory::new(self.v2_factory, self.provider.clone());
             let pair_address = factory
                 .get_pair(token_address, base_token)
+                .block(snapshot.block_id())
                 .call()
                 .await
                 .map_err(|e| UniswapError::ContractError(format!("Factory error: {}", e)))?;
             if pair_address != Address::zero() {
-                let uniswap_v2 = UniswapV2::new_with_provider(self.provider.clone());
-                if let Ok(pool_info) = uniswap_v2.get_pool_info(pair_address).await {
-                    // filter data whose liquidity pool is not zero.
-                    if !pool_info.reserve0.is_zero() && !pool_info.reserve1.is_zero() {
-                        pools.push(pool_info);
-                    }
+                pairs.push(pair_address);
+            }
+        }
+        Ok(pairs)
+    }
+
+    /// search for liquid trading pairs, sorted by market depth
+    pub async fn search_liquid_pools(
+        &self,
+        token_address: Address,
+        block: Option<BlockId>,
+    ) -> Result<Vec<PoolInfo>, UniswapError> {
+        let mut pools = Vec::new();
+        let snapshot = self.snapshot(block).await?;
+        self.get_token_info(token_address, &snapshot).await?;
+        let uniswap_v2 = UniswapV2::new_with_cache(self.provider.clone(), self.tokens.clone());
+        for pair_address in self.get_v2_pairs(token_address, &snapshot).await? {
+            if let Ok(pool_info) = uniswap_v2
+                .get_pool_info(pair_address, snapshot.block())
+                .await
+            {
+                // filter data whose liquidity pool is not zero.
+                if !pool_info.reserve0.is_zero() && !pool_info.reserve1.is_zero() {
+                    pools.push(pool_info);
                 }
             }
         }
+        // deepest first, by the amount of the token that can be sold before its price moves 1%
+        let depth = |pool_info: &PoolInfo| {
+            amounts_to_price_impact(
+                &PoolLiquidity::V2(pool_info.clone()),
+                pool_info.token0.address == token_address,
+                1.0,
+            )
+            .map(|(amount_in, _)| amount_in)
+            .unwrap_or(0.0)
+        };
+        pools.sort_by(|a, b| depth(b).total_cmp(&depth(a)));
         Ok(pools)
     }
 }
 
 impl UniswapV2 {
     pub fn new_with_provider(provider: Arc<Provider<Http>>) -> Self {
-        Self { provider }
+        Self::new_with_cache(provider, TokenCache::shared())
+    }
+
+    pub fn new_with_cache(provider: Arc<Provider<Http>>, tokens: Arc<TokenCache>) -> Self {
+        Self { provider, tokens }
     }
 }
 
 impl UniswapV3 {
     pub fn new_with_provider(provider: Arc<Provider<Http>>) -> Self {
-        Self { provider }
+        Self::new_with_cache(provider, TokenCache::shared())
+    }
+
+    pub fn new_with_cache(provider: Arc<Provider<Http>>, tokens: Arc<TokenCache>) -> Self {
+        Self { provider, tokens }
     }
 }
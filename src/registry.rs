@@ -0,0 +1,312 @@
+use ethers::{contract::parse_log, prelude::*, types::Filter};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, path::Path, sync::Arc};
+use tokio::task::JoinSet;
+
+use crate::{
+    abi::{IUniswapV2Factory, IUniswapV2Pair, PairCreatedFilter, PoolCreatedFilter},
+    events::get_logs_in_chunks,
+    global::mainnet::{
+        MAINNET_UNISWAP_V2_FACTORY_ADDRESS, MAINNET_UNISWAP_V2_FACTORY_BLOCK,
+        MAINNET_UNISWAP_V3_FACTORY_ADDRESS, MAINNET_UNISWAP_V3_FACTORY_BLOCK,
+    },
+    math::v2,
+    tool::fs::write_atomic,
+    types::{PoolVersion, RegisteredPool, UniswapError},
+};
+
+/// blocks indexed before the registry's progress is advanced
+const INDEX_WINDOW_BLOCKS: u64 = 50_000;
+/// V2 pairs read concurrently before the enumeration progress is advanced
+const ENUMERATION_BATCH: u64 = 50;
+
+/// local registry of the V2 pairs and V3 pools created by a pair of factories
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolRegistry {
+    pub factory_v2: Address,
+    pub factory_v3: Address,
+    pub v2_start_block: u64,
+    pub v3_start_block: u64,
+    pub last_indexed_block: Option<u64>,
+    pub v2_pairs_enumerated: u64,
+    pools: HashMap<Address, RegisteredPool>,
+    #[serde(skip)]
+    by_token: HashMap<Address, Vec<Address>>,
+}
+
+impl PoolRegistry {
+    pub fn new(
+        factory_v2: Address,
+        v2_start_block: u64,
+        factory_v3: Address,
+        v3_start_block: u64,
+    ) -> Self {
+        Self {
+            factory_v2,
+            factory_v3,
+            v2_start_block,
+            v3_start_block,
+            last_indexed_block: None,
+            v2_pairs_enumerated: 0,
+            pools: HashMap::new(),
+            by_token: HashMap::new(),
+        }
+    }
+
+    /// empty registry of the mainnet factories
+    pub fn mainnet() -> Self {
+        Self::new(
+            MAINNET_UNISWAP_V2_FACTORY_ADDRESS.parse().unwrap(),
+            MAINNET_UNISWAP_V2_FACTORY_BLOCK,
+            MAINNET_UNISWAP_V3_FACTORY_ADDRESS.parse().unwrap(),
+            MAINNET_UNISWAP_V3_FACTORY_BLOCK,
+        )
+    }
+
+    /// add a pool, returns false if it was already known. a known pool is updated when the
+    /// new entry carries its creation block.
+    pub fn insert(&mut self, pool: RegisteredPool) -> bool {
+        if let Some(known) = self.pools.get_mut(&pool.address) {
+            if known.created_block.is_none() && pool.created_block.is_some() {
+                *known = pool;
+            }
+            return false;
+        }
+        for token in [pool.token0, pool.token1] {
+            self.by_token.entry(token).or_default().push(pool.address);
+        }
+        self.pools.insert(pool.address, pool);
+        true
+    }
+
+    /// get a pool by address
+    pub fn get(&self, address: Address) -> Option<&RegisteredPool> {
+        self.pools.get(&address)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pools.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pools.is_empty()
+    }
+
+    /// all known pools
+    pub fn pools(&self) -> impl Iterator<Item = &RegisteredPool> {
+        self.pools.values()
+    }
+
+    /// pools holding a token
+    pub fn pools_for_token(&self, token: Address) -> Vec<&RegisteredPool> {
+        self.by_token
+            .get(&token)
+            .map(|addresses| {
+                addresses
+                    .iter()
+                    .filter_map(|address| self.pools.get(address))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// pools of a pair, in any token order
+    pub fn pools_for_pair(&self, token_a: Address, token_b: Address) -> Vec<&RegisteredPool> {
+        self.pools_for_token(token_a)
+            .into_iter()
+            .filter(|pool| pool.token0 == token_b || pool.token1 == token_b)
+            .collect()
+    }
+
+    pub(crate) fn rebuild_index(&mut self) {
+        self.by_token.clear();
+        for pool in self.pools.values() {
+            for token in [pool.token0, pool.token1] {
+                self.by_token.entry(token).or_default().push(pool.address);
+            }
+        }
+    }
+
+    /// write the registry to a JSON file, replacing it atomically
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), UniswapError> {
+        let json = serde_json::to_vec(self)
+            .map_err(|e| UniswapError::StorageError(format!("Serialize error: {}", e)))?;
+        write_atomic(path, &json)
+    }
+
+    /// read a registry written by `save`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, UniswapError> {
+        let json =
+            fs::read(path).map_err(|e| UniswapError::StorageError(format!("Read error: {}", e)))?;
+        let mut registry: Self = serde_json::from_slice(&json)
+            .map_err(|e| UniswapError::StorageError(format!("Deserialize error: {}", e)))?;
+        registry.rebuild_index();
+        Ok(registry)
+    }
+}
+
+/// fills a pool registry from factory enumeration and creation events
+pub struct PoolDiscovery {
+    pub provider: Arc<Provider<Http>>,
+}
+
+impl PoolDiscovery {
+    pub fn new(provider: Arc<Provider<Http>>) -> Self {
+        Self { provider }
+    }
+
+    /// decode a PairCreated or PoolCreated log
+    pub fn decode_creation_log(log: &Log) -> Option<RegisteredPool> {
+        let created_block = log.block_number.map(|number| number.as_u64());
+        if let Ok(event) = parse_log::<PairCreatedFilter>(log.clone()) {
+            return Some(RegisteredPool {
+                address: event.pair,
+                version: PoolVersion::V2,
+                token0: event.token_0,
+                token1: event.token_1,
+                fee: v2::FEE,
+                tick_spacing: None,
+                created_block,
+            });
+        }
+        let event = parse_log::<PoolCreatedFilter>(log.clone()).ok()?;
+        Some(RegisteredPool {
+            address: event.pool,
+            version: PoolVersion::V3,
+            token0: event.token_0,
+            token1: event.token_1,
+            fee: event.fee,
+            tick_spacing: Some(event.tick_spacing),
+            created_block,
+        })
+    }
+
+    /// read the V2 pair at `index` of the factory's allPairs list
+    async fn read_v2_pair(
+        provider: Arc<Provider<Http>>,
+        factory: Address,
+        index: u64,
+    ) -> Result<RegisteredPool, UniswapError> {
+        let pair_address = IUniswapV2Factory::new(factory, provider.clone())
+            .all_pairs(index.into())
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("All pairs error: {}", e)))?;
+        let pair_contract = IUniswapV2Pair::new(pair_address, provider);
+        let token0 = pair_contract
+            .token_0()
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("Token0 error: {}", e)))?;
+        let token1 = pair_contract
+            .token_1()
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("Token1 error: {}", e)))?;
+        Ok(RegisteredPool {
+            address: pair_address,
+            version: PoolVersion::V2,
+            token0,
+            token1,
+            fee: v2::FEE,
+            tick_spacing: None,
+            created_block: None,
+        })
+    }
+
+    /// enumerate V2 pairs with allPairs, continuing from where the last enumeration stopped.
+    /// pairs are read in concurrent batches and progress is advanced batch by batch.
+    /// at most `limit` pairs are read, returns the number of new pools.
+    pub async fn enumerate_v2_pairs(
+        &self,
+        registry: &mut PoolRegistry,
+        limit: Option<u64>,
+    ) -> Result<usize, UniswapError> {
+        let factory = IUniswapV2Factory::new(registry.factory_v2, self.provider.clone());
+        let length = factory
+            .all_pairs_length()
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("All pairs length error: {}", e)))?
+            .as_u64();
+        let end = match limit {
+            Some(limit) => length.min(registry.v2_pairs_enumerated + limit),
+            None => length,
+        };
+        let mut added = 0;
+        while registry.v2_pairs_enumerated < end {
+            let batch_end = (registry.v2_pairs_enumerated + ENUMERATION_BATCH).min(end);
+            let mut reads = JoinSet::new();
+            for index in registry.v2_pairs_enumerated..batch_end {
+                reads.spawn(Self::read_v2_pair(
+                    self.provider.clone(),
+                    registry.factory_v2,
+                    index,
+                ));
+            }
+            let mut pools = Vec::new();
+            while let Some(read) = reads.join_next().await {
+                let pool = read.map_err(|e| {
+                    UniswapError::ContractError(format!("All pairs error: {}", e))
+                })??;
+                pools.push(pool);
+            }
+            for pool in pools {
+                if registry.insert(pool) {
+                    added += 1;
+                }
+            }
+            registry.v2_pairs_enumerated = batch_end;
+        }
+        Ok(added)
+    }
+
+    /// index PairCreated and PoolCreated events from the last indexed block, or the factory
+    /// deployments, up to `to_block`. progress is advanced window by window, so a registry
+    /// saved after a failure resumes where it stopped. returns the number of new pools.
+    pub async fn index_events(
+        &self,
+        registry: &mut PoolRegistry,
+        to_block: u64,
+    ) -> Result<usize, UniswapError> {
+        let filter = Filter::new()
+            .address(vec![registry.factory_v2, registry.factory_v3])
+            .topic0(vec![
+                PairCreatedFilter::signature(),
+                PoolCreatedFilter::signature(),
+            ]);
+        let mut start = registry
+            .last_indexed_block
+            .map(|block| block + 1)
+            .unwrap_or_else(|| registry.v2_start_block.min(registry.v3_start_block));
+        let mut added = 0;
+        while start <= to_block {
+            let end = (start + INDEX_WINDOW_BLOCKS - 1).min(to_block);
+            let logs = get_logs_in_chunks(self.provider.as_ref(), &filter, start, end).await?;
+            for log in logs.iter() {
+                let from_factory =
+                    log.address == registry.factory_v2 || log.address == registry.factory_v3;
+                if let Some(pool) = Self::decode_creation_log(log)
+                    && from_factory
+                    && registry.insert(pool)
+                {
+                    added += 1;
+                }
+            }
+            registry.last_indexed_block = Some(end);
+            start = end + 1;
+        }
+        Ok(added)
+    }
+
+    /// index creation events up to the latest block
+    pub async fn sync(&self, registry: &mut PoolRegistry) -> Result<usize, UniswapError> {
+        let latest = self
+            .provider
+            .get_block_number()
+            .await
+            .map_err(UniswapError::EthersError)?
+            .as_u64();
+        self.index_events(registry, latest).await
+    }
+}
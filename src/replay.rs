@@ -0,0 +1,295 @@
+use ethers::providers::{Http, Provider, RpcError};
+use serde_json::{Value, json};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    future::Future,
+    net::SocketAddr,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+
+use crate::{
+    tool::fs::write_atomic,
+    types::{RpcErrorResponse, RpcRecord, UniswapError},
+};
+
+/// JSON-RPC error code of a request with no recorded or mocked response
+pub const RPC_NOT_FOUND_CODE: i64 = -32601;
+/// JSON-RPC error code of a failed upstream request
+pub const RPC_INTERNAL_CODE: i64 = -32603;
+
+/// answers JSON-RPC requests for an `RpcServer`
+pub trait RpcHandler: Send + Sync + 'static {
+    fn handle(
+        &self,
+        method: String,
+        params: Value,
+    ) -> impl Future<Output = Result<Value, RpcErrorResponse>> + Send;
+}
+
+/// local JSON-RPC over HTTP endpoint, any provider pointed at its url is served by the handler.
+/// the server stops when dropped.
+pub struct RpcServer {
+    pub url: String,
+    task: JoinHandle<()>,
+}
+
+impl RpcServer {
+    /// listen on a free localhost port
+    pub async fn start<H: RpcHandler>(handler: Arc<H>) -> Result<Self, UniswapError> {
+        let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .await
+            .map_err(|e| UniswapError::ProviderError(format!("Bind error: {}", e)))?;
+        let address = listener
+            .local_addr()
+            .map_err(|e| UniswapError::ProviderError(format!("Bind error: {}", e)))?;
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve_connection(stream, handler.clone()));
+            }
+        });
+        Ok(Self {
+            url: format!("http://{}", address),
+            task,
+        })
+    }
+
+    /// a provider connected to the server
+    pub fn provider(&self) -> Result<Arc<Provider<Http>>, UniswapError> {
+        let provider = Provider::<Http>::try_from(self.url.as_str())
+            .map_err(|e| UniswapError::ProviderError(format!("{:?}", e)))?;
+        Ok(Arc::new(provider))
+    }
+}
+
+impl Drop for RpcServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// read HTTP requests from a keep-alive connection until it closes
+async fn serve_connection<H: RpcHandler>(mut stream: TcpStream, handler: Arc<H>) {
+    let mut buffer = Vec::new();
+    loop {
+        let Some(body) = read_request(&mut stream, &mut buffer).await else {
+            return;
+        };
+        let response = match serde_json::from_slice::<Value>(&body) {
+            Ok(Value::Array(requests)) => {
+                let mut responses = Vec::new();
+                for request in requests {
+                    responses.push(handle_request(handler.as_ref(), request).await);
+                }
+                Value::Array(responses)
+            }
+            Ok(request) => handle_request(handler.as_ref(), request).await,
+            Err(e) => json!({
+                "jsonrpc": "2.0",
+                "id": null,
+                "error": { "code": -32700, "message": format!("Parse error: {}", e) },
+            }),
+        };
+        let body = response.to_string();
+        let head = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+            body.len()
+        );
+        if stream.write_all(head.as_bytes()).await.is_err()
+            || stream.write_all(body.as_bytes()).await.is_err()
+        {
+            return;
+        }
+    }
+}
+
+/// read one HTTP request body, `buffer` keeps bytes of the next pipelined request
+async fn read_request(stream: &mut TcpStream, buffer: &mut Vec<u8>) -> Option<Vec<u8>> {
+    let mut chunk = [0u8; 8192];
+    let header_end = loop {
+        if let Some(position) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break position + 4;
+        }
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    };
+    let head = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+    let content_length = head
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    while buffer.len() < header_end + content_length {
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+    let body = buffer[header_end..header_end + content_length].to_vec();
+    buffer.drain(..header_end + content_length);
+    Some(body)
+}
+
+async fn handle_request<H: RpcHandler>(handler: &H, request: Value) -> Value {
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let method = request
+        .get("method")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+    let params = request.get("params").cloned().unwrap_or(json!([]));
+    match handler.handle(method, params).await {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+    }
+}
+
+/// replay key of a request, params are compared by value
+fn record_key(method: &str, params: &Value) -> String {
+    format!("{}:{}", method, params)
+}
+
+/// forwards requests to an upstream node and records every response
+pub struct RpcRecorder {
+    pub upstream: Provider<Http>,
+    records: Mutex<Vec<RpcRecord>>,
+}
+
+impl RpcRecorder {
+    pub fn new(upstream_url: &str) -> Result<Self, UniswapError> {
+        let upstream = Provider::<Http>::try_from(upstream_url)
+            .map_err(|e| UniswapError::ProviderError(format!("{:?}", e)))?;
+        Ok(Self {
+            upstream,
+            records: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// the requests recorded so far, in order
+    pub fn records(&self) -> Vec<RpcRecord> {
+        self.records.lock().unwrap().clone()
+    }
+
+    /// write the recorded requests to a fixture file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), UniswapError> {
+        let json = serde_json::to_vec_pretty(&self.records())
+            .map_err(|e| UniswapError::StorageError(format!("Serialize error: {}", e)))?;
+        write_atomic(path, &json)
+    }
+}
+
+impl RpcHandler for RpcRecorder {
+    async fn handle(&self, method: String, params: Value) -> Result<Value, RpcErrorResponse> {
+        let response = self
+            .upstream
+            .request::<Value, Value>(&method, params.clone())
+            .await;
+        let (result, error) = match response {
+            Ok(result) => (Some(result), None),
+            // node errors such as reverts are part of the session, transport errors are not
+            Err(e) => match e.as_error_response() {
+                Some(error) => (
+                    None,
+                    Some(RpcErrorResponse {
+                        code: error.code,
+                        message: error.message.clone(),
+                        data: error.data.clone(),
+                    }),
+                ),
+                None => {
+                    return Err(RpcErrorResponse {
+                        code: RPC_INTERNAL_CODE,
+                        message: format!("Upstream error: {}", e),
+                        data: None,
+                    });
+                }
+            },
+        };
+        self.records.lock().unwrap().push(RpcRecord {
+            method,
+            params,
+            result: result.clone(),
+            error: error.clone(),
+        });
+        match error {
+            Some(error) => Err(error),
+            None => Ok(result.unwrap_or(Value::Null)),
+        }
+    }
+}
+
+/// serves recorded responses. repeated requests get their recorded responses in order,
+/// the last one is repeated once they run out.
+pub struct RpcReplayer {
+    records: Mutex<HashMap<String, VecDeque<RpcRecord>>>,
+}
+
+impl RpcReplayer {
+    pub fn new(records: Vec<RpcRecord>) -> Self {
+        let mut by_key: HashMap<String, VecDeque<RpcRecord>> = HashMap::new();
+        for record in records {
+            by_key
+                .entry(record_key(&record.method, &record.params))
+                .or_default()
+                .push_back(record);
+        }
+        Self {
+            records: Mutex::new(by_key),
+        }
+    }
+
+    /// load a fixture file written by `RpcRecorder::save`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, UniswapError> {
+        let json =
+            fs::read(path).map_err(|e| UniswapError::StorageError(format!("Read error: {}", e)))?;
+        let records: Vec<RpcRecord> = serde_json::from_slice(&json)
+            .map_err(|e| UniswapError::StorageError(format!("Deserialize error: {}", e)))?;
+        Ok(Self::new(records))
+    }
+
+    /// start a server replaying the fixture file
+    pub async fn serve(path: impl AsRef<Path>) -> Result<RpcServer, UniswapError> {
+        RpcServer::start(Arc::new(Self::load(path)?)).await
+    }
+}
+
+impl RpcHandler for RpcReplayer {
+    async fn handle(&self, method: String, params: Value) -> Result<Value, RpcErrorResponse> {
+        let key = record_key(&method, &params);
+        let record = {
+            let mut records = self.records.lock().unwrap();
+            let queue = records.get_mut(&key).ok_or_else(|| RpcErrorResponse {
+                code: RPC_NOT_FOUND_CODE,
+                message: format!("No recorded response for {} {}", method, params),
+                data: None,
+            })?;
+            if queue.len() > 1 {
+                queue.pop_front()
+            } else {
+                queue.front().cloned()
+            }
+        };
+        match record {
+            Some(RpcRecord {
+                error: Some(error), ..
+            }) => Err(error),
+            Some(record) => Ok(record.result.unwrap_or(Value::Null)),
+            None => Err(RpcErrorResponse {
+                code: RPC_NOT_FOUND_CODE,
+                message: format!("No recorded response for {}", method),
+                data: None,
+            }),
+        }
+    }
+}
@@ -0,0 +1,429 @@
+use ethers::{contract::parse_log, prelude::*, types::Filter, utils::id};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use crate::{
+    abi::{IERC20, IUniswapV2Pair, TransferFilter},
+    events::get_logs_in_chunks,
+    global::mainnet::{MAINNET_TEAM_FINANCE_LOCKER_ADDRESS, MAINNET_UNICRYPT_V2_LOCKER_ADDRESS},
+    price::Price,
+    tax::TaxDetector,
+    token::TokenCache,
+    tool::{block::block_or_latest, num::u256_to_f64},
+    types::{
+        HolderConcentration, LiquidityLock, PoolInfo, PriceFinder, ProxyInfo, TokenCapabilities,
+        TokenRisk, TokenRiskReport, TokenTax, UniswapError,
+    },
+};
+
+/// address tokens are commonly burned to
+pub const DEAD_ADDRESS: &str = "0x000000000000000000000000000000000000dEaD";
+/// EIP-1967 implementation slot, keccak256("eip1967.proxy.implementation") - 1
+pub const EIP1967_IMPLEMENTATION_SLOT: &str =
+    "0x360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc";
+/// EIP-1967 admin slot, keccak256("eip1967.proxy.admin") - 1
+pub const EIP1967_ADMIN_SLOT: &str =
+    "0xb53127684a568b3173ae13b9f8a6016e243e63b6e8ee1178d6a717850b5d6103";
+/// EIP-1967 beacon slot, keccak256("eip1967.proxy.beacon") - 1
+pub const EIP1967_BEACON_SLOT: &str =
+    "0xa3f0ad74e5423aebfd80d3ef4346578335a9a72aeaee59ff6cb3582b35133d50";
+
+/// functions letting a privileged account create supply
+const MINT_FUNCTIONS: [&str; 4] = [
+    "mint(address,uint256)",
+    "mint(uint256)",
+    "mintTo(address,uint256)",
+    "issue(uint256)",
+];
+/// functions stopping transfers
+const PAUSE_FUNCTIONS: [&str; 3] = ["pause()", "setPaused(bool)", "setTradingEnabled(bool)"];
+/// functions blocking single accounts
+const BLACKLIST_FUNCTIONS: [&str; 8] = [
+    "blacklist(address)",
+    "addToBlacklist(address)",
+    "addBlacklist(address)",
+    "setBlacklist(address,bool)",
+    "setBlacklisted(address,bool)",
+    "blacklistAddress(address,bool)",
+    "addBots(address[])",
+    "setBots(address[])",
+];
+/// functions changing transfer taxes
+const FEE_FUNCTIONS: [&str; 6] = [
+    "setFee(uint256)",
+    "setFees(uint256,uint256)",
+    "setTaxes(uint256,uint256)",
+    "setBuyFee(uint256)",
+    "setSellFee(uint256)",
+    "updateFees(uint256,uint256,uint256)",
+];
+
+/// function selectors pushed by a contract's bytecode, the dispatcher compares calldata against them.
+/// selectors with leading zero bytes are pushed by PUSH1 to PUSH3, so those are only taken when
+/// an EQ follows them.
+pub fn bytecode_selectors(code: &[u8]) -> HashSet<[u8; 4]> {
+    let mut selectors = HashSet::new();
+    let mut index = 0;
+    while index < code.len() {
+        let opcode = code[index];
+        // PUSH1 to PUSH32 carry 1 to 32 bytes of data
+        if (0x60..=0x7f).contains(&opcode) {
+            let size = (opcode - 0x5f) as usize;
+            let end = index + 1 + size;
+            if size == 4 && end <= code.len() {
+                selectors.insert(code[index + 1..end].try_into().unwrap());
+            } else if size < 4 && code.get(end) == Some(&0x14) {
+                let mut selector = [0u8; 4];
+                selector[4 - size..].copy_from_slice(&code[index + 1..end]);
+                selectors.insert(selector);
+            }
+            index += size;
+        }
+        index += 1;
+    }
+    selectors
+}
+
+/// the privileged functions of `signatures` a set of selectors contains
+fn matching_functions(selectors: &HashSet<[u8; 4]>, signatures: &[&str]) -> Vec<String> {
+    signatures
+        .iter()
+        .filter(|signature| selectors.contains(&id(signature)))
+        .map(|signature| signature.to_string())
+        .collect()
+}
+
+/// builds token risk reports from pool discovery, swap simulation, bytecode and storage reads
+pub struct RiskScanner {
+    pub provider: Arc<Provider<Http>>,
+    pub price: Price,
+    pub tax_detector: TaxDetector,
+    pub tokens: Arc<TokenCache>,
+    pub lockers: Vec<Address>,   // contracts holding locked LP tokens
+    pub high_tax: f64,           // tax percentage flagged as high
+    pub min_locked_share: f64,   // LP share that must be burned or locked, 0 to 1
+    pub max_top_share: f64,      // supply share the top holders may hold, 0 to 1
+    pub top_holders: usize,      // holders counted in the concentration
+    pub holder_scan_blocks: u64, // blocks of transfers scanned for holders
+}
+
+impl RiskScanner {
+    pub fn new(provider: Arc<Provider<Http>>) -> Result<Self, UniswapError> {
+        Ok(Self {
+            price: Price::new_with_finder(provider.clone(), PriceFinder::mainnet())?,
+            tax_detector: TaxDetector::new(provider.clone()),
+            tokens: TokenCache::shared(),
+            lockers: vec![
+                MAINNET_UNICRYPT_V2_LOCKER_ADDRESS.parse().unwrap(),
+                MAINNET_TEAM_FINANCE_LOCKER_ADDRESS.parse().unwrap(),
+            ],
+            high_tax: 10.0,
+            min_locked_share: 0.9,
+            max_top_share: 0.5,
+            top_holders: 10,
+            holder_scan_blocks: 50_000,
+            provider,
+        })
+    }
+
+    /// read the EIP-1967 slots of a contract
+    pub async fn get_proxy_info(
+        &self,
+        address: Address,
+        block: Option<BlockId>,
+    ) -> Result<ProxyInfo, UniswapError> {
+        let mut slots = Vec::new();
+        for slot in [
+            EIP1967_IMPLEMENTATION_SLOT,
+            EIP1967_ADMIN_SLOT,
+            EIP1967_BEACON_SLOT,
+        ] {
+            let value = self
+                .provider
+                .get_storage_at(address, slot.parse().unwrap(), block)
+                .await
+                .map_err(|e| UniswapError::ProviderError(format!("Storage error: {}", e)))?;
+            let slot_address = Address::from_slice(&value.as_bytes()[12..]);
+            slots.push((slot_address != Address::zero()).then_some(slot_address));
+        }
+        Ok(ProxyInfo {
+            implementation: slots[0],
+            admin: slots[1],
+            beacon: slots[2],
+        })
+    }
+
+    /// scan a token's bytecode, and its implementation's for proxies, for privileged functions
+    pub async fn get_capabilities(
+        &self,
+        token: Address,
+        proxy: &ProxyInfo,
+        block: Option<BlockId>,
+    ) -> Result<TokenCapabilities, UniswapError> {
+        let mut selectors = HashSet::new();
+        for address in std::iter::once(token).chain(proxy.implementation) {
+            let code = self
+                .provider
+                .get_code(address, block)
+                .await
+                .map_err(|e| UniswapError::ProviderError(format!("Code error: {}", e)))?;
+            selectors.extend(bytecode_selectors(&code));
+        }
+        let mint = matching_functions(&selectors, &MINT_FUNCTIONS);
+        let pause = matching_functions(&selectors, &PAUSE_FUNCTIONS);
+        let blacklist = matching_functions(&selectors, &BLACKLIST_FUNCTIONS);
+        let fees = matching_functions(&selectors, &FEE_FUNCTIONS);
+        let owner = if selectors.contains(&id("owner()")) {
+            let tx = TransactionRequest::new()
+                .to(token)
+                .data(id("owner()").to_vec());
+            self.provider
+                .call(&tx.into(), block)
+                .await
+                .ok()
+                .filter(|output| output.len() >= 32)
+                .map(|output| Address::from_slice(&output[12..32]))
+        } else {
+            None
+        };
+        Ok(TokenCapabilities {
+            owner,
+            can_mint: !mint.is_empty(),
+            can_pause: !pause.is_empty(),
+            can_blacklist: !blacklist.is_empty(),
+            can_set_fees: !fees.is_empty(),
+            functions: [mint, pause, blacklist, fees].concat(),
+        })
+    }
+
+    /// LP tokens of a pair burned or held by the lockers
+    pub async fn get_liquidity_lock(
+        &self,
+        pair: Address,
+        block: Option<BlockId>,
+    ) -> Result<LiquidityLock, UniswapError> {
+        let pair_contract = IUniswapV2Pair::new(pair, self.provider.clone());
+        let total_supply = pair_contract
+            .total_supply()
+            .block(block_or_latest(block))
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("Total supply error: {}", e)))?;
+        let lp_balance = |holder: Address| {
+            let call = IERC20::new(pair, self.provider.clone()).balance_of(holder);
+            async move {
+                call.block(block_or_latest(block))
+                    .call()
+                    .await
+                    .map_err(|e| UniswapError::ContractError(format!("Balance error: {}", e)))
+            }
+        };
+        let mut burned = U256::zero();
+        for holder in [Address::zero(), DEAD_ADDRESS.parse().unwrap()] {
+            burned += lp_balance(holder).await?;
+        }
+        let mut locked = U256::zero();
+        for locker in self.lockers.iter() {
+            locked += lp_balance(*locker).await?;
+        }
+        let locked_share = if total_supply.is_zero() {
+            0.0
+        } else {
+            u256_to_f64(burned + locked) / u256_to_f64(total_supply)
+        };
+        Ok(LiquidityLock {
+            pair,
+            total_supply,
+            burned,
+            locked,
+            locked_share,
+        })
+    }
+
+    /// the largest holders among the recipients of recent transfers. pools, lockers and burn
+    /// addresses are not counted, and holders that received nothing in the window are missed.
+    pub async fn get_holder_concentration(
+        &self,
+        token: Address,
+        pools: &[PoolInfo],
+        block_number: u64,
+    ) -> Result<HolderConcentration, UniswapError> {
+        let from_block = block_number.saturating_sub(self.holder_scan_blocks);
+        let filter = Filter::new()
+            .address(token)
+            .topic0(TransferFilter::signature());
+        let logs =
+            get_logs_in_chunks(self.provider.as_ref(), &filter, from_block, block_number).await?;
+        let excluded: HashSet<Address> = pools
+            .iter()
+            .map(|pool| pool.address)
+            .chain(self.lockers.iter().copied())
+            .chain([Address::zero(), DEAD_ADDRESS.parse().unwrap(), token])
+            .collect();
+        let mut received: HashMap<Address, U256> = HashMap::new();
+        for log in logs {
+            if let Ok(transfer) = parse_log::<TransferFilter>(log)
+                && !excluded.contains(&transfer.to)
+            {
+                *received.entry(transfer.to).or_default() += transfer.value;
+            }
+        }
+        // the largest recipients are the likely largest holders, their balances are read
+        let mut candidates: Vec<(Address, U256)> = received.into_iter().collect();
+        candidates.sort_by_key(|(_, balance)| Reverse(*balance));
+        candidates.truncate(self.top_holders * 10);
+        let contract = IERC20::new(token, self.provider.clone());
+        let block = BlockId::from(block_number);
+        let total_supply = contract
+            .total_supply()
+            .block(block)
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("Total supply error: {}", e)))?;
+        let mut holders = Vec::new();
+        for (holder, _) in candidates {
+            let balance = contract
+                .balance_of(holder)
+                .block(block)
+                .call()
+                .await
+                .map_err(|e| UniswapError::ContractError(format!("Balance error: {}", e)))?;
+            if !balance.is_zero() {
+                holders.push((holder, balance));
+            }
+        }
+        holders.sort_by_key(|(_, balance)| Reverse(*balance));
+        holders.truncate(self.top_holders);
+        let held = holders
+            .iter()
+            .fold(U256::zero(), |sum, (_, balance)| sum + balance);
+        let top_share = if total_supply.is_zero() {
+            0.0
+        } else {
+            u256_to_f64(held) / u256_to_f64(total_supply)
+        };
+        Ok(HolderConcentration {
+            total_supply,
+            top_holders: holders,
+            top_share,
+            from_block,
+        })
+    }
+
+    /// simulate a buy and sell against the deepest pool, `None` when the token's storage
+    /// layout cannot be overridden
+    async fn get_tax(
+        &self,
+        token: Address,
+        pool: &PoolInfo,
+        block_number: u64,
+    ) -> Result<Option<TokenTax>, UniswapError> {
+        let (base_token, reserve_base) = if pool.token0.address == token {
+            (pool.token1.address, pool.reserve1)
+        } else {
+            (pool.token0.address, pool.reserve0)
+        };
+        let amount_in = (reserve_base / 1000).max(U256::one());
+        match self
+            .tax_detector
+            .detect(token, base_token, amount_in, Some(block_number.into()))
+            .await
+        {
+            Ok(tax) => Ok(Some(tax)),
+            Err(UniswapError::ContractError(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// scan a token at `block`, defaulting to the latest block
+    pub async fn scan(
+        &self,
+        token: Address,
+        block: Option<BlockId>,
+    ) -> Result<TokenRiskReport, UniswapError> {
+        let snapshot = self.price.snapshot(block).await?;
+        let block_number = snapshot.block_number;
+        let token_info = self
+            .tokens
+            .get_token_info(self.provider.as_ref(), token, snapshot.block())
+            .await?;
+        let pools = self
+            .price
+            .search_liquid_pools(token, snapshot.block())
+            .await?;
+        let proxy = self.get_proxy_info(token, snapshot.block()).await?;
+        let capabilities = self
+            .get_capabilities(token, &proxy, snapshot.block())
+            .await?;
+        let (tax, liquidity_lock) = match pools.first() {
+            Some(pool) => (
+                self.get_tax(token, pool, block_number).await?,
+                Some(
+                    self.get_liquidity_lock(pool.address, snapshot.block())
+                        .await?,
+                ),
+            ),
+            None => (None, None),
+        };
+        let holders = self
+            .get_holder_concentration(token, &pools, block_number)
+            .await
+            .ok();
+
+        let mut risks = Vec::new();
+        if pools.is_empty() {
+            risks.push(TokenRisk::NoLiquidity);
+        }
+        if let Some(tax) = &tax {
+            if !tax.can_buy {
+                risks.push(TokenRisk::CannotBuy);
+            }
+            if !tax.can_sell {
+                risks.push(TokenRisk::CannotSell);
+            }
+            if tax.buy_tax >= self.high_tax || tax.sell_tax >= self.high_tax {
+                risks.push(TokenRisk::HighTax);
+            }
+        }
+        // owner functions are harmless once ownership is renounced
+        if !capabilities.is_renounced() {
+            for (flag, risk) in [
+                (capabilities.can_mint, TokenRisk::Mintable),
+                (capabilities.can_pause, TokenRisk::Pausable),
+                (capabilities.can_blacklist, TokenRisk::Blacklist),
+                (capabilities.can_set_fees, TokenRisk::MutableFees),
+            ] {
+                if flag {
+                    risks.push(risk);
+                }
+            }
+        }
+        if proxy.is_proxy() {
+            risks.push(TokenRisk::Upgradeable);
+        }
+        if let Some(lock) = &liquidity_lock
+            && lock.locked_share < self.min_locked_share
+        {
+            risks.push(TokenRisk::UnlockedLiquidity);
+        }
+        if let Some(holders) = &holders
+            && holders.top_share > self.max_top_share
+        {
+            risks.push(TokenRisk::ConcentratedHolders);
+        }
+        Ok(TokenRiskReport {
+            token: token_info,
+            pools,
+            tax,
+            capabilities,
+            proxy,
+            liquidity_lock,
+            holders,
+            risks,
+            block_number,
+        })
+    }
+}
@@ -0,0 +1,85 @@
+use ethers::types::{Address, H256};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::{
+    registry::PoolRegistry,
+    tool::fs::write_atomic,
+    types::{PoolInfo, PoolLiquidity, UniswapError, V3PoolState},
+};
+
+/// format version written into every snapshot, bumped on incompatible changes
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// pool states and registry at a block, restoring one resumes syncing from that block
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    pub version: u32,
+    pub block_number: u64,
+    pub block_hash: Option<H256>,
+    pub pools: Vec<PoolLiquidity>,
+    pub decimals: HashMap<Address, u8>,
+    pub registry: Option<PoolRegistry>,
+}
+
+impl StateSnapshot {
+    pub fn new(block_number: u64, block_hash: Option<H256>) -> Self {
+        Self {
+            version: SNAPSHOT_VERSION,
+            block_number,
+            block_hash,
+            pools: Vec::new(),
+            decimals: HashMap::new(),
+            registry: None,
+        }
+    }
+
+    /// add a V2 pair, its token decimals are taken from the pool info
+    pub fn add_v2_pool(&mut self, pool_info: PoolInfo) {
+        self.decimals
+            .insert(pool_info.token0.address, pool_info.token0.decimals);
+        self.decimals
+            .insert(pool_info.token1.address, pool_info.token1.decimals);
+        self.pools.push(PoolLiquidity::V2(pool_info));
+    }
+
+    /// add a V3 pool with its tick state
+    pub fn add_v3_pool(&mut self, state: V3PoolState, decimals0: u8, decimals1: u8) {
+        self.decimals.insert(state.token0, decimals0);
+        self.decimals.insert(state.token1, decimals1);
+        self.pools.push(PoolLiquidity::V3(state));
+    }
+
+    /// encode the snapshot as JSON
+    pub fn to_json(&self) -> Result<Vec<u8>, UniswapError> {
+        serde_json::to_vec(self)
+            .map_err(|e| UniswapError::StorageError(format!("Serialize error: {}", e)))
+    }
+
+    /// decode a snapshot, rejecting other format versions
+    pub fn from_json(json: &[u8]) -> Result<Self, UniswapError> {
+        let mut snapshot: Self = serde_json::from_slice(json)
+            .map_err(|e| UniswapError::StorageError(format!("Deserialize error: {}", e)))?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(UniswapError::StorageError(format!(
+                "Unsupported snapshot version {}, expected {}",
+                snapshot.version, SNAPSHOT_VERSION
+            )));
+        }
+        if let Some(registry) = snapshot.registry.as_mut() {
+            registry.rebuild_index();
+        }
+        Ok(snapshot)
+    }
+
+    /// write the snapshot to a file, replacing it atomically
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), UniswapError> {
+        write_atomic(path, &self.to_json()?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, UniswapError> {
+        let json =
+            fs::read(path).map_err(|e| UniswapError::StorageError(format!("Read error: {}", e)))?;
+        Self::from_json(&json)
+    }
+}
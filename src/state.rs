@@ -0,0 +1,482 @@
+use ethers::{contract::parse_log, prelude::*, types::Filter};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, hash_map::Entry},
+    sync::Arc,
+};
+
+use crate::{
+    abi::{
+        TransferFilter,
+        i_uniswap_v2_pair::SyncFilter as V2SyncFilter,
+        i_uniswap_v3_pool::{
+            BurnFilter as V3BurnFilter, MintFilter as V3MintFilter, SwapFilter as V3SwapFilter,
+        },
+    },
+    depth::{pool_amount_out, spot_price},
+    events::get_logs_in_chunks,
+    registry::PoolRegistry,
+    snapshot::StateSnapshot,
+    types::{PoolLiquidity, PoolVersion, TickLiquidity, UniswapError, V3PoolState},
+    v2::UniswapV2,
+    v3::UniswapV3,
+};
+
+/// blocks of history kept to undo reorged logs
+pub const DEFAULT_REORG_DEPTH: u64 = 64;
+/// ticks loaded on each side of the current tick of a V3 pool
+pub const DEFAULT_TICK_RANGE: i32 = 4000;
+
+/// add `liquidity_delta` to a position's range, as a V3 Mint (positive) or Burn (negative)
+fn update_position(
+    state: &mut V3PoolState,
+    tick_lower: i32,
+    tick_upper: i32,
+    liquidity_delta: i128,
+) -> Result<(), UniswapError> {
+    for (tick, delta) in [
+        (tick_lower, liquidity_delta),
+        (tick_upper, -liquidity_delta),
+    ] {
+        match state.ticks.binary_search_by_key(&tick, |entry| entry.tick) {
+            Ok(index) => {
+                state.ticks[index].liquidity_net += delta;
+                if state.ticks[index].liquidity_net == 0 {
+                    state.ticks.remove(index);
+                }
+            }
+            Err(index) => state.ticks.insert(
+                index,
+                TickLiquidity {
+                    tick,
+                    liquidity_net: delta,
+                },
+            ),
+        }
+    }
+    if tick_lower <= state.tick && state.tick < tick_upper {
+        state.liquidity = state
+            .liquidity
+            .checked_add_signed(liquidity_delta)
+            .ok_or_else(|| UniswapError::MathError("Liquidity underflow".to_string()))?;
+    }
+    Ok(())
+}
+
+/// whether the price of a V3 pool has moved outside its loaded ticks
+fn outside_window(state: &V3PoolState) -> bool {
+    state.tick < state.tick_lower || state.tick >= state.tick_upper
+}
+
+/// in-memory V2 and V3 pool state kept current by Sync, LP Transfer, Swap, Mint and Burn
+/// logs. reads are synchronous and make no RPC calls.
+pub struct PoolStateManager {
+    pub provider: Arc<Provider<Http>>,
+    pub reorg_depth: u64,
+    pub tick_range: i32,
+    pub last_block: Option<u64>,
+    pools: HashMap<Address, PoolLiquidity>,
+    decimals: HashMap<Address, u8>,
+    block_hashes: BTreeMap<u64, H256>,
+    // pool states before the first log applied to them in each block
+    journal: BTreeMap<u64, HashMap<Address, PoolLiquidity>>,
+}
+
+impl PoolStateManager {
+    pub fn new(provider: Arc<Provider<Http>>) -> Self {
+        Self {
+            provider,
+            reorg_depth: DEFAULT_REORG_DEPTH,
+            tick_range: DEFAULT_TICK_RANGE,
+            last_block: None,
+            pools: HashMap::new(),
+            decimals: HashMap::new(),
+            block_hashes: BTreeMap::new(),
+            journal: BTreeMap::new(),
+        }
+    }
+
+    /// restore the pools of a snapshot, the next sync resumes from the snapshot's block
+    pub fn new_with_snapshot(provider: Arc<Provider<Http>>, snapshot: StateSnapshot) -> Self {
+        let mut manager = Self::new(provider);
+        for state in snapshot.pools {
+            manager.insert_pool(state);
+        }
+        manager.decimals.extend(snapshot.decimals);
+        if let Some(block_hash) = snapshot.block_hash {
+            manager
+                .block_hashes
+                .insert(snapshot.block_number, block_hash);
+        }
+        manager.last_block = Some(snapshot.block_number);
+        manager
+    }
+
+    /// snapshot the pools at the last synced block, with an optional registry
+    pub fn snapshot(&self, registry: Option<&PoolRegistry>) -> Result<StateSnapshot, UniswapError> {
+        let block_number = self
+            .last_block
+            .ok_or_else(|| UniswapError::StorageError("No pools loaded to snapshot".to_string()))?;
+        let mut snapshot =
+            StateSnapshot::new(block_number, self.block_hashes.get(&block_number).copied());
+        snapshot.pools = self.pools.values().cloned().collect();
+        snapshot.decimals = self.decimals.clone();
+        snapshot.registry = registry.cloned();
+        Ok(snapshot)
+    }
+
+    /// load a pool at the manager's block, the latest block for the first pool
+    pub async fn add_pool(
+        &mut self,
+        pool_address: Address,
+        version: PoolVersion,
+    ) -> Result<(), UniswapError> {
+        let block_number = match self.last_block {
+            Some(block_number) => block_number,
+            None => self
+                .provider
+                .get_block_number()
+                .await
+                .map_err(UniswapError::EthersError)?
+                .as_u64(),
+        };
+        let block = Some(BlockId::from(block_number));
+        let uniswap_v2 = UniswapV2::new_with_provider(self.provider.clone());
+        let state = match version {
+            PoolVersion::V2 => {
+                PoolLiquidity::V2(uniswap_v2.get_pool_info(pool_address, block).await?)
+            }
+            PoolVersion::V3 => PoolLiquidity::V3(
+                UniswapV3::new_with_provider(self.provider.clone())
+                    .get_tick_state(pool_address, self.tick_range, block)
+                    .await?,
+            ),
+        };
+        let (token0, token1) = self.insert_pool(state);
+        for token in [token0, token1] {
+            if let Entry::Vacant(entry) = self.decimals.entry(token) {
+                let token_info = uniswap_v2.get_token_info(token, block).await?;
+                entry.insert(token_info.decimals);
+            }
+        }
+        if self.last_block.is_none() {
+            self.last_block = Some(block_number);
+        }
+        Ok(())
+    }
+
+    /// insert a pool state loaded elsewhere, it must be at the manager's block
+    pub fn insert_pool(&mut self, state: PoolLiquidity) -> (Address, Address) {
+        let (address, token0, token1) = match &state {
+            PoolLiquidity::V2(pool_info) => (
+                pool_info.address,
+                pool_info.token0.address,
+                pool_info.token1.address,
+            ),
+            PoolLiquidity::V3(state) => (state.pool, state.token0, state.token1),
+        };
+        if let PoolLiquidity::V2(pool_info) = &state {
+            self.decimals
+                .insert(pool_info.token0.address, pool_info.token0.decimals);
+            self.decimals
+                .insert(pool_info.token1.address, pool_info.token1.decimals);
+        }
+        self.pools.insert(address, state);
+        (token0, token1)
+    }
+
+    /// set the decimals of a token, needed for prices of V3 pools inserted with `insert_pool`
+    pub fn set_decimals(&mut self, token: Address, decimals: u8) {
+        self.decimals.insert(token, decimals);
+    }
+
+    /// the current state of a pool
+    pub fn get(&self, pool_address: Address) -> Option<&PoolLiquidity> {
+        self.pools.get(&pool_address)
+    }
+
+    /// addresses of the tracked pools
+    pub fn pool_addresses(&self) -> Vec<Address> {
+        self.pools.keys().copied().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pools.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pools.is_empty()
+    }
+
+    /// pools updated by logs of `from_block` or later, within the kept history
+    pub fn changed_pools(&self, from_block: u64) -> HashSet<Address> {
+        self.journal
+            .range(from_block..)
+            .flat_map(|(_, states)| states.keys().copied())
+            .collect()
+    }
+
+    /// V3 pools whose price has moved outside the loaded ticks, their quotes stop at the
+    /// edge of the window until `sync` reloads them
+    pub fn stale_pools(&self) -> Vec<Address> {
+        self.pools
+            .values()
+            .filter_map(|state| match state {
+                PoolLiquidity::V3(state) if outside_window(state) => Some(state.pool),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// current reserves of a V2 pair
+    pub fn reserves(&self, pool_address: Address) -> Option<(U256, U256)> {
+        match self.pools.get(&pool_address)? {
+            PoolLiquidity::V2(pool_info) => Some((pool_info.reserve0, pool_info.reserve1)),
+            PoolLiquidity::V3(_) => None,
+        }
+    }
+
+    /// current price of token0 in token1, decimals adjusted
+    pub fn price(&self, pool_address: Address) -> Option<f64> {
+        let state = self.pools.get(&pool_address)?;
+        let (token0, token1) = match state {
+            PoolLiquidity::V2(pool_info) => (pool_info.token0.address, pool_info.token1.address),
+            PoolLiquidity::V3(state) => (state.token0, state.token1),
+        };
+        Some(spot_price(
+            state,
+            *self.decimals.get(&token0)?,
+            *self.decimals.get(&token1)?,
+        ))
+    }
+
+    /// output amount of an exact input swap against the current state
+    pub fn quote(
+        &self,
+        pool_address: Address,
+        token_in: Address,
+        amount_in: U256,
+    ) -> Result<U256, UniswapError> {
+        let state = self
+            .pools
+            .get(&pool_address)
+            .ok_or(UniswapError::PoolNotFound)?;
+        pool_amount_out(state, token_in, amount_in)
+    }
+
+    /// the log filter matching every event that updates the tracked pools
+    pub fn filter(&self) -> Filter {
+        Filter::new().address(self.pool_addresses()).topic0(vec![
+            V2SyncFilter::signature(),
+            TransferFilter::signature(),
+            V3SwapFilter::signature(),
+            V3MintFilter::signature(),
+            V3BurnFilter::signature(),
+        ])
+    }
+
+    /// apply a log to the pool it belongs to, returns false when the log does not update a
+    /// tracked pool. logs must be applied in chain order, removed logs roll the state back.
+    pub fn apply_log(&mut self, log: &Log) -> Result<bool, UniswapError> {
+        let Some(block_number) = log.block_number.map(|number| number.as_u64()) else {
+            return Ok(false);
+        };
+        if log.removed == Some(true) {
+            self.rollback(block_number);
+            return Ok(true);
+        }
+        let Some(state) = self.pools.get(&log.address) else {
+            return Ok(false);
+        };
+        let previous = state.clone();
+        let mut state = previous.clone();
+        let updated = match &mut state {
+            PoolLiquidity::V2(pool_info) => {
+                if let Ok(event) = parse_log::<V2SyncFilter>(log.clone()) {
+                    pool_info.reserve0 = U256::from(event.reserve_0);
+                    pool_info.reserve1 = U256::from(event.reserve_1);
+                    true
+                } else if let Ok(event) = parse_log::<TransferFilter>(log.clone()) {
+                    // LP tokens minted from or burned to the zero address change the total supply
+                    if event.from.is_zero() {
+                        pool_info.liquidity = pool_info
+                            .liquidity
+                            .checked_add(event.value)
+                            .ok_or_else(|| {
+                                UniswapError::MathError("Liquidity overflow".to_string())
+                            })?;
+                        true
+                    } else if event.to.is_zero() {
+                        pool_info.liquidity = pool_info
+                            .liquidity
+                            .checked_sub(event.value)
+                            .ok_or_else(|| {
+                                UniswapError::MathError("Liquidity underflow".to_string())
+                            })?;
+                        true
+                    } else {
+                        false
+                    }
+                } else {
+                    false
+                }
+            }
+            PoolLiquidity::V3(state) => {
+                if let Ok(event) = parse_log::<V3SwapFilter>(log.clone()) {
+                    state.sqrt_price_x96 = event.sqrt_price_x96;
+                    state.liquidity = event.liquidity;
+                    state.tick = event.tick;
+                    true
+                } else if let Ok(event) = parse_log::<V3MintFilter>(log.clone()) {
+                    update_position(
+                        state,
+                        event.tick_lower,
+                        event.tick_upper,
+                        event.amount as i128,
+                    )?;
+                    true
+                } else if let Ok(event) = parse_log::<V3BurnFilter>(log.clone()) {
+                    update_position(
+                        state,
+                        event.tick_lower,
+                        event.tick_upper,
+                        -(event.amount as i128),
+                    )?;
+                    true
+                } else {
+                    false
+                }
+            }
+        };
+        if !updated {
+            return Ok(false);
+        }
+        self.journal
+            .entry(block_number)
+            .or_default()
+            .entry(log.address)
+            .or_insert(previous);
+        if let Some(block_hash) = log.block_hash {
+            self.block_hashes.insert(block_number, block_hash);
+        }
+        self.pools.insert(log.address, state);
+        Ok(true)
+    }
+
+    /// reload the ticks of the pools whose price left the loaded window, at `block_number`
+    async fn reload_stale_pools(&mut self, block_number: u64) -> Result<(), UniswapError> {
+        let uniswap_v3 = UniswapV3::new_with_provider(self.provider.clone());
+        for pool_address in self.stale_pools() {
+            let state = uniswap_v3
+                .get_tick_state(
+                    pool_address,
+                    self.tick_range,
+                    Some(BlockId::from(block_number)),
+                )
+                .await?;
+            if let Some(previous) = self.pools.insert(pool_address, PoolLiquidity::V3(state)) {
+                self.journal
+                    .entry(block_number)
+                    .or_default()
+                    .entry(pool_address)
+                    .or_insert(previous);
+            }
+        }
+        Ok(())
+    }
+
+    /// undo every log applied at or after `block_number`
+    pub fn rollback(&mut self, block_number: u64) {
+        let reverted = self.journal.split_off(&block_number);
+        for (_, states) in reverted.into_iter().rev() {
+            self.pools.extend(states);
+        }
+        self.block_hashes.split_off(&block_number);
+        if let Some(last_block) = self.last_block
+            && last_block >= block_number
+        {
+            self.last_block = Some(block_number.saturating_sub(1));
+        }
+    }
+
+    /// drop history older than the reorg depth
+    fn prune(&mut self) {
+        if let Some(last_block) = self.last_block {
+            let keep_from = last_block.saturating_sub(self.reorg_depth);
+            self.journal = self.journal.split_off(&keep_from);
+            self.block_hashes = self.block_hashes.split_off(&keep_from);
+        }
+    }
+
+    /// find the newest recorded block still on the canonical chain and roll back past it.
+    /// fails when the reorg is deeper than the kept history.
+    async fn handle_reorg(&mut self) -> Result<(), UniswapError> {
+        let recorded: Vec<(u64, H256)> = self
+            .block_hashes
+            .iter()
+            .rev()
+            .map(|(number, hash)| (*number, *hash))
+            .collect();
+        let Some((newest, _)) = recorded.first().copied() else {
+            return Ok(());
+        };
+        for (block_number, block_hash) in recorded {
+            let block = self
+                .provider
+                .get_block(block_number)
+                .await
+                .map_err(UniswapError::EthersError)?;
+            if block.and_then(|block| block.hash) == Some(block_hash) {
+                if block_number < newest {
+                    self.rollback(block_number + 1);
+                }
+                return Ok(());
+            }
+        }
+        Err(UniswapError::ReorgError(
+            "Reorg deeper than the kept history, reload the pools".to_string(),
+        ))
+    }
+
+    /// fetch and apply the logs since the last synced block up to `to_block`, the latest
+    /// block by default. reorged blocks are rolled back and replayed from the canonical chain.
+    pub async fn sync(&mut self, to_block: Option<u64>) -> Result<usize, UniswapError> {
+        let Some(last_block) = self.last_block else {
+            return Ok(0);
+        };
+        self.handle_reorg().await?;
+        let from_block = self.last_block.unwrap_or(last_block) + 1;
+        let to_block = match to_block {
+            Some(to_block) => to_block,
+            None => self
+                .provider
+                .get_block_number()
+                .await
+                .map_err(UniswapError::EthersError)?
+                .as_u64(),
+        };
+        if from_block > to_block || self.pools.is_empty() {
+            return Ok(0);
+        }
+        let logs = get_logs_in_chunks(self.provider.as_ref(), &self.filter(), from_block, to_block)
+            .await?;
+        let mut applied = 0;
+        for log in logs.iter() {
+            if self.apply_log(log)? {
+                applied += 1;
+            }
+        }
+        self.reload_stale_pools(to_block).await?;
+        if let Some(block) = self
+            .provider
+            .get_block(to_block)
+            .await
+            .map_err(UniswapError::EthersError)?
+            && let Some(block_hash) = block.hash
+        {
+            self.block_hashes.insert(to_block, block_hash);
+        }
+        self.last_block = Some(to_block);
+        self.prune();
+        Ok(applied)
+    }
+}
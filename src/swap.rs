@@ -0,0 +1,355 @@
+use ethers::{prelude::*, types::transaction::eip2718::TypedTransaction};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock},
+};
+
+use crate::{
+    abi::{IUniswapV2Factory, IUniswapV2Router},
+    global::mainnet::{
+        DAI_ADDRESS, MAINNET_UNISWAP_V2_FACTORY_ADDRESS, MAINNET_UNISWAP_V2_ROUTER_ADDRESS,
+        USDC_ADDRESS, USDT_ADDRESS, WBTC_ADDRESS, WETH_ADDRESS,
+    },
+    math::{liquidity::apply_slippage, v2},
+    tax::TaxDetector,
+    tool::tx::{ensure_allowance, send_transaction, wallet_client},
+    types::{SwapQuote, SwapRequest, TokenTax, TransactionResult, UniswapError},
+    v2::UniswapV2,
+};
+
+/// share of the base reserve swapped when measuring a token's taxes
+const TAX_PROBE_DIVISOR: u64 = 1000;
+
+/// reduce an amount by a tax percentage
+fn apply_tax(amount: U256, tax: f64) -> U256 {
+    apply_slippage(amount, tax)
+}
+
+/// uniswap v2 router swap client. tokens with transfer taxes are detected and swapped through
+/// the SupportingFeeOnTransferTokens functions, with quotes net of the taxes.
+pub struct SwapRouter {
+    pub provider: Arc<Provider<Http>>,
+    pub router: Address,
+    pub v2_factory: Address,
+    pub detect_taxes: bool, // simulate unknown tokens, otherwise only taxes set with `set_tax` apply
+    pub known_tokens: HashSet<Address>, // tokens without a transfer tax, never simulated
+    taxes: RwLock<HashMap<Address, TokenTax>>,
+}
+
+impl SwapRouter {
+    pub fn new(provider: Arc<Provider<Http>>) -> Self {
+        Self::new_with_address(
+            provider,
+            MAINNET_UNISWAP_V2_ROUTER_ADDRESS.parse().unwrap(),
+            MAINNET_UNISWAP_V2_FACTORY_ADDRESS.parse().unwrap(),
+        )
+    }
+
+    pub fn new_with_address(
+        provider: Arc<Provider<Http>>,
+        router: Address,
+        v2_factory: Address,
+    ) -> Self {
+        Self {
+            provider,
+            router,
+            v2_factory,
+            detect_taxes: true,
+            known_tokens: [
+                WETH_ADDRESS,
+                USDC_ADDRESS,
+                USDT_ADDRESS,
+                DAI_ADDRESS,
+                WBTC_ADDRESS,
+            ]
+            .iter()
+            .map(|address| address.parse().unwrap())
+            .collect(),
+            taxes: RwLock::new(HashMap::new()),
+        }
+    }
+
+    fn contract(&self) -> IUniswapV2Router<Provider<Http>> {
+        IUniswapV2Router::new(self.router, self.provider.clone())
+    }
+
+    /// the tax detector simulating against this router and factory
+    pub fn tax_detector(&self) -> TaxDetector {
+        TaxDetector::new_with_address(self.provider.clone(), self.router, self.v2_factory)
+    }
+
+    /// record a known token tax, it takes precedence over detection
+    pub fn set_tax(&self, tax: TokenTax) {
+        self.taxes.write().unwrap().insert(tax.token, tax);
+    }
+
+    /// mark tokens as free of transfer taxes, for example the tokens of a trusted list
+    pub fn add_known_tokens(&mut self, tokens: impl IntoIterator<Item = Address>) {
+        self.known_tokens.extend(tokens);
+    }
+
+    /// the cached tax of a token
+    pub fn cached_tax(&self, token: Address) -> Option<TokenTax> {
+        self.taxes.read().unwrap().get(&token).cloned()
+    }
+
+    /// get pair address by tokens
+    pub async fn get_pair_address(
+        &self,
+        token_a: Address,
+        token_b: Address,
+    ) -> Result<Address, UniswapError> {
+        let factory = IUniswapV2Factory::new(self.v2_factory, self.provider.clone());
+        let pair_address = factory
+            .get_pair(token_a, token_b)
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("Factory error: {}", e)))?;
+        if pair_address == Address::zero() {
+            return Err(UniswapError::PoolNotFound);
+        }
+        Ok(pair_address)
+    }
+
+    /// the direct pair when it exists, otherwise a route through the router WETH
+    pub async fn get_path(
+        &self,
+        token_in: Address,
+        token_out: Address,
+    ) -> Result<Vec<Address>, UniswapError> {
+        match self.get_pair_address(token_in, token_out).await {
+            Ok(_) => Ok(vec![token_in, token_out]),
+            Err(UniswapError::PoolNotFound) => {
+                let weth = self
+                    .contract()
+                    .weth()
+                    .call()
+                    .await
+                    .map_err(|e| UniswapError::ContractError(format!("WETH error: {}", e)))?;
+                if token_in == weth || token_out == weth {
+                    return Err(UniswapError::PoolNotFound);
+                }
+                Ok(vec![token_in, weth, token_out])
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// reserves of a pair ordered as (token_a, token_b)
+    async fn get_reserves(
+        &self,
+        token_a: Address,
+        token_b: Address,
+    ) -> Result<(U256, U256), UniswapError> {
+        let pair_address = self.get_pair_address(token_a, token_b).await?;
+        let (reserve0, reserve1, _) = UniswapV2::new_with_provider(self.provider.clone())
+            .get_reserves(pair_address, None)
+            .await?;
+        if token_a < token_b {
+            Ok((reserve0, reserve1))
+        } else {
+            Ok((reserve1, reserve0))
+        }
+    }
+
+    /// the tax of `token`, detected against its pair with `base_token` when not cached.
+    /// `None` for known tokens, and for failed detections which are added to `warnings`.
+    async fn get_tax(
+        &self,
+        token: Address,
+        base_token: Address,
+        reserve_base: U256,
+        warnings: &mut Vec<String>,
+    ) -> Option<TokenTax> {
+        if let Some(tax) = self.cached_tax(token) {
+            return Some(tax);
+        }
+        if !self.detect_taxes || self.known_tokens.contains(&token) {
+            return None;
+        }
+        let amount_in = (reserve_base / TAX_PROBE_DIVISOR).max(U256::one());
+        match self
+            .tax_detector()
+            .detect(token, base_token, amount_in, None)
+            .await
+        {
+            Ok(tax) => {
+                self.set_tax(tax.clone());
+                Some(tax)
+            }
+            Err(e) => {
+                warnings.push(format!("Tax of {:?} unknown: {}", token, e));
+                None
+            }
+        }
+    }
+
+    /// quote an exact input swap along `path`, net of the sell tax of the input token and the
+    /// buy tax of every token received
+    pub async fn quote_swap_path(
+        &self,
+        path: &[Address],
+        amount_in: U256,
+        slippage: f64,
+    ) -> Result<SwapQuote, UniswapError> {
+        if path.len() < 2 {
+            return Err(UniswapError::InvalidAddress(
+                "Swap path needs two tokens".to_string(),
+            ));
+        }
+        let mut amounts = vec![amount_in];
+        let mut amount = amount_in;
+        let mut fee_on_transfer = false;
+        let mut warnings = Vec::new();
+        for (hop, tokens) in path.windows(2).enumerate() {
+            let (token_in, token_out) = (tokens[0], tokens[1]);
+            let (reserve_in, reserve_out) = self.get_reserves(token_in, token_out).await?;
+            if hop == 0
+                && let Some(tax) = self
+                    .get_tax(token_in, token_out, reserve_out, &mut warnings)
+                    .await
+            {
+                if !tax.can_sell {
+                    return Err(UniswapError::TransactionFailed(format!(
+                        "{:?} cannot be sold",
+                        token_in
+                    )));
+                }
+                fee_on_transfer |= tax.is_fee_on_transfer();
+                amount = apply_tax(amount, tax.sell_tax);
+            }
+            amount = v2::get_amount_out(amount, reserve_in, reserve_out)?;
+            if let Some(tax) = self
+                .get_tax(token_out, token_in, reserve_in, &mut warnings)
+                .await
+            {
+                if !tax.can_buy {
+                    return Err(UniswapError::TransactionFailed(format!(
+                        "{:?} cannot be bought",
+                        token_out
+                    )));
+                }
+                fee_on_transfer |= tax.is_fee_on_transfer();
+                amount = apply_tax(amount, tax.buy_tax);
+            }
+            amounts.push(amount);
+        }
+        Ok(SwapQuote {
+            path: path.to_vec(),
+            amount_in,
+            amounts,
+            amount_out: amount,
+            amount_out_min: apply_slippage(amount, slippage),
+            fee_on_transfer,
+            warnings,
+        })
+    }
+
+    /// quote a swap request along the path from `get_path`
+    pub async fn quote_swap(&self, request: &SwapRequest) -> Result<SwapQuote, UniswapError> {
+        let path = self.get_path(request.token_in, request.token_out).await?;
+        self.quote_swap_path(&path, request.amount_in, request.slippage)
+            .await
+    }
+
+    /// build the exact input token swap, using the fee-on-transfer variant when the quote needs it
+    pub fn build_swap_tx(&self, request: &SwapRequest, quote: &SwapQuote) -> TypedTransaction {
+        let contract = self.contract();
+        if quote.fee_on_transfer {
+            contract
+                .swap_exact_tokens_for_tokens_supporting_fee_on_transfer_tokens(
+                    quote.amount_in,
+                    quote.amount_out_min,
+                    quote.path.clone(),
+                    request.recipient,
+                    request.deadline.into(),
+                )
+                .tx
+        } else {
+            contract
+                .swap_exact_tokens_for_tokens(
+                    quote.amount_in,
+                    quote.amount_out_min,
+                    quote.path.clone(),
+                    request.recipient,
+                    request.deadline.into(),
+                )
+                .tx
+        }
+    }
+
+    /// build the exact ETH input swap, the quote path must start with WETH
+    pub fn build_swap_eth_tx(&self, request: &SwapRequest, quote: &SwapQuote) -> TypedTransaction {
+        let contract = self.contract();
+        if quote.fee_on_transfer {
+            contract
+                .swap_exact_eth_for_tokens_supporting_fee_on_transfer_tokens(
+                    quote.amount_out_min,
+                    quote.path.clone(),
+                    request.recipient,
+                    request.deadline.into(),
+                )
+                .value(quote.amount_in)
+                .tx
+        } else {
+            contract
+                .swap_exact_eth_for_tokens(
+                    quote.amount_out_min,
+                    quote.path.clone(),
+                    request.recipient,
+                    request.deadline.into(),
+                )
+                .value(quote.amount_in)
+                .tx
+        }
+    }
+
+    /// build the exact token input swap paying out ETH, the quote path must end with WETH
+    pub fn build_swap_for_eth_tx(
+        &self,
+        request: &SwapRequest,
+        quote: &SwapQuote,
+    ) -> TypedTransaction {
+        let contract = self.contract();
+        if quote.fee_on_transfer {
+            contract
+                .swap_exact_tokens_for_eth_supporting_fee_on_transfer_tokens(
+                    quote.amount_in,
+                    quote.amount_out_min,
+                    quote.path.clone(),
+                    request.recipient,
+                    request.deadline.into(),
+                )
+                .tx
+        } else {
+            contract
+                .swap_exact_tokens_for_eth(
+                    quote.amount_in,
+                    quote.amount_out_min,
+                    quote.path.clone(),
+                    request.recipient,
+                    request.deadline.into(),
+                )
+                .tx
+        }
+    }
+
+    /// swap an exact amount of `token_in` for `token_out`
+    pub async fn swap(
+        &self,
+        wallet: LocalWallet,
+        request: &SwapRequest,
+    ) -> Result<(SwapQuote, TransactionResult), UniswapError> {
+        let quote = self.quote_swap(request).await?;
+        let client = wallet_client(self.provider.clone(), wallet).await?;
+        ensure_allowance(
+            client.clone(),
+            request.token_in,
+            self.router,
+            request.amount_in,
+        )
+        .await?;
+        let (result, _) = send_transaction(client, self.build_swap_tx(request, &quote)).await?;
+        Ok((quote, result))
+    }
+}
@@ -0,0 +1,423 @@
+use ethers::{
+    abi::{AbiDecode, AbiEncode},
+    prelude::*,
+    providers::{RawCall, spoof},
+    types::transaction::eip2718::TypedTransaction,
+    utils::keccak256,
+};
+use std::sync::Arc;
+
+use crate::{
+    abi::{
+        IUniswapV2Factory, IUniswapV2Router,
+        i_uniswap_v2_pair::{GetReservesCall, GetReservesReturn},
+        ierc20::{AllowanceCall, BalanceOfCall},
+    },
+    global::mainnet::{MAINNET_UNISWAP_V2_FACTORY_ADDRESS, MAINNET_UNISWAP_V2_ROUTER_ADDRESS},
+    math::v2,
+    tool::{block::block_or_latest, num::u256_to_f64, rpc::is_revert},
+    types::{TokenTax, UniswapError},
+};
+
+/// account swaps are simulated from, its balances are overridden for each call
+pub const SIMULATION_ACCOUNT: &str = "0x00000000000000000000000000000000000C0DE5";
+/// storage slots searched for the balance and allowance mappings of a token
+pub const MAX_SLOT_SEARCH: u64 = 64;
+/// resolution of the simulated taxes, in basis points
+const TAX_RESOLUTION_BPS: u64 = 10_000;
+
+fn word(value: impl Into<U256>) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    value.into().to_big_endian(&mut bytes);
+    bytes
+}
+
+fn address_word(address: Address) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    bytes[12..].copy_from_slice(address.as_bytes());
+    bytes
+}
+
+fn mapping_key(key: [u8; 32], slot: [u8; 32], vyper: bool) -> [u8; 32] {
+    let (first, second) = if vyper { (slot, key) } else { (key, slot) };
+    keccak256([first, second].concat())
+}
+
+/// storage keys of `balances[holder]` with the mapping at `slot`, for solidity and vyper layouts
+fn balance_keys(holder: Address, slot: u64) -> [H256; 2] {
+    [false, true].map(|vyper| H256(mapping_key(address_word(holder), word(slot), vyper)))
+}
+
+/// storage keys of `allowances[owner][spender]` with the mapping at `slot`
+fn allowance_keys(owner: Address, spender: Address, slot: u64) -> [H256; 2] {
+    [false, true].map(|vyper| {
+        let inner = mapping_key(address_word(owner), word(slot), vyper);
+        H256(mapping_key(address_word(spender), inner, vyper))
+    })
+}
+
+/// measures the buy and sell taxes of a token by simulating router swaps with eth_call.
+/// the simulation account is funded through state overrides and the sell is chained on the
+/// buy with debug_traceCall, so the node must support both.
+pub struct TaxDetector {
+    pub provider: Arc<Provider<Http>>,
+    pub router: Address,
+    pub factory: Address,
+}
+
+impl TaxDetector {
+    pub fn new(provider: Arc<Provider<Http>>) -> Self {
+        Self::new_with_address(
+            provider,
+            MAINNET_UNISWAP_V2_ROUTER_ADDRESS.parse().unwrap(),
+            MAINNET_UNISWAP_V2_FACTORY_ADDRESS.parse().unwrap(),
+        )
+    }
+
+    pub fn new_with_address(
+        provider: Arc<Provider<Http>>,
+        router: Address,
+        factory: Address,
+    ) -> Self {
+        Self {
+            provider,
+            router,
+            factory,
+        }
+    }
+
+    fn account() -> Address {
+        SIMULATION_ACCOUNT.parse().unwrap()
+    }
+
+    /// eth_call with state overrides, `None` when the call reverts
+    async fn call(
+        &self,
+        tx: &TypedTransaction,
+        state: &spoof::State,
+        block: BlockId,
+    ) -> Result<Option<Bytes>, UniswapError> {
+        match self.provider.call_raw(tx).block(block).state(state).await {
+            Ok(output) => Ok(Some(output)),
+            Err(e) if MiddlewareError::as_error_response(&e).is_some_and(is_revert) => Ok(None),
+            Err(e) => Err(UniswapError::EthersError(e)),
+        }
+    }
+
+    /// find the storage keys a getter of `token` reads, by overriding candidate slots until
+    /// its result changes
+    async fn find_slot<F>(
+        &self,
+        token: Address,
+        getter: Vec<u8>,
+        keys: F,
+        block: BlockId,
+    ) -> Result<Option<[H256; 2]>, UniswapError>
+    where
+        F: Fn(u64) -> [H256; 2],
+    {
+        let tx: TypedTransaction = TransactionRequest::new().to(token).data(getter).into();
+        let baseline = self.call(&tx, &spoof::State::default(), block).await?;
+        let marker = H256(word(U256::from(0x5107_u64) << 128));
+        for slot in 0..MAX_SLOT_SEARCH {
+            let slot_keys = keys(slot);
+            let mut state = spoof::State::default();
+            for key in slot_keys {
+                state.account(token).store(key, marker);
+            }
+            let output = self.call(&tx, &state, block).await?;
+            if output.is_some() && output != baseline {
+                return Ok(Some(slot_keys));
+            }
+        }
+        Ok(None)
+    }
+
+    /// override the balance of the simulation account and its allowance for the router
+    pub async fn fund(
+        &self,
+        state: &mut spoof::State,
+        token: Address,
+        amount: U256,
+        block: BlockId,
+    ) -> Result<(), UniswapError> {
+        let account = Self::account();
+        let router = self.router;
+        let balance_slot = self
+            .find_slot(
+                token,
+                BalanceOfCall { account }.encode(),
+                |slot| balance_keys(account, slot),
+                block,
+            )
+            .await?;
+        let allowance_slot = self
+            .find_slot(
+                token,
+                AllowanceCall {
+                    owner: account,
+                    spender: router,
+                }
+                .encode(),
+                |slot| allowance_keys(account, router, slot),
+                block,
+            )
+            .await?;
+        let (Some(balance_keys), Some(allowance_keys)) = (balance_slot, allowance_slot) else {
+            return Err(UniswapError::ContractError(format!(
+                "Storage layout of {:?} not found",
+                token
+            )));
+        };
+        for key in balance_keys {
+            state.account(token).store(key, H256(word(amount)));
+        }
+        for key in allowance_keys {
+            state.account(token).store(key, H256(word(U256::MAX)));
+        }
+        Ok(())
+    }
+
+    /// a fee-on-transfer router swap from the simulation account
+    fn swap_tx(
+        &self,
+        path: Vec<Address>,
+        amount_in: U256,
+        amount_out_min: U256,
+    ) -> TypedTransaction {
+        IUniswapV2Router::new(self.router, self.provider.clone())
+            .swap_exact_tokens_for_tokens_supporting_fee_on_transfer_tokens(
+                amount_in,
+                amount_out_min,
+                path,
+                Self::account(),
+                U256::from(u64::MAX),
+            )
+            .from(Self::account())
+            .tx
+    }
+
+    /// `state` with the changes of `tx` applied, traced by debug_traceCall with the
+    /// prestateTracer in diff mode
+    async fn apply_call(
+        &self,
+        tx: &TypedTransaction,
+        state: &spoof::State,
+        block: BlockId,
+    ) -> Result<spoof::State, UniswapError> {
+        let options = GethDebugTracingCallOptions {
+            tracing_options: GethDebugTracingOptions {
+                tracer: Some(GethDebugTracerType::BuiltInTracer(
+                    GethDebugBuiltInTracerType::PreStateTracer,
+                )),
+                tracer_config: Some(GethDebugTracerConfig::BuiltInTracer(
+                    GethDebugBuiltInTracerConfig::PreStateTracer(PreStateConfig {
+                        diff_mode: Some(true),
+                    }),
+                )),
+                ..Default::default()
+            },
+            state_overrides: Some(state.clone()),
+            block_overrides: None,
+        };
+        let trace = self
+            .provider
+            .debug_trace_call(tx.clone(), Some(block), options)
+            .await
+            .map_err(UniswapError::EthersError)?;
+        let diff = match trace {
+            GethTrace::Known(GethTraceFrame::PreStateTracer(PreStateFrame::Diff(diff))) => diff,
+            GethTrace::Unknown(value) => serde_json::from_value::<DiffMode>(value)
+                .map_err(|e| UniswapError::ContractError(format!("Invalid state diff: {}", e)))?,
+            _ => {
+                return Err(UniswapError::ContractError(
+                    "Invalid state diff".to_string(),
+                ));
+            }
+        };
+        let mut state = state.clone();
+        for (address, post) in diff.post.iter() {
+            let account = state.account(*address);
+            if let Some(balance) = post.balance {
+                account.balance(balance);
+            }
+            if let Some(nonce) = post.nonce {
+                account.nonce(nonce.as_u64().into());
+            }
+            for (key, value) in post.storage.iter().flatten() {
+                account.store(*key, *value);
+            }
+        }
+        // slots cleared by the call are only listed in the pre state
+        for (address, pre) in diff.pre.iter() {
+            let post_storage = diff
+                .post
+                .get(address)
+                .and_then(|post| post.storage.as_ref());
+            for key in pre.storage.iter().flatten().map(|(key, _)| key) {
+                if !post_storage.is_some_and(|storage| storage.contains_key(key)) {
+                    state.account(*address).store(*key, H256::zero());
+                }
+            }
+        }
+        Ok(state)
+    }
+
+    /// reserves of `pair` under `state`
+    async fn get_reserves(
+        &self,
+        pair: Address,
+        state: &spoof::State,
+        block: BlockId,
+    ) -> Result<(U256, U256), UniswapError> {
+        let tx: TypedTransaction = TransactionRequest::new()
+            .to(pair)
+            .data(GetReservesCall.encode())
+            .into();
+        let output = self
+            .call(&tx, state, block)
+            .await?
+            .ok_or_else(|| UniswapError::ContractError("getReserves reverted".to_string()))?;
+        let reserves = GetReservesReturn::decode(output)
+            .map_err(|e| UniswapError::ContractError(format!("Decode error: {}", e)))?;
+        Ok((
+            U256::from(reserves.reserve_0),
+            U256::from(reserves.reserve_1),
+        ))
+    }
+
+    /// the largest output a fee-on-transfer swap delivers, to `TAX_RESOLUTION_BPS` of
+    /// `expected_out`. `None` when the swap reverts even without a minimum.
+    async fn simulate_swap(
+        &self,
+        path: Vec<Address>,
+        amount_in: U256,
+        expected_out: U256,
+        state: &spoof::State,
+        block: BlockId,
+    ) -> Result<Option<U256>, UniswapError> {
+        let min_out = |bps: u64| {
+            expected_out * U256::from(TAX_RESOLUTION_BPS - bps) / U256::from(TAX_RESOLUTION_BPS)
+        };
+        let swap_tx = |amount_out_min: U256| self.swap_tx(path.clone(), amount_in, amount_out_min);
+        if self
+            .call(&swap_tx(U256::zero()), state, block)
+            .await?
+            .is_none()
+        {
+            return Ok(None);
+        }
+        // the smallest withheld share at which the swap's minimum output is met
+        let (mut low, mut high) = (0, TAX_RESOLUTION_BPS);
+        while low < high {
+            let middle = (low + high) / 2;
+            if self
+                .call(&swap_tx(min_out(middle)), state, block)
+                .await?
+                .is_some()
+            {
+                high = middle;
+            } else {
+                low = middle + 1;
+            }
+        }
+        Ok(Some(min_out(low)))
+    }
+
+    /// measure the taxes of buying `token` with `amount_in` of `base_token` at `block`, then
+    /// selling the bought amount back on the state the buy left behind. the buy's state changes
+    /// are traced with debug_traceCall, so the node must support the prestateTracer. when the
+    /// buy reverts the sell is simulated on the state at `block`.
+    pub async fn detect(
+        &self,
+        token: Address,
+        base_token: Address,
+        amount_in: U256,
+        block: Option<BlockId>,
+    ) -> Result<TokenTax, UniswapError> {
+        let block_number = self
+            .provider
+            .get_block(block_or_latest(block))
+            .await
+            .map_err(UniswapError::EthersError)?
+            .and_then(|block| block.number)
+            .ok_or_else(|| UniswapError::ContractError("Block not found".to_string()))?
+            .as_u64();
+        let block = BlockId::from(block_number);
+        let factory = IUniswapV2Factory::new(self.factory, self.provider.clone());
+        let pair = factory
+            .get_pair(token, base_token)
+            .block(block)
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("Factory error: {}", e)))?;
+        if pair == Address::zero() {
+            return Err(UniswapError::PoolNotFound);
+        }
+        // reserves ordered as (token, base_token)
+        let ordered = |(reserve0, reserve1): (U256, U256)| {
+            if token < base_token {
+                (reserve0, reserve1)
+            } else {
+                (reserve1, reserve0)
+            }
+        };
+        // buy
+        let mut state = spoof::State::default();
+        self.fund(&mut state, base_token, amount_in, block).await?;
+        let (reserve_token, reserve_base) = ordered(self.get_reserves(pair, &state, block).await?);
+        let expected_buy = v2::get_amount_out(amount_in, reserve_base, reserve_token)?;
+        let bought = self
+            .simulate_swap(
+                vec![base_token, token],
+                amount_in,
+                expected_buy,
+                &state,
+                block,
+            )
+            .await?;
+        let buy_tax = match bought {
+            Some(bought) => (1.0 - u256_to_f64(bought) / u256_to_f64(expected_buy)) * 100.0,
+            None => 100.0,
+        };
+        // sell what the buy delivered, after the buy
+        let (sell_amount, mut state) = match bought {
+            Some(bought) => {
+                let buy_tx = self.swap_tx(vec![base_token, token], amount_in, U256::zero());
+                (bought, self.apply_call(&buy_tx, &state, block).await?)
+            }
+            None => (expected_buy, spoof::State::default()),
+        };
+        self.fund(&mut state, token, sell_amount, block).await?;
+        let (reserve_token, reserve_base) = ordered(self.get_reserves(pair, &state, block).await?);
+        let expected_sell = v2::get_amount_out(sell_amount, reserve_token, reserve_base)?;
+        let sold = self
+            .simulate_swap(
+                vec![token, base_token],
+                sell_amount,
+                expected_sell,
+                &state,
+                block,
+            )
+            .await?;
+        let sell_tax = match sold {
+            Some(sold) if sold == expected_sell => 0.0,
+            // the amount that reached the pair is what pays out `sold`
+            Some(sold) => {
+                let received = v2::get_amount_in(sold, reserve_token, reserve_base)?;
+                ((1.0 - u256_to_f64(received) / u256_to_f64(sell_amount)) * 100.0).max(0.0)
+            }
+            None => 100.0,
+        };
+        Ok(TokenTax {
+            token,
+            pair,
+            base_token,
+            can_buy: bought.is_some(),
+            can_sell: sold.is_some(),
+            buy_tax,
+            sell_tax,
+            block_number,
+        })
+    }
+}
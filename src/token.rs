@@ -0,0 +1,288 @@
+use ethers::{
+    abi::{AbiEncode, ParamType, Token, decode},
+    prelude::*,
+    types::transaction::eip2718::TypedTransaction,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock, RwLock},
+};
+
+use crate::{
+    abi::{DecimalsCall, NameCall, SymbolCall},
+    global::mainnet::{DAI_ADDRESS, USDC_ADDRESS, USDT_ADDRESS, WBTC_ADDRESS, WETH_ADDRESS},
+    tool::{block::block_or_latest, fs::write_atomic, rpc::is_revert},
+    types::{MetadataEncoding, TokenInfo, TokenMetadata, UniswapError},
+};
+
+/// decimals assumed for tokens that do not implement decimals()
+pub const DEFAULT_DECIMALS: u8 = 18;
+
+/// call a token getter, `None` when the call reverts. transport errors are returned.
+async fn call_getter<M: Middleware>(
+    provider: &M,
+    address: Address,
+    data: Vec<u8>,
+    block: Option<BlockId>,
+) -> Result<Option<Bytes>, UniswapError> {
+    let tx: TypedTransaction = TransactionRequest::new().to(address).data(data).into();
+    match provider.call(&tx, Some(block_or_latest(block))).await {
+        Ok(output) if output.is_empty() => Ok(None),
+        Ok(output) => Ok(Some(output)),
+        Err(e) if e.as_error_response().is_some_and(is_revert) => Ok(None),
+        Err(e) => Err(UniswapError::ProviderError(format!("Call error: {}", e))),
+    }
+}
+
+/// decode a string getter result that is either an ABI string or a bytes32
+pub fn decode_metadata_string(output: &[u8]) -> (String, MetadataEncoding) {
+    if output.len() >= 64
+        && let Ok(tokens) = decode(&[ParamType::String], output)
+        && let Some(Token::String(value)) = tokens.into_iter().next()
+    {
+        return (value, MetadataEncoding::String);
+    }
+    if output.len() == 32 {
+        let end = output.iter().position(|byte| *byte == 0).unwrap_or(32);
+        let value = String::from_utf8_lossy(&output[..end]).trim().to_string();
+        return (value, MetadataEncoding::Bytes32);
+    }
+    (String::new(), MetadataEncoding::Missing)
+}
+
+/// read symbol, name and decimals, tolerating bytes32 strings and reverting getters.
+/// fails only when the address answers none of them.
+pub async fn get_token_metadata<M: Middleware>(
+    provider: &M,
+    address: Address,
+    block: Option<BlockId>,
+) -> Result<TokenMetadata, UniswapError> {
+    let symbol = call_getter(provider, address, SymbolCall.encode(), block).await?;
+    let name = call_getter(provider, address, NameCall.encode(), block).await?;
+    let decimals = call_getter(provider, address, DecimalsCall.encode(), block).await?;
+    if symbol.is_none() && name.is_none() && decimals.is_none() {
+        return Err(UniswapError::ContractError(format!(
+            "{:?} is not an ERC20 token",
+            address
+        )));
+    }
+    let (symbol, symbol_encoding) = symbol
+        .map(|output| decode_metadata_string(&output))
+        .unwrap_or((String::new(), MetadataEncoding::Missing));
+    let (name, name_encoding) = name
+        .map(|output| decode_metadata_string(&output))
+        .unwrap_or((String::new(), MetadataEncoding::Missing));
+    // some tokens return decimals as uint256
+    let decimals = decimals
+        .filter(|output| output.len() >= 32)
+        .map(|output| U256::from_big_endian(&output[..32]))
+        .filter(|value| *value <= U256::from(u8::MAX))
+        .map(|value| value.as_u32() as u8);
+    Ok(TokenMetadata {
+        info: TokenInfo {
+            address,
+            symbol,
+            decimals: decimals.unwrap_or(DEFAULT_DECIMALS),
+            name,
+        },
+        symbol_encoding,
+        name_encoding,
+        missing_decimals: decimals.is_none(),
+    })
+}
+
+/// read the metadata of several tokens, a failing token does not abort the others.
+/// results are in the order of `addresses`.
+pub async fn get_tokens_metadata<M: Middleware>(
+    provider: &M,
+    addresses: &[Address],
+    block: Option<BlockId>,
+) -> Vec<Result<TokenMetadata, UniswapError>> {
+    let mut results = Vec::new();
+    for address in addresses {
+        results.push(get_token_metadata(provider, *address, block).await);
+    }
+    results
+}
+
+/// a cached token with the chain it lives on
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedToken {
+    chain_id: u64,
+    #[serde(flatten)]
+    token: TokenInfo,
+}
+
+/// thread-safe token metadata cache keyed by chain and address. token metadata is immutable,
+/// so entries never expire.
+#[derive(Debug, Default)]
+pub struct TokenCache {
+    tokens: RwLock<HashMap<(u64, Address), TokenInfo>>,
+    failures: RwLock<HashMap<(u64, Address), String>>, // lookups the contract answered with an error
+    chain_ids: RwLock<HashMap<String, u64>>,           // by provider url
+    path: Option<PathBuf>,
+}
+
+impl TokenCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// a cache persisted to `path`, loading the entries already saved there
+    pub fn new_with_path(path: impl AsRef<Path>) -> Result<Self, UniswapError> {
+        let path = path.as_ref().to_path_buf();
+        let cache = Self {
+            path: Some(path.clone()),
+            ..Self::default()
+        };
+        if path.exists() {
+            let json = fs::read(&path)
+                .map_err(|e| UniswapError::StorageError(format!("Read error: {}", e)))?;
+            let tokens: Vec<CachedToken> = serde_json::from_slice(&json)
+                .map_err(|e| UniswapError::StorageError(format!("Deserialize error: {}", e)))?;
+            for cached in tokens {
+                cache.insert(cached.chain_id, cached.token);
+            }
+        }
+        Ok(cache)
+    }
+
+    /// the process-wide cache used by default, seeded with the mainnet quote tokens
+    pub fn shared() -> Arc<TokenCache> {
+        static SHARED: OnceLock<Arc<TokenCache>> = OnceLock::new();
+        SHARED
+            .get_or_init(|| {
+                let cache = TokenCache::new();
+                cache.seed_mainnet();
+                Arc::new(cache)
+            })
+            .clone()
+    }
+
+    pub fn insert(&self, chain_id: u64, token: TokenInfo) {
+        self.failures
+            .write()
+            .unwrap()
+            .remove(&(chain_id, token.address));
+        self.tokens
+            .write()
+            .unwrap()
+            .insert((chain_id, token.address), token);
+    }
+
+    /// insert tokens known ahead of time
+    pub fn seed(&self, chain_id: u64, tokens: impl IntoIterator<Item = TokenInfo>) {
+        for token in tokens {
+            self.insert(chain_id, token);
+        }
+    }
+
+    /// seed WETH, USDC, USDT, DAI and WBTC on mainnet
+    pub fn seed_mainnet(&self) {
+        let tokens = [
+            (WETH_ADDRESS, "WETH", "Wrapped Ether", 18),
+            (USDC_ADDRESS, "USDC", "USD Coin", 6),
+            (USDT_ADDRESS, "USDT", "Tether USD", 6),
+            (DAI_ADDRESS, "DAI", "Dai Stablecoin", 18),
+            (WBTC_ADDRESS, "WBTC", "Wrapped BTC", 8),
+        ];
+        self.seed(
+            1,
+            tokens.map(|(address, symbol, name, decimals)| TokenInfo {
+                address: address.parse().unwrap(),
+                symbol: symbol.to_string(),
+                decimals,
+                name: name.to_string(),
+            }),
+        );
+    }
+
+    pub fn get(&self, chain_id: u64, address: Address) -> Option<TokenInfo> {
+        self.tokens
+            .read()
+            .unwrap()
+            .get(&(chain_id, address))
+            .cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.read().unwrap().is_empty()
+    }
+
+    /// the chain id of a provider, read once per provider url
+    pub async fn chain_id(&self, provider: &Provider<Http>) -> Result<u64, UniswapError> {
+        let url = provider.url().to_string();
+        if let Some(chain_id) = self.chain_ids.read().unwrap().get(&url) {
+            return Ok(*chain_id);
+        }
+        let chain_id = provider
+            .get_chainid()
+            .await
+            .map_err(UniswapError::EthersError)?
+            .as_u64();
+        self.chain_ids.write().unwrap().insert(url, chain_id);
+        Ok(chain_id)
+    }
+
+    /// the cached token info, fetched with `get_token_metadata` on a miss. tokens that are
+    /// not ERC20s or lack decimals are remembered and fail without another fetch, provider
+    /// errors are not cached.
+    pub async fn get_token_info(
+        &self,
+        provider: &Provider<Http>,
+        address: Address,
+        block: Option<BlockId>,
+    ) -> Result<TokenInfo, UniswapError> {
+        let chain_id = self.chain_id(provider).await?;
+        if let Some(token) = self.get(chain_id, address) {
+            return Ok(token);
+        }
+        if let Some(error) = self.failures.read().unwrap().get(&(chain_id, address)) {
+            return Err(UniswapError::ContractError(error.clone()));
+        }
+        let token = get_token_metadata(provider, address, block)
+            .await
+            .and_then(|metadata| metadata.into_token_info());
+        match token {
+            Ok(token) => {
+                self.insert(chain_id, token.clone());
+                Ok(token)
+            }
+            Err(UniswapError::ContractError(error)) => {
+                self.failures
+                    .write()
+                    .unwrap()
+                    .insert((chain_id, address), error.clone());
+                Err(UniswapError::ContractError(error))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// write the cache to its path, a no-op for caches without one
+    pub fn save(&self) -> Result<(), UniswapError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let tokens: Vec<CachedToken> = self
+            .tokens
+            .read()
+            .unwrap()
+            .iter()
+            .map(|((chain_id, _), token)| CachedToken {
+                chain_id: *chain_id,
+                token: token.clone(),
+            })
+            .collect();
+        let json = serde_json::to_vec(&tokens)
+            .map_err(|e| UniswapError::StorageError(format!("Serialize error: {}", e)))?;
+        write_atomic(path, &json)
+    }
+}
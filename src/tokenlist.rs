@@ -0,0 +1,344 @@
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use crate::{
+    token::TokenCache,
+    types::{QuoteAsset, TokenInfo, UniswapError},
+};
+
+/// most tokens a list may hold under the token list schema
+pub const MAX_LIST_TOKENS: usize = 10_000;
+
+/// semantic version of a token list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct TokenListVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+/// a tag a list defines for its tokens
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenListTag {
+    pub name: String,
+    pub description: String,
+}
+
+/// a token of a token list
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenListToken {
+    pub chain_id: u64,
+    pub address: Address,
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
+    #[serde(rename = "logoURI", default, skip_serializing_if = "Option::is_none")]
+    pub logo_uri: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extensions: Option<Value>,
+}
+
+impl TokenListToken {
+    pub fn token_info(&self) -> TokenInfo {
+        TokenInfo {
+            address: self.address,
+            symbol: self.symbol.clone(),
+            decimals: self.decimals,
+            name: self.name.clone(),
+        }
+    }
+
+    /// bridged addresses of the token by chain id, from the bridgeInfo extension
+    pub fn bridge_info(&self) -> HashMap<u64, Address> {
+        let mut bridged = HashMap::new();
+        let Some(Value::Object(bridge_info)) = self
+            .extensions
+            .as_ref()
+            .and_then(|extensions| extensions.get("bridgeInfo"))
+        else {
+            return bridged;
+        };
+        for (chain_id, info) in bridge_info {
+            if let Ok(chain_id) = chain_id.parse::<u64>()
+                && let Some(address) = info
+                    .get("tokenAddress")
+                    .and_then(Value::as_str)
+                    .and_then(|address| address.parse().ok())
+            {
+                bridged.insert(chain_id, address);
+            }
+        }
+        bridged
+    }
+}
+
+/// a Uniswap Token List, as the tokenlists.org schema
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenList {
+    pub name: String,
+    pub timestamp: String,
+    pub version: TokenListVersion,
+    pub tokens: Vec<TokenListToken>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub tags: HashMap<String, TokenListTag>,
+    #[serde(rename = "logoURI", default, skip_serializing_if = "Option::is_none")]
+    pub logo_uri: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+}
+
+impl TokenList {
+    /// parse and validate a token list
+    pub fn from_json(json: &[u8]) -> Result<Self, UniswapError> {
+        let list: Self = serde_json::from_slice(json)
+            .map_err(|e| UniswapError::TokenListError(format!("Deserialize error: {}", e)))?;
+        list.validate()?;
+        Ok(list)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, UniswapError> {
+        let json =
+            fs::read(path).map_err(|e| UniswapError::StorageError(format!("Read error: {}", e)))?;
+        Self::from_json(&json)
+    }
+
+    /// check the list against the schema rules serde does not enforce
+    pub fn validate(&self) -> Result<(), UniswapError> {
+        let mut problems = Vec::new();
+        if self.name.is_empty() || self.name.chars().count() > 30 {
+            problems.push(format!(
+                "list name {:?} must be 1 to 30 characters",
+                self.name
+            ));
+        }
+        if self.tokens.len() > MAX_LIST_TOKENS {
+            problems.push(format!(
+                "list has {} tokens, at most {} are allowed",
+                self.tokens.len(),
+                MAX_LIST_TOKENS
+            ));
+        }
+        let mut seen = HashSet::new();
+        for token in self.tokens.iter() {
+            if !seen.insert((token.chain_id, token.address)) {
+                problems.push(format!(
+                    "duplicate token {:?} on chain {}",
+                    token.address, token.chain_id
+                ));
+            }
+            if token.symbol.is_empty() || token.symbol.chars().count() > 20 {
+                problems.push(format!(
+                    "symbol {:?} must be 1 to 20 characters",
+                    token.symbol
+                ));
+            }
+            if token.name.is_empty() || token.name.chars().count() > 40 {
+                problems.push(format!("name {:?} must be 1 to 40 characters", token.name));
+            }
+            for tag in token.tags.iter() {
+                if !self.tags.contains_key(tag) {
+                    problems.push(format!("token {} uses undefined tag {}", token.symbol, tag));
+                }
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(UniswapError::TokenListError(problems.join(", ")))
+        }
+    }
+
+    /// the tokens of a chain
+    pub fn tokens_for_chain(&self, chain_id: u64) -> impl Iterator<Item = &TokenListToken> {
+        self.tokens
+            .iter()
+            .filter(move |token| token.chain_id == chain_id)
+    }
+}
+
+/// changes between two versions of a token list
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TokenListDiff {
+    pub added: Vec<TokenListToken>,
+    pub removed: Vec<TokenListToken>,
+    pub changed: Vec<(TokenListToken, TokenListToken)>, // (old, new)
+}
+
+impl TokenListDiff {
+    /// tokens are matched by chain and address
+    pub fn new(old: &TokenList, new: &TokenList) -> Self {
+        let old_tokens: HashMap<(u64, Address), &TokenListToken> = old
+            .tokens
+            .iter()
+            .map(|token| ((token.chain_id, token.address), token))
+            .collect();
+        let new_tokens: HashMap<(u64, Address), &TokenListToken> = new
+            .tokens
+            .iter()
+            .map(|token| ((token.chain_id, token.address), token))
+            .collect();
+        let mut diff = Self::default();
+        for token in new.tokens.iter() {
+            match old_tokens.get(&(token.chain_id, token.address)) {
+                None => diff.added.push(token.clone()),
+                Some(old_token) if *old_token != token => {
+                    diff.changed.push(((*old_token).clone(), token.clone()))
+                }
+                Some(_) => {}
+            }
+        }
+        for token in old.tokens.iter() {
+            if !new_tokens.contains_key(&(token.chain_id, token.address)) {
+                diff.removed.push(token.clone());
+            }
+        }
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// the smallest version the new list needs: removals are major, additions minor and
+    /// other changes patch
+    pub fn required_version(&self, old_version: TokenListVersion) -> TokenListVersion {
+        let TokenListVersion {
+            major,
+            minor,
+            patch,
+        } = old_version;
+        if !self.removed.is_empty() {
+            TokenListVersion {
+                major: major + 1,
+                minor: 0,
+                patch: 0,
+            }
+        } else if !self.added.is_empty() {
+            TokenListVersion {
+                major,
+                minor: minor + 1,
+                patch: 0,
+            }
+        } else if !self.changed.is_empty() {
+            TokenListVersion {
+                major,
+                minor,
+                patch: patch + 1,
+            }
+        } else {
+            old_version
+        }
+    }
+}
+
+/// tokens merged from several lists, searchable by chain, address and symbol.
+/// a token listed twice keeps the entry of the first list merged.
+#[derive(Debug, Clone, Default)]
+pub struct TokenRegistry {
+    tokens: HashMap<(u64, Address), TokenListToken>,
+    by_symbol: HashMap<(u64, String), Vec<Address>>, // upper case symbols
+}
+
+impl TokenRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// merge lists in order of precedence
+    pub fn new_with_lists(lists: &[TokenList]) -> Self {
+        let mut registry = Self::new();
+        for list in lists {
+            registry.merge(list);
+        }
+        registry
+    }
+
+    /// add the tokens of a list not already known, returns how many were added
+    pub fn merge(&mut self, list: &TokenList) -> usize {
+        let mut added = 0;
+        for token in list.tokens.iter() {
+            let key = (token.chain_id, token.address);
+            if self.tokens.contains_key(&key) {
+                continue;
+            }
+            self.by_symbol
+                .entry((token.chain_id, token.symbol.to_uppercase()))
+                .or_default()
+                .push(token.address);
+            self.tokens.insert(key, token.clone());
+            added += 1;
+        }
+        added
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    pub fn get(&self, chain_id: u64, address: Address) -> Option<&TokenListToken> {
+        self.tokens.get(&(chain_id, address))
+    }
+
+    /// tokens with a symbol, case insensitive. symbols are not unique across lists.
+    pub fn get_by_symbol(&self, chain_id: u64, symbol: &str) -> Vec<&TokenListToken> {
+        self.by_symbol
+            .get(&(chain_id, symbol.to_uppercase()))
+            .map(|addresses| {
+                addresses
+                    .iter()
+                    .filter_map(|address| self.get(chain_id, *address))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// the tokens of a chain as token infos
+    pub fn token_infos(&self, chain_id: u64) -> Vec<TokenInfo> {
+        self.tokens
+            .values()
+            .filter(|token| token.chain_id == chain_id)
+            .map(TokenListToken::token_info)
+            .collect()
+    }
+
+    /// seed a token cache with every listed token
+    pub fn seed_cache(&self, cache: &TokenCache) {
+        for token in self.tokens.values() {
+            cache.insert(token.chain_id, token.token_info());
+        }
+    }
+
+    /// quote assets for the listed tokens carrying `tag`, for example a stablecoin tag
+    pub fn quote_assets_with_tag(
+        &self,
+        chain_id: u64,
+        tag: &str,
+        usd_pegged: bool,
+        intermediate: bool,
+    ) -> Vec<QuoteAsset> {
+        let mut tokens: Vec<&TokenListToken> = self
+            .tokens
+            .values()
+            .filter(|token| token.chain_id == chain_id && token.tags.iter().any(|t| t == tag))
+            .collect();
+        tokens.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        tokens
+            .into_iter()
+            .map(|token| QuoteAsset::new(token.address, &token.symbol, usd_pegged, intermediate))
+            .collect()
+    }
+}
@@ -23,31 +23,74 @@ pub mod address {
         hex::decode_to_slice(clean, &mut bytes).map_err(|e| format!("Hex decode error: {}", e))?;
         Ok(H160::from(bytes))
     }
+}
 
-    /// verify address format
-    fn verify_address_format(address: &str) -> bool {
-        let trimmed = address.trim();
-        if trimmed.is_empty() {
-            return false;
-        }
-        let without_prefix =
-            if let Some(rest) = trimmed.strip_prefix("0x").or(trimmed.strip_prefix("0X")) {
-                rest
-            } else {
-                trimmed
-            };
-        if without_prefix.len() != 40 {
-            return false;
-        }
-        if !without_prefix.chars().all(|c| c.is_ascii_hexdigit()) {
-            return false;
+/// block tool module
+pub mod block {
+    use crate::types::UniswapError;
+    use ethers::{
+        providers::{Http, Middleware, Provider},
+        types::{BlockId, BlockNumber},
+    };
+
+    /// the block to read at, defaulting to the latest block.
+    pub fn block_or_latest(block: Option<BlockId>) -> BlockId {
+        block.unwrap_or(BlockId::Number(BlockNumber::Latest))
+    }
+
+    /// resolve a block tag to its number, so that several reads see the same block.
+    /// block numbers and hashes are returned unchanged.
+    pub async fn pin_block(
+        provider: &Provider<Http>,
+        block: Option<BlockId>,
+    ) -> Result<BlockId, UniswapError> {
+        match block {
+            Some(block @ (BlockId::Hash(_) | BlockId::Number(BlockNumber::Number(_)))) => Ok(block),
+            _ => {
+                let block = provider
+                    .get_block(block_or_latest(block))
+                    .await
+                    .map_err(UniswapError::EthersError)?
+                    .and_then(|block| block.number)
+                    .ok_or_else(|| UniswapError::ContractError("Block not found".to_string()))?;
+                Ok(BlockId::from(block.as_u64()))
+            }
         }
-        true
+    }
+}
+
+/// json-rpc tool module
+pub mod rpc {
+    use ethers::providers::JsonRpcError;
+
+    /// error code nodes answer reverted calls with
+    pub const REVERT_CODE: i64 = 3;
+
+    /// whether an error response reports a reverted call, as opposed to a node failure such
+    /// as a rate limit, a pruned state or an unsupported parameter.
+    pub fn is_revert(error: &JsonRpcError) -> bool {
+        error.code == REVERT_CODE || error.message.to_lowercase().contains("execution reverted")
     }
 }
 
 /// number tool module
 pub mod num {
+    use ethers::types::U256;
+
+    /// convert a U256 to f64 without overflowing on values above u128.
+    pub fn u256_to_f64(value: U256) -> f64 {
+        if value <= U256::from(u128::MAX) {
+            value.as_u128() as f64
+        } else {
+            value.to_string().parse::<f64>().unwrap_or(f64::INFINITY)
+        }
+    }
+
+    /// convert a raw token amount to a decimals adjusted f64.
+    pub fn u256_to_decimal(value: U256, decimals: u8) -> f64 {
+        u256_to_f64(value) / 10f64.powi(decimals as i32)
+    }
+
     /// format big numbers
     pub fn format_big_num(value: f64) -> String {
         if value >= 1_000_000_000.0 {
@@ -61,3 +104,109 @@ pub mod num {
         }
     }
 }
+
+/// transaction tool module
+pub mod tx {
+    use crate::{
+        abi::IERC20,
+        types::{TransactionResult, UniswapError},
+    };
+    use ethers::{prelude::*, types::transaction::eip2718::TypedTransaction};
+    use std::sync::Arc;
+
+    /// wallet backed client used to sign and send transactions
+    pub type WalletClient = SignerMiddleware<Provider<Http>, LocalWallet>;
+
+    /// create a wallet client, the chain id is read from the provider.
+    pub async fn wallet_client(
+        provider: Arc<Provider<Http>>,
+        wallet: LocalWallet,
+    ) -> Result<Arc<WalletClient>, UniswapError> {
+        let client = SignerMiddleware::new_with_provider_chain((*provider).clone(), wallet)
+            .await
+            .map_err(|e| UniswapError::WalletError(format!("Signer error: {}", e)))?;
+        Ok(Arc::new(client))
+    }
+
+    /// sign and send a transaction, then wait for its receipt.
+    pub async fn send_transaction(
+        client: Arc<WalletClient>,
+        tx: TypedTransaction,
+    ) -> Result<(TransactionResult, TransactionReceipt), UniswapError> {
+        let pending = client
+            .send_transaction(tx, None)
+            .await
+            .map_err(|e| UniswapError::TransactionFailed(format!("Send error: {}", e)))?;
+        let receipt = pending
+            .await
+            .map_err(UniswapError::EthersError)?
+            .ok_or_else(|| UniswapError::TransactionFailed("Transaction dropped".to_string()))?;
+        let result = TransactionResult {
+            tx_hash: receipt.transaction_hash,
+            status: receipt.status == Some(U64::one()),
+            gas_used: receipt.gas_used.unwrap_or_default(),
+            block_number: receipt.block_number.unwrap_or_default().as_u64(),
+        };
+        Ok((result, receipt))
+    }
+
+    /// approve `spender` for `amount` of `token`, and wait for the transaction to succeed.
+    pub async fn approve(
+        client: Arc<WalletClient>,
+        token: Address,
+        spender: Address,
+        amount: U256,
+    ) -> Result<TransactionResult, UniswapError> {
+        let tx = IERC20::new(token, client.clone())
+            .approve(spender, amount)
+            .tx;
+        let (result, _) = send_transaction(client, tx).await?;
+        if !result.status {
+            return Err(UniswapError::TransactionFailed(format!(
+                "Approve reverted: {:?}",
+                result.tx_hash
+            )));
+        }
+        Ok(result)
+    }
+
+    /// approve `spender` for exactly `amount` of `token` if the current allowance is lower.
+    /// a non-zero allowance is reset to zero first, as tokens like USDT reject changing it.
+    pub async fn ensure_allowance(
+        client: Arc<WalletClient>,
+        token: Address,
+        spender: Address,
+        amount: U256,
+    ) -> Result<Option<TransactionResult>, UniswapError> {
+        let contract = IERC20::new(token, client.clone());
+        let allowance = contract
+            .allowance(client.address(), spender)
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("Allowance error: {}", e)))?;
+        if allowance >= amount {
+            return Ok(None);
+        }
+        if !allowance.is_zero() {
+            approve(client.clone(), token, spender, U256::zero()).await?;
+        }
+        approve(client, token, spender, amount).await.map(Some)
+    }
+}
+
+/// file system tool module
+pub mod fs {
+    use crate::types::UniswapError;
+    use std::path::Path;
+
+    /// write `contents` to `path` through a temporary file renamed over it, so readers never
+    /// see a partial file.
+    pub fn write_atomic(path: impl AsRef<Path>, contents: &[u8]) -> Result<(), UniswapError> {
+        let path = path.as_ref();
+        let temp_path = path.with_extension("tmp");
+        std::fs::write(&temp_path, contents)
+            .map_err(|e| UniswapError::StorageError(format!("Write error: {}", e)))?;
+        std::fs::rename(&temp_path, path)
+            .map_err(|e| UniswapError::StorageError(format!("Rename error: {}", e)))
+    }
+}
@@ -1,6 +1,7 @@
 use ethers::abi::Error;
 use ethers::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use thiserror::Error;
 
 /// uniswap error type
@@ -24,6 +25,18 @@ pub enum UniswapError {
     InsufficientBalance,
     #[error("Wallet error: {0}")]
     WalletError(String),
+    #[error("Provider error: {0}")]
+    ProviderError(String),
+    #[error("Math error: {0}")]
+    MathError(String),
+    #[error("Storage error: {0}")]
+    StorageError(String),
+    #[error("Token list error: {0}")]
+    TokenListError(String),
+    #[error("Oracle error: {0}")]
+    OracleError(String),
+    #[error("Reorg error: {0}")]
+    ReorgError(String),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -98,22 +111,804 @@ pub struct TokenPriceInfo {
     pub token_symbol: String,
     pub token_name: String,
     pub decimals: u8,
-    pub eth_price: Option<f64>,  // 1 token = n ETH
-    pub usd_price: Option<f64>,  // 1 token = n USD
-    pub usdc_price: Option<f64>, // 1 token = n USDC
-    pub usdt_price: Option<f64>, // 1 token = n USDT
-    pub dai_price: Option<f64>,  // 1 token = n DAI
+    pub eth_price: Option<f64>,              // 1 token = n ETH
+    pub usd_price: Option<f64>,              // 1 token = n USD
+    pub usdc_price: Option<f64>,             // 1 token = n USDC
+    pub usdt_price: Option<f64>,             // 1 token = n USDT
+    pub dai_price: Option<f64>,              // 1 token = n DAI
+    pub quote_prices: HashMap<Address, f64>, // quote token -> 1 token = n quote token
     pub liquidity: U256,
     pub price_source: String,
     pub last_updated: u64,
+    pub policy: PricingPolicy,
+    pub provenance: Vec<PriceProvenance>,
 }
 
-#[derive(Debug, Clone)]
+/// which price a pricing policy reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PriceMode {
+    Spot,
+    Twap,
+}
+
+/// how the prices of several pools for the same pair are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PriceAggregation {
+    LiquidityWeightedMedian,
+    DeepestPool,
+}
+
+/// manipulation resistance rules applied to every pool a price is read from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PricingPolicy {
+    pub mode: PriceMode,
+    pub aggregation: PriceAggregation,
+    pub twap_window: u32,                // TWAP window in seconds
+    pub min_liquidity_usd: f64,          // minimum pool liquidity in USD
+    pub max_twap_deviation: Option<f64>, // maximum spot vs TWAP deviation percentage
+}
+
+impl Default for PricingPolicy {
+    fn default() -> Self {
+        Self {
+            mode: PriceMode::Spot,
+            aggregation: PriceAggregation::LiquidityWeightedMedian,
+            twap_window: 1800,
+            min_liquidity_usd: 0.0,
+            max_twap_deviation: None,
+        }
+    }
+}
+
+impl PricingPolicy {
+    /// whether pools need a TWAP read under this policy
+    pub fn needs_twap(&self) -> bool {
+        self.mode == PriceMode::Twap || self.max_twap_deviation.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PoolVersion {
+    V2,
+    V3,
+}
+
+/// a single pool's price of a token in a quote token, as evaluated by a pricing policy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolPrice {
+    pub pool: Address,
+    pub version: PoolVersion,
+    pub fee: Option<u32>,
+    pub token0: Address,
+    pub token1: Address,
+    pub reserve0: Option<U256>,       // V2 reserves used
+    pub reserve1: Option<U256>,       // V2 reserves used
+    pub liquidity: U256,              // V2 total supply, V3 in-range liquidity
+    pub sqrt_price_x96: Option<U256>, // V3 price used
+    pub spot_price: f64,
+    pub twap_price: Option<f64>,
+    pub price: f64,
+    pub quote_liquidity: f64, // quote token held by the pool, decimals adjusted
+    pub liquidity_usd: Option<f64>, // estimated pool liquidity in USD
+    pub accepted: bool,
+    pub reject_reason: Option<String>,
+}
+
+/// one leg of a price route, priced from the pools it lists
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceHop {
+    pub token_in: Address,
+    pub token_out: Address,
+    pub price: f64,                     // 1 token_in = n token_out
+    pub selected_pool: Option<Address>, // the pool whose price was reported
+    pub pools: Vec<PoolPrice>,
+}
+
+/// where a quoted price came from, detailed enough to reproduce it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceProvenance {
+    pub quote_symbol: String, // "ETH", a quote asset symbol or "USD"
+    pub quote_token: Address,
+    pub price: f64,
+    pub route: Vec<Address>, // e.g. token -> WETH -> USDC
+    pub hops: Vec<PriceHop>,
+    pub block_number: u64,
+    pub block_timestamp: u64,
+}
+
+/// a token prices are quoted in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuoteAsset {
+    pub address: Address,
+    pub symbol: String,
+    pub usd_pegged: bool,   // 1 unit is treated as 1 USD
+    pub intermediate: bool, // USD prices may be routed through this token
+}
+
+impl QuoteAsset {
+    pub fn new(address: Address, symbol: &str, usd_pegged: bool, intermediate: bool) -> Self {
+        Self {
+            address,
+            symbol: symbol.to_string(),
+            usd_pegged,
+            intermediate,
+        }
+    }
+}
+
+/// per chain pricing configuration, lists the quote and intermediate tokens prices are read against
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PriceFinder {
-    pub weth_address: Address,
-    pub usdc_address: Address,
-    pub usdt_address: Address,
-    pub dai_address: Address,
+    pub chain_id: u64,
+    pub weth_address: Address, // wrapped native token, always an intermediate
     pub factory_v2: Address,
     pub factory_v3: Address,
+    pub quote_assets: Vec<QuoteAsset>, // in order of preference for USD prices
+}
+
+impl PriceFinder {
+    /// ethereum mainnet configuration quoting in USDC, USDT and DAI
+    pub fn mainnet() -> Self {
+        use crate::global::mainnet::*;
+        Self {
+            chain_id: 1,
+            weth_address: WETH_ADDRESS.parse().unwrap(),
+            factory_v2: MAINNET_UNISWAP_V2_FACTORY_ADDRESS.parse().unwrap(),
+            factory_v3: MAINNET_UNISWAP_V3_FACTORY_ADDRESS.parse().unwrap(),
+            quote_assets: vec![
+                QuoteAsset::new(USDC_ADDRESS.parse().unwrap(), "USDC", true, false),
+                QuoteAsset::new(USDT_ADDRESS.parse().unwrap(), "USDT", true, false),
+                QuoteAsset::new(DAI_ADDRESS.parse().unwrap(), "DAI", true, false),
+            ],
+        }
+    }
+
+    /// add a quote asset
+    pub fn with_quote_asset(mut self, quote_asset: QuoteAsset) -> Self {
+        self.quote_assets.push(quote_asset);
+        self
+    }
+
+    /// the configured quote asset for an address
+    pub fn quote_asset(&self, address: Address) -> Option<&QuoteAsset> {
+        self.quote_assets
+            .iter()
+            .find(|quote_asset| quote_asset.address == address)
+    }
+
+    /// USD pegged quote assets in order of preference
+    pub fn usd_pegged(&self) -> impl Iterator<Item = &QuoteAsset> {
+        self.quote_assets
+            .iter()
+            .filter(|quote_asset| quote_asset.usd_pegged)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionInfo {
+    pub token_id: U256,
+    pub nonce: u128,
+    pub operator: Address,
+    pub token0: Address,
+    pub token1: Address,
+    pub fee: u32,
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    pub liquidity: u128,
+    pub fee_growth_inside0_last_x128: U256,
+    pub fee_growth_inside1_last_x128: U256,
+    pub tokens_owed0: u128,
+    pub tokens_owed1: u128,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MintRequest {
+    pub token0: Address,
+    pub token1: Address,
+    pub fee: u32,
+    pub tick_lower: i32, // ticks price token0 in token1, multiples of the pool tick spacing
+    pub tick_upper: i32,
+    pub amount0_desired: U256,
+    pub amount1_desired: U256,
+    pub recipient: Address,
+    pub deadline: u64,
+    pub slippage: f64, // slippage percentage
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IncreaseLiquidityRequest {
+    pub token_id: U256,
+    pub amount0_desired: U256,
+    pub amount1_desired: U256,
+    pub deadline: u64,
+    pub slippage: f64, // slippage percentage
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecreaseLiquidityRequest {
+    pub token_id: U256,
+    pub liquidity: u128,
+    pub deadline: u64,
+    pub slippage: f64, // slippage percentage
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollectRequest {
+    pub token_id: U256,
+    pub recipient: Address,
+    pub amount0_max: u128,
+    pub amount1_max: u128,
+}
+
+/// decoded IncreaseLiquidity / DecreaseLiquidity event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiquidityChange {
+    pub token_id: U256,
+    pub liquidity: u128,
+    pub amount0: U256,
+    pub amount1: U256,
+}
+
+/// decoded Collect event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollectedFees {
+    pub token_id: U256,
+    pub recipient: Address,
+    pub amount0: U256,
+    pub amount1: U256,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionTransactionResult {
+    pub transaction: TransactionResult,
+    pub increase_liquidity: Vec<LiquidityChange>,
+    pub decrease_liquidity: Vec<LiquidityChange>,
+    pub collect: Vec<CollectedFees>,
+}
+
+/// position manager events of a position, in chain order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionHistory {
+    pub token_id: U256,
+    pub first_block: Option<u64>,
+    pub increase_liquidity: Vec<LiquidityChange>,
+    pub decrease_liquidity: Vec<LiquidityChange>,
+    pub collect: Vec<CollectedFees>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddLiquidityRequest {
+    pub token_a: Address,
+    pub token_b: Address,
+    pub amount_a_desired: U256,
+    pub amount_b_desired: U256,
+    pub recipient: Address,
+    pub deadline: u64,
+    pub slippage: f64, // slippage percentage
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoveLiquidityRequest {
+    pub token_a: Address,
+    pub token_b: Address,
+    pub liquidity: U256,
+    pub recipient: Address,
+    pub deadline: u64,
+    pub slippage: f64, // slippage percentage
+}
+
+/// offline quote of a V2 addLiquidity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddLiquidityQuote {
+    pub amount_a: U256,
+    pub amount_b: U256,
+    pub amount_a_min: U256,
+    pub amount_b_min: U256,
+    pub liquidity_minted: U256,
+    pub protocol_fee_liquidity: U256,
+    pub pool_share: f64,
+}
+
+/// offline quote of a V2 removeLiquidity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoveLiquidityQuote {
+    pub amount_a: U256,
+    pub amount_b: U256,
+    pub amount_a_min: U256,
+    pub amount_b_min: U256,
+    pub protocol_fee_liquidity: U256,
+}
+
+/// V2 pair price cumulative observation, cumulatives are UQ112x112 seconds-weighted sums
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct V2Observation {
+    pub pair: Address,
+    pub block_number: u64,
+    pub timestamp: u64,
+    pub price0_cumulative: U256,
+    pub price1_cumulative: U256,
+}
+
+/// V2 time weighted average price between two observations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct V2Twap {
+    pub pair: Address,
+    pub start_timestamp: u64,
+    pub end_timestamp: u64,
+    pub price0_average_x112: U256, // token0 priced in token1, UQ112x112
+    pub price1_average_x112: U256, // token1 priced in token0, UQ112x112
+    pub price0: f64,               // 1 token0 = n token1, decimals adjusted
+    pub price1: f64,               // 1 token1 = n token0, decimals adjusted
+}
+
+/// V3 pool oracle buffer state
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct V3OracleState {
+    pub pool: Address,
+    pub observation_index: u16,
+    pub observation_cardinality: u16,
+    pub observation_cardinality_next: u16,
+    pub oldest_observation_seconds_ago: u32,
+}
+
+/// V3 time weighted average over a window, as OracleLibrary.consult
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct V3Twap {
+    pub pool: Address,
+    pub seconds_ago: u32,
+    pub arithmetic_mean_tick: i32,
+    pub harmonic_mean_liquidity: u128,
+    pub price0: f64, // 1 token0 = n token1, decimals adjusted
+    pub price1: f64, // 1 token1 = n token0, decimals adjusted
+}
+
+/// a block every read of a computation is pinned to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockSnapshot {
+    pub block_number: u64,
+    pub block_timestamp: u64,
+}
+
+impl BlockSnapshot {
+    pub fn new(block_number: u64, block_timestamp: u64) -> Self {
+        Self {
+            block_number,
+            block_timestamp,
+        }
+    }
+
+    /// the block id to pin contract calls to
+    pub fn block_id(&self) -> BlockId {
+        BlockId::Number(BlockNumber::Number(self.block_number.into()))
+    }
+
+    /// the block id wrapped for read methods taking an optional block
+    pub fn block(&self) -> Option<BlockId> {
+        Some(self.block_id())
+    }
+}
+
+/// candle interval
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CandleInterval {
+    Minute1,
+    Minute5,
+    Hour1,
+    Day1,
+    Seconds(u64),
+}
+
+impl CandleInterval {
+    /// interval length in seconds
+    pub fn seconds(&self) -> u64 {
+        match self {
+            CandleInterval::Minute1 => 60,
+            CandleInterval::Minute5 => 300,
+            CandleInterval::Hour1 => 3600,
+            CandleInterval::Day1 => 86400,
+            CandleInterval::Seconds(seconds) => (*seconds).max(1),
+        }
+    }
+
+    /// the open time of the candle containing `timestamp`
+    pub fn open_time(&self, timestamp: u64) -> u64 {
+        timestamp - timestamp % self.seconds()
+    }
+}
+
+/// a trade decoded from a swap event, priced as base token in quote token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PricePoint {
+    pub pool: Address,
+    pub version: PoolVersion,
+    pub tx_hash: TxHash,
+    pub block_number: u64,
+    pub log_index: u64,
+    pub timestamp: u64,
+    pub price: f64,        // execution price, 1 base = n quote, decimals adjusted
+    pub base_amount: f64,  // decimals adjusted
+    pub quote_amount: f64, // decimals adjusted
+}
+
+/// OHLCV candle of a pool, priced as base token in quote token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Candle {
+    pub pool: Address,
+    pub base_token: Address,
+    pub quote_token: Address,
+    pub interval: u64, // seconds
+    pub open_time: u64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume_base: f64,
+    pub volume_quote: f64,
+    pub volume_usd: Option<f64>,
+    pub trades: u64,
+}
+
+/// pool analytics over a block window, volumes, fees and balances are decimals adjusted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolStats {
+    pub pool_info: PoolInfo,
+    pub version: PoolVersion,
+    pub fee: u32, // hundredths of a bip, 3000 = 0.3%
+    pub start_block: u64,
+    pub end_block: u64,
+    pub window_seconds: u64,
+    pub swap_count: u64,
+    pub volume0: f64,
+    pub volume1: f64,
+    pub volume_usd: Option<f64>,
+    pub fees0: f64,
+    pub fees1: f64,
+    pub fees_usd: Option<f64>,
+    pub balance0: f64,
+    pub balance1: f64,
+    pub token0_usd_price: Option<f64>,
+    pub token1_usd_price: Option<f64>,
+    pub tvl_usd: Option<f64>,
+    pub fee_apr: Option<f64>, // percentage, fees annualized over TVL
+    pub in_range_liquidity: Option<u128>, // V3 only
+    // V3 only, annualized USD fees per unit of liquidity, assuming the in-range liquidity at
+    // the end of the window was in range for the whole window
+    pub annual_fees_usd_per_liquidity: Option<f64>,
+}
+
+/// LP entry, amounts are decimals adjusted and price is token0 in token1
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LpEntry {
+    pub amount0: f64, // deposited
+    pub amount1: f64, // deposited
+    pub price: f64,
+    pub block_number: Option<u64>,
+}
+
+/// current LP state, amounts are decimals adjusted and price is token0 in token1
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LpState {
+    pub price: f64,
+    pub amount0: f64, // principal, including withdrawn principal
+    pub amount1: f64, // principal, including withdrawn principal
+    pub fees0: f64,   // collected and uncollected
+    pub fees1: f64,   // collected and uncollected
+}
+
+/// LP performance report, values are in the quote token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LpPnl {
+    pub version: PoolVersion,
+    pub quote_token: Address,
+    pub entry: LpEntry,
+    pub state: LpState,
+    pub entry_value: f64,          // deposits at the entry price
+    pub hodl_value: f64,           // deposits at the current price
+    pub position_value: f64,       // principal at the current price
+    pub fees_value: f64,           // fees at the current price
+    pub impermanent_loss: f64,     // position value - hodl value
+    pub impermanent_loss_pct: f64, // percentage of hodl value
+    pub net_pnl: f64,              // position value + fees - entry value
+    pub net_pnl_pct: f64,          // percentage of entry value
+}
+
+/// net liquidity added when an initialized tick is crossed left to right
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TickLiquidity {
+    pub tick: i32,
+    pub liquidity_net: i128,
+}
+
+fn default_tick_lower() -> i32 {
+    crate::math::tick::MIN_TICK
+}
+
+fn default_tick_upper() -> i32 {
+    crate::math::tick::MAX_TICK
+}
+
+/// V3 pool state needed to simulate swaps, `ticks` are the initialized ticks in ascending order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct V3PoolState {
+    pub pool: Address,
+    pub token0: Address,
+    pub token1: Address,
+    pub fee: u32,
+    pub tick_spacing: i32,
+    pub sqrt_price_x96: U256,
+    pub tick: i32,
+    pub liquidity: u128,
+    pub ticks: Vec<TickLiquidity>,
+    // the initialized ticks are loaded between these ticks, swaps stop at them
+    #[serde(default = "default_tick_lower")]
+    pub tick_lower: i32,
+    #[serde(default = "default_tick_upper")]
+    pub tick_upper: i32,
+}
+
+/// result of a simulated V3 swap
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwapSimulation {
+    pub amount_in: U256, // including fees
+    pub amount_out: U256,
+    pub fee_amount: U256,
+    pub sqrt_price_x96_after: U256,
+    pub tick_after: i32,
+    pub liquidity_after: u128,
+    pub ticks_crossed: u32,
+    pub partial: bool, // stopped at the edge of the loaded ticks before the amount was filled
+}
+
+/// liquidity of a pool for depth calculations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PoolLiquidity {
+    V2(PoolInfo),
+    V3(V3PoolState),
+}
+
+/// a trade against the pool liquidity of a pair, amounts are decimals adjusted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DepthPoint {
+    pub price_impact: f64, // percentage move of the marginal price
+    pub amount_in: f64,
+    pub amount_out: f64,
+    pub execution_price: f64, // token0 in token1
+    pub slippage: f64,        // percentage of the execution price from the spot price
+}
+
+/// market depth of a pair, selling token0 moves the price of token0 down and buying moves it up
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketDepth {
+    pub token0: Address,
+    pub token1: Address,
+    pub pools: Vec<Address>,
+    pub spot_price: f64,       // token0 in token1, of the deepest pool
+    pub sell: Vec<DepthPoint>, // token0 in, token1 out
+    pub buy: Vec<DepthPoint>,  // token1 in, token0 out
+    pub sell_curve: Vec<DepthPoint>,
+    pub buy_curve: Vec<DepthPoint>,
+}
+
+/// a pool known to the registry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegisteredPool {
+    pub address: Address,
+    pub version: PoolVersion,
+    pub token0: Address,
+    pub token1: Address,
+    pub fee: u32,                  // hundredths of a bip, 3000 for V2
+    pub tick_spacing: Option<i32>, // V3 only
+    pub created_block: Option<u64>,
+}
+
+/// a JSON-RPC error response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpcErrorResponse {
+    pub code: i64,
+    pub message: String,
+    pub data: Option<serde_json::Value>,
+}
+
+/// a recorded JSON-RPC request and its response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpcRecord {
+    pub method: String,
+    pub params: serde_json::Value,
+    pub result: Option<serde_json::Value>,
+    pub error: Option<RpcErrorResponse>,
+}
+
+/// how a token metadata string was read
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MetadataEncoding {
+    String,
+    Bytes32, // non-standard, as MKR and SAI
+    Missing, // reverted or returned no data
+}
+
+/// token metadata read tolerantly from a possibly non-standard ERC20
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenMetadata {
+    pub info: TokenInfo, // missing strings are empty, missing decimals default to 18
+    pub symbol_encoding: MetadataEncoding,
+    pub name_encoding: MetadataEncoding,
+    pub missing_decimals: bool,
+}
+
+impl TokenMetadata {
+    /// whether every getter behaves as the ERC20 standard
+    pub fn is_standard(&self) -> bool {
+        self.symbol_encoding == MetadataEncoding::String
+            && self.name_encoding == MetadataEncoding::String
+            && !self.missing_decimals
+    }
+
+    /// the token info, failing when decimals are missing since amounts cannot be scaled
+    pub fn into_token_info(self) -> Result<TokenInfo> {
+        if self.missing_decimals {
+            return Err(UniswapError::ContractError(format!(
+                "Decimals error: {:?} does not implement decimals()",
+                self.info.address
+            )));
+        }
+        Ok(self.info)
+    }
+}
+
+/// transfer taxes of a token measured by simulating swaps against its pair
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenTax {
+    pub token: Address,
+    pub pair: Address,
+    pub base_token: Address, // the other token of the pair
+    pub can_buy: bool,
+    pub can_sell: bool,
+    pub buy_tax: f64,  // percentage of the bought amount withheld
+    pub sell_tax: f64, // percentage of the sold amount withheld
+    pub block_number: u64,
+}
+
+impl TokenTax {
+    /// whether swaps of the token need the fee-on-transfer router functions
+    pub fn is_fee_on_transfer(&self) -> bool {
+        self.buy_tax > 0.0 || self.sell_tax > 0.0
+    }
+}
+
+/// quote of a V2 swap, net of token transfer taxes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwapQuote {
+    pub path: Vec<Address>,
+    pub amount_in: U256,
+    pub amounts: Vec<U256>, // amount received at each step of the path, after taxes
+    pub amount_out: U256,
+    pub amount_out_min: U256,
+    pub fee_on_transfer: bool, // the swap must use the SupportingFeeOnTransferTokens functions
+    pub warnings: Vec<String>, // tokens whose tax could not be detected and is assumed zero
+}
+
+/// EIP-1967 proxy slots of a contract, all empty for contracts that are not proxies
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProxyInfo {
+    pub implementation: Option<Address>,
+    pub admin: Option<Address>,
+    pub beacon: Option<Address>,
+}
+
+impl ProxyInfo {
+    pub fn is_proxy(&self) -> bool {
+        self.implementation.is_some() || self.beacon.is_some()
+    }
+}
+
+/// privileged functions found in a token's bytecode
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TokenCapabilities {
+    pub owner: Option<Address>, // None when the token has no owner()
+    pub can_mint: bool,
+    pub can_pause: bool,
+    pub can_blacklist: bool,
+    pub can_set_fees: bool,
+    pub functions: Vec<String>, // signatures of the privileged functions found
+}
+
+impl TokenCapabilities {
+    /// whether ownership was given up to the zero or dead address
+    pub fn is_renounced(&self) -> bool {
+        self.owner == Some(Address::zero())
+            || self.owner == Some(crate::risk::DEAD_ADDRESS.parse().unwrap())
+    }
+}
+
+/// how much of a pair's LP supply is burned or held by lockers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiquidityLock {
+    pub pair: Address,
+    pub total_supply: U256,
+    pub burned: U256,      // held by the zero and dead addresses
+    pub locked: U256,      // held by known locker contracts
+    pub locked_share: f64, // share of the supply burned or locked, 0 to 1
+}
+
+/// the largest holders found for a token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HolderConcentration {
+    pub total_supply: U256,
+    pub top_holders: Vec<(Address, U256)>, // largest balance first
+    pub top_share: f64,                    // share of the supply held by the top holders, 0 to 1
+    pub from_block: u64,                   // first block scanned for holders
+}
+
+/// a risk flagged by the token risk scanner
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TokenRisk {
+    NoLiquidity,
+    CannotBuy,
+    CannotSell,
+    HighTax,
+    Mintable,
+    Pausable,
+    Blacklist,
+    MutableFees,
+    Upgradeable,
+    UnlockedLiquidity,
+    ConcentratedHolders,
+}
+
+/// risk report of a token ahead of trading it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenRiskReport {
+    pub token: TokenInfo,
+    pub pools: Vec<PoolInfo>,  // deepest first
+    pub tax: Option<TokenTax>, // simulated against the deepest pool
+    pub capabilities: TokenCapabilities,
+    pub proxy: ProxyInfo,
+    pub liquidity_lock: Option<LiquidityLock>,
+    pub holders: Option<HolderConcentration>,
+    pub risks: Vec<TokenRisk>,
+    pub block_number: u64,
+}
+
+impl TokenRiskReport {
+    /// a token that can be bought but not sold
+    pub fn is_honeypot(&self) -> bool {
+        self.risks.contains(&TokenRisk::CannotSell)
+    }
+}
+
+/// a profitable round trip between two pools of the same pair: buy `token_out` with
+/// `token_in` on `buy_pool` and sell it back on `sell_pool`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArbitrageOpportunity {
+    pub token_in: Address,
+    pub token_out: Address,
+    pub buy_pool: Address,
+    pub sell_pool: Address,
+    pub amount_in: U256,           // profit-maximizing input
+    pub intermediate_amount: U256, // token_out bought and sold
+    pub amount_out: U256,
+    pub profit: U256,             // amount_out - amount_in, in token_in
+    pub gas_units: u64,           // estimated gas of the two swaps
+    pub gas_cost: Option<U256>,   // in token_in, None without a WETH price for it
+    pub net_profit: Option<I256>, // profit after gas, in token_in
+    pub block_number: u64,
+}
+
+/// a closed path of swaps starting and ending at `tokens[0]`, `pools[i]` swaps `tokens[i]`
+/// for `tokens[i + 1]`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArbitrageCycle {
+    pub tokens: Vec<Address>,
+    pub pools: Vec<Address>,
+}
+
+/// a profitable cycle sized by exact simulation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CycleOpportunity {
+    pub cycle: ArbitrageCycle,
+    pub amount_in: U256,    // profit-maximizing input of tokens[0]
+    pub amounts: Vec<U256>, // amount held after each swap
+    pub profit: U256,       // in tokens[0]
+    pub gas_units: u64,
+    pub block_number: u64,
 }
@@ -1,78 +1,72 @@
 use crate::{
-    abi::{IERC20, IUniswapV2Pair},
+    abi::IUniswapV2Pair,
     events::EVENT_SWAP,
-    types::{PoolInfo, PriceData, TokenInfo, UniswapError},
+    oracle::{compute_twap, current_cumulative_prices},
+    token::TokenCache,
+    tool::block::{block_or_latest, pin_block},
+    types::{PoolInfo, PriceData, TokenInfo, UniswapError, V2Observation, V2Twap},
 };
 use ethers::{prelude::*, types::Filter};
 use std::sync::Arc;
 
 pub struct UniswapV2 {
     pub provider: Arc<Provider<Http>>,
+    pub tokens: Arc<TokenCache>,
 }
 
 impl UniswapV2 {
-    pub fn new(provider_url: &str) -> Result<Self, ()> {
+    pub fn new(provider_url: &str) -> Result<Self, UniswapError> {
         let provider = Provider::<Http>::try_from(provider_url)
-            .map_err(|e| {
-                println!("error:{:?}", e);
-            })
-            .unwrap();
-        Ok(Self {
-            provider: Arc::new(provider),
-        })
+            .map_err(|e| UniswapError::ProviderError(format!("{:?}", e)))?;
+        Ok(Self::new_with_provider(Arc::new(provider)))
     }
 
-    /// get token info
-    pub async fn get_token_info(&self, address: Address) -> Result<TokenInfo, UniswapError> {
-        let contract = IERC20::new(address, self.provider.clone());
-        let symbol = contract
-            .symbol()
-            .call()
-            .await
-            .map_err(|e| UniswapError::ContractError(format!("Symbol error: {}", e)))?;
-        let name = contract
-            .name()
-            .call()
-            .await
-            .map_err(|e| UniswapError::ContractError(format!("Name error: {}", e)))?;
-        let decimals = contract
-            .decimals()
-            .call()
+    /// get token info from the token cache, symbol and name may be bytes32 or missing but
+    /// decimals are required
+    pub async fn get_token_info(
+        &self,
+        address: Address,
+        block: Option<BlockId>,
+    ) -> Result<TokenInfo, UniswapError> {
+        self.tokens
+            .get_token_info(self.provider.as_ref(), address, block)
             .await
-            .map_err(|e| UniswapError::ContractError(format!("Decimals error: {}", e)))?;
-        Ok(TokenInfo {
-            address,
-            symbol,
-            name,
-            decimals,
-        })
     }
 
-    /// get liquid pool info
-    pub async fn get_pool_info(&self, pool_address: Address) -> Result<PoolInfo, UniswapError> {
+    /// get liquid pool info, every read is pinned to `block`
+    pub async fn get_pool_info(
+        &self,
+        pool_address: Address,
+        block: Option<BlockId>,
+    ) -> Result<PoolInfo, UniswapError> {
+        let block = Some(pin_block(&self.provider, block).await?);
         let pair_contract = IUniswapV2Pair::new(pool_address, self.provider.clone());
         let token0_addr = pair_contract
             .token_0()
+            .block(block_or_latest(block))
             .call()
             .await
             .map_err(|e| UniswapError::ContractError(format!("Token0 error: {}", e)))?;
         let token1_addr = pair_contract
             .token_1()
+            .block(block_or_latest(block))
             .call()
             .await
             .map_err(|e| UniswapError::ContractError(format!("Token1 error: {}", e)))?;
         let (reserve0, reserve1, _) = pair_contract
             .get_reserves()
+            .block(block_or_latest(block))
             .call()
             .await
             .map_err(|e| UniswapError::ContractError(format!("Reserves error: {}", e)))?;
         let liquidity = pair_contract
             .total_supply()
+            .block(block_or_latest(block))
             .call()
             .await
             .map_err(|e| UniswapError::ContractError(format!("Total supply error: {}", e)))?;
-        let token0 = self.get_token_info(token0_addr).await?;
-        let token1 = self.get_token_info(token1_addr).await?;
+        let token0 = self.get_token_info(token0_addr, block).await?;
+        let token1 = self.get_token_info(token1_addr, block).await?;
         Ok(PoolInfo {
             address: pool_address,
             token0,
@@ -88,8 +82,9 @@ impl UniswapV2 {
         &self,
         pool_address: Address,
         base_token: Address,
+        block: Option<BlockId>,
     ) -> Result<PriceData, UniswapError> {
-        let pool_info = self.get_pool_info(pool_address).await?;
+        let pool_info = self.get_pool_info(pool_address, block).await?;
         let (reserve_base, reserve_quote) = if pool_info.token0.address == base_token {
             (pool_info.reserve0, pool_info.reserve1)
         } else if pool_info.token1.address == base_token {
@@ -110,6 +105,94 @@ impl UniswapV2 {
         })
     }
 
+    /// get reserves and the timestamp of the last reserve update
+    pub async fn get_reserves(
+        &self,
+        pool_address: Address,
+        block: Option<BlockId>,
+    ) -> Result<(U256, U256, u32), UniswapError> {
+        let pair_contract = IUniswapV2Pair::new(pool_address, self.provider.clone());
+        let (reserve0, reserve1, block_timestamp_last) = pair_contract
+            .get_reserves()
+            .block(block_or_latest(block))
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("Reserves error: {}", e)))?;
+        Ok((reserve0.into(), reserve1.into(), block_timestamp_last))
+    }
+
+    /// get the price cumulatives of a pair at a block, accrued up to that block's timestamp
+    pub async fn get_observation(
+        &self,
+        pool_address: Address,
+        block: Option<BlockId>,
+    ) -> Result<V2Observation, UniswapError> {
+        let block_data = self
+            .provider
+            .get_block(block_or_latest(block))
+            .await
+            .map_err(UniswapError::EthersError)?
+            .ok_or_else(|| UniswapError::ContractError("Block not found".to_string()))?;
+        let block_number = block_data.number.unwrap_or_default();
+        // pin every read to the resolved block so the observation is consistent
+        let pinned = BlockId::Number(BlockNumber::Number(block_number));
+        let pair_contract = IUniswapV2Pair::new(pool_address, self.provider.clone());
+        let price0_cumulative_last = pair_contract
+            .price_0_cumulative_last()
+            .block(pinned)
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("Price0 cumulative error: {}", e)))?;
+        let price1_cumulative_last = pair_contract
+            .price_1_cumulative_last()
+            .block(pinned)
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("Price1 cumulative error: {}", e)))?;
+        let (reserve0, reserve1, block_timestamp_last) =
+            self.get_reserves(pool_address, Some(pinned)).await?;
+        let timestamp = block_data.timestamp.as_u64();
+        let (price0_cumulative, price1_cumulative) = current_cumulative_prices(
+            price0_cumulative_last,
+            price1_cumulative_last,
+            reserve0,
+            reserve1,
+            block_timestamp_last,
+            timestamp,
+        );
+        Ok(V2Observation {
+            pair: pool_address,
+            block_number: block_number.as_u64(),
+            timestamp,
+            price0_cumulative,
+            price1_cumulative,
+        })
+    }
+
+    /// get the time weighted average price of a pair between two blocks
+    pub async fn get_twap_between_blocks(
+        &self,
+        pool_address: Address,
+        start_block: u64,
+        end_block: u64,
+    ) -> Result<V2Twap, UniswapError> {
+        let pool_info = self
+            .get_pool_info(pool_address, Some(end_block.into()))
+            .await?;
+        let start = self
+            .get_observation(pool_address, Some(start_block.into()))
+            .await?;
+        let end = self
+            .get_observation(pool_address, Some(end_block.into()))
+            .await?;
+        compute_twap(
+            &start,
+            &end,
+            pool_info.token0.decimals,
+            pool_info.token1.decimals,
+        )
+    }
+
     /// monitor the latest transactions
     pub async fn listen_swaps(&self, pool_address: Address) -> Result<(), UniswapError> {
         let filter = Filter::new().address(pool_address).event(EVENT_SWAP);
@@ -117,7 +200,7 @@ impl UniswapV2 {
             .provider
             .watch(&filter)
             .await
-            .map_err(|e| UniswapError::EthersError(e))?;
+            .map_err(UniswapError::EthersError)?;
         println!("Listening for swaps on pool: {:?}", pool_address);
         while let Some(log) = stream.next().await {
             println!("New swap detected: {:?}", log);
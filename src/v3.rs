@@ -1,25 +1,28 @@
-use ethers::prelude::*;
+use ethers::{prelude::*, types::transaction::eip2718::TypedTransaction};
 use std::sync::Arc;
 
 use crate::{
-    abi::{IERC20, IUniswapV3Pool},
-    types::{PoolInfo, PriceData, TokenInfo, UniswapError},
+    abi::IUniswapV3Pool,
+    math::tick::{MAX_TICK, MIN_TICK, tick_to_price},
+    oracle::consult_cumulatives,
+    token::TokenCache,
+    tool::block::{block_or_latest, pin_block},
+    types::{
+        PoolInfo, PriceData, TickLiquidity, TokenInfo, UniswapError, V3OracleState, V3PoolState,
+        V3Twap,
+    },
 };
 
 pub struct UniswapV3 {
     pub provider: Arc<Provider<Http>>,
+    pub tokens: Arc<TokenCache>,
 }
 
 impl UniswapV3 {
-    pub fn new(provider_url: &str) -> Result<Self, ()> {
+    pub fn new(provider_url: &str) -> Result<Self, UniswapError> {
         let provider = Provider::<Http>::try_from(provider_url)
-            .map_err(|e| {
-                println!("provider connection error:{:?}", e);
-            })
-            .unwrap();
-        Ok(Self {
-            provider: Arc::new(provider),
-        })
+            .map_err(|e| UniswapError::ProviderError(format!("{:?}", e)))?;
+        Ok(Self::new_with_provider(Arc::new(provider)))
     }
 
     /// get price by token0 token1
@@ -28,10 +31,13 @@ impl UniswapV3 {
         pool_address: Address,
         token_a: &TokenInfo,
         token_b: &TokenInfo,
+        block: Option<BlockId>,
     ) -> Result<f64, UniswapError> {
+        let block = Some(pin_block(&self.provider, block).await?);
         let pool_contract = IUniswapV3Pool::new(pool_address, self.provider.clone());
         let (sqrt_price_x96, _, _, _, _, _, _) = pool_contract
             .slot_0()
+            .block(block_or_latest(block))
             .call()
             .await
             .map_err(|e| UniswapError::ContractError(format!("Slot0 error: {}", e)))?;
@@ -40,6 +46,7 @@ impl UniswapV3 {
         let price_ratio = (sqrt_price / 2.0_f64.powi(96)).powi(2);
         let token0 = pool_contract
             .token_0()
+            .block(block_or_latest(block))
             .call()
             .await
             .map_err(|e| UniswapError::ContractError(format!("Token0 error: {}", e)))?;
@@ -53,33 +60,42 @@ impl UniswapV3 {
         Ok(adjusted_price)
     }
 
-    /// get liquid pool info
-    pub async fn get_pool_info(&self, pool_address: Address) -> Result<PoolInfo, UniswapError> {
+    /// get liquid pool info, every read is pinned to `block`
+    pub async fn get_pool_info(
+        &self,
+        pool_address: Address,
+        block: Option<BlockId>,
+    ) -> Result<PoolInfo, UniswapError> {
+        let block = Some(pin_block(&self.provider, block).await?);
         let pool_contract = IUniswapV3Pool::new(pool_address, self.provider.clone());
         let token0_addr = pool_contract
             .token_0()
+            .block(block_or_latest(block))
             .call()
             .await
             .map_err(|e| UniswapError::ContractError(format!("Token0 error: {}", e)))?;
         let token1_addr = pool_contract
             .token_1()
+            .block(block_or_latest(block))
             .call()
             .await
             .map_err(|e| UniswapError::ContractError(format!("Token1 error: {}", e)))?;
         let liquidity = pool_contract
             .liquidity()
+            .block(block_or_latest(block))
             .call()
             .await
             .map_err(|e| UniswapError::ContractError(format!("Liquidity error: {}", e)))?;
         let (sqrt_price_x96, _, _, _, _, _, _) = pool_contract
             .slot_0()
+            .block(block_or_latest(block))
             .call()
             .await
             .map_err(|e| UniswapError::ContractError(format!("Slot0 error: {}", e)))?;
         // price
         let _price = (sqrt_price_x96.as_u128() as f64).powf(2.0) / (2.0_f64.powf(192.0));
-        let token0 = self.get_token_info(token0_addr).await?;
-        let token1 = self.get_token_info(token1_addr).await?;
+        let token0 = self.get_token_info(token0_addr, block).await?;
+        let token1 = self.get_token_info(token1_addr, block).await?;
         Ok(PoolInfo {
             address: pool_address,
             token0,
@@ -90,53 +106,71 @@ impl UniswapV3 {
         })
     }
 
-    /// get token info
-    pub async fn get_token_info(&self, address: Address) -> Result<TokenInfo, UniswapError> {
-        let contract = IERC20::new(address, self.provider.clone());
-        let symbol = contract
-            .symbol()
+    /// get the current sqrt price, tick and in-range liquidity of a pool
+    pub async fn get_pool_state(
+        &self,
+        pool_address: Address,
+        block: Option<BlockId>,
+    ) -> Result<(U256, i32, u128), UniswapError> {
+        let block = Some(pin_block(&self.provider, block).await?);
+        let pool_contract = IUniswapV3Pool::new(pool_address, self.provider.clone());
+        let (sqrt_price_x96, tick, _, _, _, _, _) = pool_contract
+            .slot_0()
+            .block(block_or_latest(block))
             .call()
             .await
-            .map_err(|e| UniswapError::ContractError(format!("Symbol error: {}", e)))?;
-        let name = contract
-            .name()
+            .map_err(|e| UniswapError::ContractError(format!("Slot0 error: {}", e)))?;
+        let liquidity = pool_contract
+            .liquidity()
+            .block(block_or_latest(block))
             .call()
             .await
-            .map_err(|e| UniswapError::ContractError(format!("Name error: {}", e)))?;
-        let decimals = contract
-            .decimals()
-            .call()
+            .map_err(|e| UniswapError::ContractError(format!("Liquidity error: {}", e)))?;
+        Ok((sqrt_price_x96, tick, liquidity))
+    }
+
+    /// get token info from the token cache, symbol and name may be bytes32 or missing but
+    /// decimals are required
+    pub async fn get_token_info(
+        &self,
+        address: Address,
+        block: Option<BlockId>,
+    ) -> Result<TokenInfo, UniswapError> {
+        self.tokens
+            .get_token_info(self.provider.as_ref(), address, block)
             .await
-            .map_err(|e| UniswapError::ContractError(format!("Decimals error: {}", e)))?;
-        Ok(TokenInfo {
-            address,
-            symbol,
-            name,
-            decimals,
-        })
     }
 
     /// get price
-    pub async fn get_price(&self, pool_address: Address) -> Result<PriceData, UniswapError> {
+    pub async fn get_price(
+        &self,
+        pool_address: Address,
+        block: Option<BlockId>,
+    ) -> Result<PriceData, UniswapError> {
+        let block = Some(pin_block(&self.provider, block).await?);
         let pool_contract = IUniswapV3Pool::new(pool_address, self.provider.clone());
         let token0 = pool_contract
             .token_0()
+            .block(block_or_latest(block))
             .call()
             .await
             .map_err(|e| UniswapError::ContractError(format!("Token0 error: {}", e)))?;
         let token1 = pool_contract
             .token_1()
+            .block(block_or_latest(block))
             .call()
             .await
             .map_err(|e| UniswapError::ContractError(format!("Token1 error: {}", e)))?;
         let (sqrt_price_x96, _, _, _, _, _, _) = pool_contract
             .slot_0()
+            .block(block_or_latest(block))
             .call()
             .await
             .map_err(|e| UniswapError::ContractError(format!("Slot0 error: {}", e)))?;
         let price = (sqrt_price_x96.as_u128() as f64).powf(2.0) / (2.0_f64.powf(192.0));
         let liquidity = pool_contract
             .liquidity()
+            .block(block_or_latest(block))
             .call()
             .await
             .map_err(|e| UniswapError::ContractError(format!("Liquidity error: {}", e)))?;
@@ -147,4 +181,214 @@ impl UniswapV3 {
             liquidity: liquidity.into(),
         })
     }
+
+    /// get cumulative tick and seconds per liquidity values for each of `seconds_agos`
+    pub async fn observe(
+        &self,
+        pool_address: Address,
+        seconds_agos: Vec<u32>,
+        block: Option<BlockId>,
+    ) -> Result<(Vec<i64>, Vec<U256>), UniswapError> {
+        let pool_contract = IUniswapV3Pool::new(pool_address, self.provider.clone());
+        pool_contract
+            .observe(seconds_agos)
+            .block(block_or_latest(block))
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("Observe error: {}", e)))
+    }
+
+    /// get the oracle buffer state at `block`, including how far back the oldest observation reaches
+    pub async fn get_oracle_state(
+        &self,
+        pool_address: Address,
+        block: Option<BlockId>,
+    ) -> Result<V3OracleState, UniswapError> {
+        // pin every read to one block so the age is measured against the same state
+        let block = Some(pin_block(&self.provider, block).await?);
+        let block_data = self
+            .provider
+            .get_block(block_or_latest(block))
+            .await
+            .map_err(UniswapError::EthersError)?
+            .ok_or_else(|| UniswapError::ContractError("Block not found".to_string()))?;
+        let pool_contract = IUniswapV3Pool::new(pool_address, self.provider.clone());
+        let (_, _, observation_index, observation_cardinality, observation_cardinality_next, _, _) =
+            pool_contract
+                .slot_0()
+                .block(block_or_latest(block))
+                .call()
+                .await
+                .map_err(|e| UniswapError::ContractError(format!("Slot0 error: {}", e)))?;
+        if observation_cardinality == 0 {
+            return Err(UniswapError::OracleError(
+                "Pool oracle is not initialized".to_string(),
+            ));
+        }
+        // the slot after the current index is the oldest once the buffer has wrapped
+        let next_index = (observation_index as u64 + 1) % observation_cardinality as u64;
+        let (mut observation_timestamp, _, _, initialized) = pool_contract
+            .observations(next_index.into())
+            .block(block_or_latest(block))
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("Observations error: {}", e)))?;
+        if !initialized {
+            (observation_timestamp, _, _, _) = pool_contract
+                .observations(U256::zero())
+                .block(block_or_latest(block))
+                .call()
+                .await
+                .map_err(|e| UniswapError::ContractError(format!("Observations error: {}", e)))?;
+        }
+        let now = block_data.timestamp.as_u64() as u32;
+        Ok(V3OracleState {
+            pool: pool_address,
+            observation_index,
+            observation_cardinality,
+            observation_cardinality_next,
+            oldest_observation_seconds_ago: now.wrapping_sub(observation_timestamp),
+        })
+    }
+
+    /// get the time weighted average tick, price and harmonic mean liquidity over the
+    /// `seconds_ago` seconds ending at `block`, failing if the pool's observations do not
+    /// reach that far back
+    pub async fn consult(
+        &self,
+        pool_address: Address,
+        seconds_ago: u32,
+        block: Option<BlockId>,
+    ) -> Result<V3Twap, UniswapError> {
+        // the coverage check and the observations must see the same block
+        let block = Some(pin_block(&self.provider, block).await?);
+        let oracle_state = self.get_oracle_state(pool_address, block).await?;
+        if oracle_state.oldest_observation_seconds_ago < seconds_ago {
+            return Err(UniswapError::OracleError(format!(
+                "Oracle covers {}s, {}s requested, increase observation cardinality",
+                oracle_state.oldest_observation_seconds_ago, seconds_ago
+            )));
+        }
+        let (tick_cumulatives, seconds_per_liquidity) = self
+            .observe(pool_address, vec![seconds_ago, 0], block)
+            .await?;
+        if tick_cumulatives.len() != 2 || seconds_per_liquidity.len() != 2 {
+            return Err(UniswapError::ContractError(
+                "Unexpected observe result".to_string(),
+            ));
+        }
+        let (arithmetic_mean_tick, harmonic_mean_liquidity) = consult_cumulatives(
+            [tick_cumulatives[0], tick_cumulatives[1]],
+            [seconds_per_liquidity[0], seconds_per_liquidity[1]],
+            seconds_ago,
+        )?;
+        let pool_info = self.get_pool_info(pool_address, block).await?;
+        let price0 = tick_to_price(
+            arithmetic_mean_tick,
+            pool_info.token0.decimals,
+            pool_info.token1.decimals,
+        );
+        Ok(V3Twap {
+            pool: pool_address,
+            seconds_ago,
+            arithmetic_mean_tick,
+            harmonic_mean_liquidity,
+            price0,
+            price1: if price0 > 0.0 { 1.0 / price0 } else { 0.0 },
+        })
+    }
+
+    /// get the pool state with the initialized ticks within `tick_range` ticks of the current
+    /// tick, enough to simulate swaps that move the price up to 1.0001^tick_range
+    pub async fn get_tick_state(
+        &self,
+        pool_address: Address,
+        tick_range: i32,
+        block: Option<BlockId>,
+    ) -> Result<V3PoolState, UniswapError> {
+        let block = Some(pin_block(&self.provider, block).await?);
+        let pool_contract = IUniswapV3Pool::new(pool_address, self.provider.clone());
+        let (sqrt_price_x96, tick, liquidity) = self.get_pool_state(pool_address, block).await?;
+        let token0 = pool_contract
+            .token_0()
+            .block(block_or_latest(block))
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("Token0 error: {}", e)))?;
+        let token1 = pool_contract
+            .token_1()
+            .block(block_or_latest(block))
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("Token1 error: {}", e)))?;
+        let fee = pool_contract
+            .fee()
+            .block(block_or_latest(block))
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("Fee error: {}", e)))?;
+        let tick_spacing = pool_contract
+            .tick_spacing()
+            .block(block_or_latest(block))
+            .call()
+            .await
+            .map_err(|e| UniswapError::ContractError(format!("Tick spacing error: {}", e)))?;
+        // each bitmap word tracks 256 compressed ticks
+        let word_position = |tick: i32| (tick.div_euclid(tick_spacing) >> 8) as i16;
+        let first_word = word_position((tick - tick_range).max(MIN_TICK));
+        let last_word = word_position((tick + tick_range).min(MAX_TICK));
+        let mut ticks = Vec::new();
+        for word in first_word..=last_word {
+            let bitmap = pool_contract
+                .tick_bitmap(word)
+                .block(block_or_latest(block))
+                .call()
+                .await
+                .map_err(|e| UniswapError::ContractError(format!("Tick bitmap error: {}", e)))?;
+            for bit in 0..256 {
+                if !bitmap.bit(bit) {
+                    continue;
+                }
+                let initialized_tick = ((word as i32) * 256 + bit as i32) * tick_spacing;
+                let (_, liquidity_net, _, _, _, _, _, _) = pool_contract
+                    .ticks(initialized_tick)
+                    .block(block_or_latest(block))
+                    .call()
+                    .await
+                    .map_err(|e| UniswapError::ContractError(format!("Ticks error: {}", e)))?;
+                ticks.push(TickLiquidity {
+                    tick: initialized_tick,
+                    liquidity_net,
+                });
+            }
+        }
+        // liquidity is only known up to the ticks of the next unloaded words
+        let tick_lower = (first_word as i32 * 256 * tick_spacing).max(MIN_TICK);
+        let tick_upper = ((last_word as i32 + 1) * 256 * tick_spacing).min(MAX_TICK);
+        Ok(V3PoolState {
+            pool: pool_address,
+            token0,
+            token1,
+            fee,
+            tick_spacing,
+            sqrt_price_x96,
+            tick,
+            liquidity,
+            ticks,
+            tick_lower,
+            tick_upper,
+        })
+    }
+
+    /// build increaseObservationCardinalityNext transaction to grow the oracle buffer
+    pub fn build_increase_observation_cardinality_next_tx(
+        &self,
+        pool_address: Address,
+        observation_cardinality_next: u16,
+    ) -> TypedTransaction {
+        let pool_contract = IUniswapV3Pool::new(pool_address, self.provider.clone());
+        pool_contract
+            .increase_observation_cardinality_next(observation_cardinality_next)
+            .tx
+    }
 }
@@ -0,0 +1,142 @@
+use ethers::{
+    abi::{Token, encode},
+    contract::EthEvent,
+    providers::{Http, Provider},
+    types::{Address, H256, Log, U64, U256},
+};
+use std::sync::Arc;
+use uniswap_sdk::{
+    abi::SyncFilter,
+    cycle::CycleScanner,
+    state::PoolStateManager,
+    types::{PoolInfo, PoolLiquidity, TokenInfo},
+};
+
+const START_BLOCK: u64 = 100;
+
+fn address(n: u64) -> Address {
+    Address::from_low_u64_be(n)
+}
+
+fn ether(amount: u64) -> U256 {
+    U256::from(amount) * U256::exp10(18)
+}
+
+fn token(n: u64) -> TokenInfo {
+    TokenInfo {
+        address: address(n),
+        symbol: format!("T{}", n),
+        decimals: 18,
+        name: format!("Token {}", n),
+    }
+}
+
+fn pair(address_n: u64, token0: u64, token1: u64, reserve0: u64, reserve1: u64) -> PoolLiquidity {
+    PoolLiquidity::V2(PoolInfo {
+        address: address(address_n),
+        token0: token(token0),
+        token1: token(token1),
+        reserve0: ether(reserve0),
+        reserve1: ether(reserve1),
+        liquidity: ether(1_000),
+    })
+}
+
+fn sync_log(pair: Address, block_number: u64, reserve0: U256, reserve1: U256) -> Log {
+    Log {
+        address: pair,
+        topics: vec![SyncFilter::signature()],
+        data: encode(&[Token::Uint(reserve0), Token::Uint(reserve1)]).into(),
+        block_number: Some(U64::from(block_number)),
+        block_hash: Some(H256::from_low_u64_be(block_number)),
+        ..Default::default()
+    }
+}
+
+/// two triangles through token 1, each pricing its last token 10% above the others.
+/// `last_pool` is the address of the second triangle's closing pool.
+fn state(last_pool: u64) -> PoolStateManager {
+    let provider = Arc::new(Provider::<Http>::try_from("http://127.0.0.1:1").unwrap());
+    let mut state = PoolStateManager::new(provider);
+    for pool in [
+        pair(10, 1, 2, 1_000, 1_000),
+        pair(11, 2, 3, 1_000, 1_000),
+        pair(12, 1, 3, 1_100, 1_000),
+        pair(20, 1, 4, 1_000, 1_000),
+        pair(21, 4, 5, 1_000, 1_000),
+        pair(last_pool, 1, 5, 1_100, 1_000),
+    ] {
+        state.insert_pool(pool);
+    }
+    state.last_block = Some(START_BLOCK);
+    state
+}
+
+fn opportunity_block(scanner: &CycleScanner, pool: Address) -> Option<u64> {
+    scanner
+        .opportunities()
+        .into_iter()
+        .find(|opportunity| opportunity.cycle.pools.contains(&pool))
+        .map(|opportunity| opportunity.block_number)
+}
+
+#[test]
+fn scan_finds_triangle() {
+    let state = state(22);
+    let mut scanner = CycleScanner::new(vec![address(1)]);
+    let opportunities = scanner.scan(&state);
+    // each triangle in both directions
+    assert_eq!(scanner.cycles().len(), 4);
+    assert_eq!(opportunities.len(), 2);
+    for opportunity in opportunities.iter() {
+        let cycle = &opportunity.cycle;
+        assert_eq!(cycle.tokens.first(), Some(&address(1)));
+        assert_eq!(cycle.tokens.last(), Some(&address(1)));
+        assert_eq!(cycle.pools.len(), 3);
+        assert!(opportunity.profit > U256::zero());
+        let amounts = &opportunity.amounts;
+        assert_eq!(amounts[0], opportunity.amount_in);
+        assert_eq!(amounts[3] - amounts[0], opportunity.profit);
+        assert_eq!(opportunity.block_number, START_BLOCK);
+    }
+    // the profitable direction buys the underpriced token with token 1 first
+    let first = opportunities
+        .iter()
+        .find(|opportunity| opportunity.cycle.pools.contains(&address(10)))
+        .unwrap();
+    assert_eq!(
+        first.cycle.tokens,
+        vec![address(1), address(2), address(3), address(1)]
+    );
+}
+
+#[test]
+fn update_evaluates_changed_pools() {
+    let mut state = state(22);
+    let mut scanner = CycleScanner::new(vec![address(1)]);
+    scanner.scan(&state);
+    state
+        .apply_log(&sync_log(address(10), 101, ether(1_000), ether(1_010)))
+        .unwrap();
+    state.last_block = Some(101);
+    scanner.update(&state);
+    // only the triangle through the synced pair is re-evaluated
+    assert_eq!(opportunity_block(&scanner, address(10)), Some(101));
+    assert_eq!(opportunity_block(&scanner, address(20)), Some(START_BLOCK));
+}
+
+#[test]
+fn update_rebuilds_when_pools_are_replaced() {
+    let mut scanner = CycleScanner::new(vec![address(1)]);
+    scanner.scan(&state(22));
+    // as many pools, but one of them is another pool
+    let state = state(23);
+    scanner.update(&state);
+    assert!(
+        scanner
+            .cycles()
+            .iter()
+            .all(|cycle| !cycle.pools.contains(&address(22)))
+    );
+    assert!(opportunity_block(&scanner, address(23)).is_some());
+}
@@ -0,0 +1,127 @@
+use ethers::types::{Address, U256};
+use uniswap_sdk::{
+    arbitrage::pool_capacity,
+    depth::{amounts_to_price_impact, pool_amount_out},
+    math::{
+        tick::{MAX_TICK, MIN_TICK, get_sqrt_ratio_at_tick},
+        v2,
+    },
+    types::{PoolInfo, PoolLiquidity, TickLiquidity, TokenInfo, UniswapError, V3PoolState},
+};
+
+const LIQUIDITY: u128 = 1_000_000_000_000_000_000;
+
+fn token0() -> Address {
+    Address::from_low_u64_be(1)
+}
+
+fn token1() -> Address {
+    Address::from_low_u64_be(2)
+}
+
+fn ether(amount: u64) -> U256 {
+    U256::from(amount) * U256::exp10(18)
+}
+
+fn token(address: Address) -> TokenInfo {
+    TokenInfo {
+        address,
+        symbol: String::new(),
+        decimals: 18,
+        name: String::new(),
+    }
+}
+
+fn v2_pool() -> PoolLiquidity {
+    PoolLiquidity::V2(PoolInfo {
+        address: Address::from_low_u64_be(3),
+        token0: token(token0()),
+        token1: token(token1()),
+        reserve0: ether(100),
+        reserve1: ether(200),
+        liquidity: U256::zero(),
+    })
+}
+
+/// a 0.3% pool at tick 0 with `LIQUIDITY` over every tick, loaded within `window`
+fn v3_pool(window: (i32, i32)) -> PoolLiquidity {
+    PoolLiquidity::V3(V3PoolState {
+        pool: Address::from_low_u64_be(3),
+        token0: token0(),
+        token1: token1(),
+        fee: 3000,
+        tick_spacing: 60,
+        sqrt_price_x96: get_sqrt_ratio_at_tick(0).unwrap(),
+        tick: 0,
+        liquidity: LIQUIDITY,
+        ticks: vec![
+            TickLiquidity {
+                tick: -887220,
+                liquidity_net: LIQUIDITY as i128,
+            },
+            TickLiquidity {
+                tick: 887220,
+                liquidity_net: -(LIQUIDITY as i128),
+            },
+        ],
+        tick_lower: window.0,
+        tick_upper: window.1,
+    })
+}
+
+#[test]
+fn v2_amount_out() {
+    let pool = v2_pool();
+    assert_eq!(
+        pool_amount_out(&pool, token0(), ether(1)).unwrap(),
+        v2::get_amount_out(ether(1), ether(100), ether(200)).unwrap()
+    );
+    assert!(matches!(
+        pool_amount_out(&pool, Address::from_low_u64_be(9), ether(1)),
+        Err(UniswapError::PoolNotFound)
+    ));
+}
+
+#[test]
+fn v3_amount_out_within_window() {
+    let pool = v3_pool((-600, 600));
+    let amount_out = pool_amount_out(&pool, token0(), U256::exp10(15)).unwrap();
+    assert!(amount_out < U256::exp10(15) * 997 / 1000);
+    // beyond the window the liquidity is unknown, the swap is not filled partially
+    assert!(matches!(
+        pool_amount_out(&pool, token0(), ether(1_000)),
+        Err(UniswapError::MathError(_))
+    ));
+    let pool = v3_pool((MIN_TICK, MAX_TICK));
+    assert!(pool_amount_out(&pool, token0(), ether(1_000)).is_ok());
+}
+
+#[test]
+fn v3_capacity() {
+    let pool = v3_pool((-600, 600));
+    let capacity = pool_capacity(&pool, token0()).unwrap();
+    assert!(!capacity.is_zero());
+    // the capacity moves the price to the window edge and fills
+    assert!(pool_amount_out(&pool, token0(), capacity).is_ok());
+    assert!(pool_amount_out(&pool, token0(), capacity + U256::exp10(12)).is_err());
+    assert_eq!(pool_capacity(&v2_pool(), token1()).unwrap(), ether(200));
+}
+
+#[test]
+fn price_impact() {
+    for pool in [v2_pool(), v3_pool((MIN_TICK, MAX_TICK))] {
+        let (small_in, small_out) = amounts_to_price_impact(&pool, true, 1.0).unwrap();
+        let (large_in, large_out) = amounts_to_price_impact(&pool, true, 5.0).unwrap();
+        assert!(small_in > 0.0 && small_out > 0.0);
+        assert!(large_in > small_in && large_out > small_out);
+        assert!(amounts_to_price_impact(&pool, true, 100.0).is_err());
+        assert!(amounts_to_price_impact(&pool, false, 0.0).is_err());
+    }
+    // a 5% move runs past a window of about 1%
+    let pool = v3_pool((-120, 120));
+    assert!(amounts_to_price_impact(&pool, true, 1.0).is_ok());
+    assert!(matches!(
+        amounts_to_price_impact(&pool, true, 5.0),
+        Err(UniswapError::MathError(_))
+    ));
+}
@@ -0,0 +1,333 @@
+use ethers::types::{Address, I256, U256};
+use uniswap_sdk::{
+    arbitrage::v2_optimal_amount_in,
+    math::{
+        swap::simulate_swap,
+        tick::{
+            MAX_TICK, MIN_TICK, get_sqrt_ratio_at_tick, get_tick_at_sqrt_ratio, max_sqrt_ratio,
+            min_sqrt_ratio, nearest_usable_tick, price_to_tick, tick_to_price,
+        },
+        v2::{
+            MINIMUM_LIQUIDITY, get_amount_in, get_amount_out, get_liquidity_minted,
+            get_liquidity_value, get_optimal_amounts, quote,
+        },
+    },
+    types::{TickLiquidity, UniswapError, V3PoolState},
+};
+
+const LIQUIDITY: u128 = 1_000_000_000_000_000_000;
+
+fn ether(amount: u64) -> U256 {
+    U256::from(amount) * U256::exp10(18)
+}
+
+/// a 0.3% pool at tick 0 with `LIQUIDITY` between ticks -600 and 600, loaded within `window`
+fn pool(window: (i32, i32)) -> V3PoolState {
+    V3PoolState {
+        pool: Address::zero(),
+        token0: Address::from_low_u64_be(1),
+        token1: Address::from_low_u64_be(2),
+        fee: 3000,
+        tick_spacing: 60,
+        sqrt_price_x96: get_sqrt_ratio_at_tick(0).unwrap(),
+        tick: 0,
+        liquidity: LIQUIDITY,
+        ticks: vec![
+            TickLiquidity {
+                tick: -600,
+                liquidity_net: LIQUIDITY as i128,
+            },
+            TickLiquidity {
+                tick: 600,
+                liquidity_net: -(LIQUIDITY as i128),
+            },
+        ],
+        tick_lower: window.0,
+        tick_upper: window.1,
+    }
+}
+
+#[test]
+fn sqrt_ratio_at_tick() {
+    assert_eq!(get_sqrt_ratio_at_tick(0).unwrap(), U256::one() << 96);
+    assert_eq!(get_sqrt_ratio_at_tick(MIN_TICK).unwrap(), min_sqrt_ratio());
+    assert_eq!(get_sqrt_ratio_at_tick(MAX_TICK).unwrap(), max_sqrt_ratio());
+    assert!(matches!(
+        get_sqrt_ratio_at_tick(MAX_TICK + 1),
+        Err(UniswapError::MathError(_))
+    ));
+}
+
+#[test]
+fn tick_at_sqrt_ratio() {
+    for tick in [MIN_TICK, -200_000, -1, 0, 1, 60, 200_000, MAX_TICK - 1] {
+        let sqrt_price_x96 = get_sqrt_ratio_at_tick(tick).unwrap();
+        assert_eq!(get_tick_at_sqrt_ratio(sqrt_price_x96).unwrap(), tick);
+        assert_eq!(get_tick_at_sqrt_ratio(sqrt_price_x96 + 1).unwrap(), tick);
+    }
+    assert!(get_tick_at_sqrt_ratio(max_sqrt_ratio()).is_err());
+    assert!(get_tick_at_sqrt_ratio(min_sqrt_ratio() - 1).is_err());
+}
+
+#[test]
+fn usable_ticks() {
+    assert_eq!(nearest_usable_tick(89, 60), 60);
+    assert_eq!(nearest_usable_tick(91, 60), 120);
+    assert_eq!(nearest_usable_tick(-91, 60), -120);
+    assert_eq!(nearest_usable_tick(MIN_TICK, 60), -887220);
+    assert_eq!(nearest_usable_tick(MAX_TICK, 60), 887220);
+}
+
+#[test]
+fn price_ticks() {
+    // 1 ETH = 2000 USDC, token0 WETH with 18 decimals and token1 USDC with 6
+    let tick = price_to_tick(2000.0, 18, 6);
+    assert!(tick_to_price(tick, 18, 6) <= 2000.0);
+    assert!(tick_to_price(tick + 1, 18, 6) > 2000.0);
+    assert_eq!(price_to_tick(1.0001f64.powf(100.5), 18, 18), 100);
+    assert_eq!(price_to_tick(1.0001f64.powf(-100.5), 18, 18), -101);
+}
+
+#[test]
+fn swap_exact_input() {
+    let pool = pool((MIN_TICK, MAX_TICK));
+    let amount_in = U256::exp10(15);
+    let swap = simulate_swap(&pool, true, I256::from_raw(amount_in), None).unwrap();
+    assert_eq!(swap.amount_in, amount_in);
+    assert_eq!(swap.fee_amount, amount_in * 3 / 1000);
+    // the price moves against the swap, out is below the fee adjusted spot amount
+    let spot_out = amount_in * 997 / 1000;
+    assert!(swap.amount_out < spot_out);
+    assert!(swap.amount_out > spot_out * 999 / 1000);
+    assert!(swap.sqrt_price_x96_after < pool.sqrt_price_x96);
+    assert_eq!(swap.ticks_crossed, 0);
+    assert_eq!(swap.liquidity_after, LIQUIDITY);
+    assert!(!swap.partial);
+}
+
+#[test]
+fn swap_exact_output() {
+    let pool = pool((MIN_TICK, MAX_TICK));
+    let amount_out = U256::exp10(15);
+    let swap = simulate_swap(&pool, false, -I256::from_raw(amount_out), None).unwrap();
+    assert_eq!(swap.amount_out, amount_out);
+    assert!(swap.amount_in > amount_out * 1000 / 997);
+    assert!(swap.sqrt_price_x96_after > pool.sqrt_price_x96);
+    assert!(!swap.partial);
+}
+
+#[test]
+fn swap_price_limit() {
+    let pool = pool((MIN_TICK, MAX_TICK));
+    let limit = get_sqrt_ratio_at_tick(-10).unwrap();
+    let swap = simulate_swap(&pool, true, I256::from_raw(ether(1_000)), Some(limit)).unwrap();
+    assert_eq!(swap.sqrt_price_x96_after, limit);
+    assert_eq!(swap.tick_after, -10);
+    // stopping at the limit fills the swap as far as the caller allowed
+    assert!(!swap.partial);
+    assert!(simulate_swap(&pool, false, I256::from_raw(ether(1)), Some(limit)).is_err());
+    assert!(simulate_swap(&pool, true, I256::zero(), None).is_err());
+}
+
+#[test]
+fn swap_crosses_ticks() {
+    let pool = pool((MIN_TICK, MAX_TICK));
+    let swap = simulate_swap(&pool, true, I256::from_raw(ether(1_000)), None).unwrap();
+    assert_eq!(swap.ticks_crossed, 1);
+    assert_eq!(swap.liquidity_after, 0);
+    assert!(swap.tick_after < -600);
+    // with no liquidity below the range the price runs to the default limit, as onchain
+    assert_eq!(swap.sqrt_price_x96_after, min_sqrt_ratio() + 1);
+    assert!(!swap.partial);
+    assert!(swap.amount_in < ether(1_000));
+}
+
+#[test]
+fn swap_stops_at_window_edge() {
+    let pool = pool((-300, 300));
+    let swap = simulate_swap(&pool, false, I256::from_raw(ether(1_000)), None).unwrap();
+    assert!(swap.partial);
+    assert_eq!(swap.ticks_crossed, 0);
+    assert_eq!(swap.tick_after, 300);
+    assert_eq!(
+        swap.sqrt_price_x96_after,
+        get_sqrt_ratio_at_tick(300).unwrap()
+    );
+    assert_eq!(swap.liquidity_after, LIQUIDITY);
+    // the same swap within the window is filled
+    let small = simulate_swap(&pool, false, I256::from_raw(U256::exp10(15)), None).unwrap();
+    assert!(!small.partial);
+}
+
+#[test]
+fn v2_quote() {
+    assert_eq!(quote(ether(1), ether(2), ether(4)).unwrap(), ether(2));
+    // the product exceeds 256 bits but the quote does not
+    assert_eq!(
+        quote(U256::MAX / 2, U256::MAX / 2, ether(1)).unwrap(),
+        ether(1)
+    );
+    assert!(matches!(
+        quote(U256::MAX, U256::one(), U256::from(2)),
+        Err(UniswapError::MathError(_))
+    ));
+    assert!(quote(U256::zero(), ether(1), ether(1)).is_err());
+    assert!(quote(ether(1), U256::zero(), ether(1)).is_err());
+}
+
+#[test]
+fn v2_amounts() {
+    let (reserve_in, reserve_out) = (U256::from(1_000_000), U256::from(1_000_000));
+    assert_eq!(
+        get_amount_out(U256::from(1_000), reserve_in, reserve_out).unwrap(),
+        U256::from(996)
+    );
+    for amount_out in [1u64, 996, 500_000] {
+        let amount_out = U256::from(amount_out);
+        let amount_in = get_amount_in(amount_out, reserve_in, reserve_out).unwrap();
+        assert!(get_amount_out(amount_in, reserve_in, reserve_out).unwrap() >= amount_out);
+        assert!(get_amount_out(amount_in - 1, reserve_in, reserve_out).unwrap() < amount_out);
+    }
+    assert!(get_amount_in(reserve_out, reserve_in, reserve_out).is_err());
+    assert!(get_amount_out(U256::zero(), reserve_in, reserve_out).is_err());
+}
+
+#[test]
+fn v2_optimal_amounts() {
+    // a fresh pair takes the desired amounts
+    assert_eq!(
+        get_optimal_amounts(ether(1), ether(3), U256::zero(), U256::zero()).unwrap(),
+        (ether(1), ether(3))
+    );
+    assert_eq!(
+        get_optimal_amounts(ether(1), ether(3), ether(10), ether(20)).unwrap(),
+        (ether(1), ether(2))
+    );
+    assert_eq!(
+        get_optimal_amounts(ether(1), ether(1), ether(10), ether(20)).unwrap(),
+        (ether(1) / 2, ether(1))
+    );
+}
+
+#[test]
+fn v2_liquidity() {
+    let (amount0, amount1) = (ether(4), ether(1));
+    let (minted, fee) = get_liquidity_minted(
+        amount0,
+        amount1,
+        U256::zero(),
+        U256::zero(),
+        U256::zero(),
+        U256::zero(),
+        false,
+    )
+    .unwrap();
+    assert_eq!(minted, ether(2) - MINIMUM_LIQUIDITY);
+    assert!(fee.is_zero());
+    let total_supply = ether(2);
+    // a proportional deposit mints proportional liquidity, the excess is donated
+    let (minted, _) = get_liquidity_minted(
+        ether(2),
+        ether(1),
+        amount0,
+        amount1,
+        total_supply,
+        U256::zero(),
+        false,
+    )
+    .unwrap();
+    assert_eq!(minted, ether(1));
+    let (value0, value1, fee) = get_liquidity_value(
+        total_supply,
+        amount0,
+        amount1,
+        total_supply,
+        U256::zero(),
+        false,
+    )
+    .unwrap();
+    assert_eq!((value0, value1), (amount0, amount1));
+    assert!(fee.is_zero());
+    assert!(
+        get_liquidity_value(
+            total_supply + 1,
+            amount0,
+            amount1,
+            total_supply,
+            U256::zero(),
+            false
+        )
+        .is_err()
+    );
+}
+
+#[test]
+fn v2_liquidity_of_empty_pair() {
+    assert!(matches!(
+        get_liquidity_value(
+            U256::zero(),
+            U256::zero(),
+            U256::zero(),
+            U256::zero(),
+            U256::zero(),
+            false
+        ),
+        Err(UniswapError::MathError(_))
+    ));
+    assert!(matches!(
+        get_liquidity_minted(
+            U256::MAX,
+            U256::from(2),
+            U256::zero(),
+            U256::zero(),
+            U256::zero(),
+            U256::zero(),
+            false
+        ),
+        Err(UniswapError::MathError(_))
+    ));
+    assert!(matches!(
+        get_liquidity_minted(
+            U256::MAX,
+            ether(1),
+            ether(1),
+            ether(1),
+            ether(1),
+            U256::zero(),
+            false
+        ),
+        Err(UniswapError::MathError(_))
+    ));
+}
+
+#[test]
+fn v2_protocol_fee() {
+    // k doubled since the last mint, part of the growth is minted to the fee recipient
+    let (reserve0, reserve1) = (ether(8), ether(2));
+    let k_last = ether(4) * ether(2);
+    let total_supply = ether(4);
+    let (_, _, fee) =
+        get_liquidity_value(ether(1), reserve0, reserve1, total_supply, k_last, true).unwrap();
+    assert!(!fee.is_zero());
+    let (_, _, fee_off) =
+        get_liquidity_value(ether(1), reserve0, reserve1, total_supply, k_last, false).unwrap();
+    assert!(fee_off.is_zero());
+}
+
+#[test]
+fn v2_arbitrage_optimal_input() {
+    let buy = (ether(1_000), ether(2_000));
+    let sell = (ether(2_000), ether(1_100));
+    let optimal = v2_optimal_amount_in(buy, sell);
+    assert!(!optimal.is_zero());
+    let profit = |amount_in: U256| {
+        let intermediate = get_amount_out(amount_in, buy.0, buy.1).unwrap();
+        let amount_out = get_amount_out(intermediate, sell.0, sell.1).unwrap();
+        I256::from_raw(amount_out) - I256::from_raw(amount_in)
+    };
+    assert!(profit(optimal) > I256::zero());
+    assert!(profit(optimal) >= profit(optimal * 9 / 10));
+    assert!(profit(optimal) >= profit(optimal * 11 / 10));
+    // equal prices leave nothing after fees
+    assert!(v2_optimal_amount_in(buy, (buy.1, buy.0)).is_zero());
+}
@@ -0,0 +1,247 @@
+use ethers::types::{Address, U256};
+use std::{str::FromStr, sync::Arc};
+use uniswap_sdk::{
+    global::mainnet::{USDC_ADDRESS, WETH_ADDRESS},
+    math::tick::get_sqrt_ratio_at_tick,
+    mock::{MOCK_BLOCK_TIME, MockEnvironment},
+    price::Price,
+    token::TokenCache,
+    types::{TickLiquidity, UniswapError},
+    v2::UniswapV2,
+    v3::UniswapV3,
+};
+
+fn units(amount: u64, decimals: u32) -> U256 {
+    U256::from(amount) * U256::exp10(decimals as usize)
+}
+
+fn weth() -> Address {
+    Address::from_str(WETH_ADDRESS).unwrap()
+}
+
+fn usdc() -> Address {
+    Address::from_str(USDC_ADDRESS).unwrap()
+}
+
+/// mainnet tokens with a WETH/USDC pair pricing ETH at 2000 USD
+fn environment() -> Arc<MockEnvironment> {
+    let mock = Arc::new(MockEnvironment::new());
+    mock.add_mainnet_tokens();
+    mock.add_v2_pair(weth(), usdc(), units(1_000, 18), units(2_000_000, 6));
+    mock
+}
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!(
+        (actual - expected).abs() <= expected.abs() * 1e-9,
+        "{} != {}",
+        actual,
+        expected
+    );
+}
+
+#[tokio::test]
+async fn v2_pool_info() {
+    let mock = environment();
+    let token = mock.create_token("TKN", "Token", 18);
+    let pair = mock.add_v2_pair(token, weth(), units(4_000, 18), units(2, 18));
+    let server = mock.serve().await.unwrap();
+    let uniswap_v2 =
+        UniswapV2::new_with_cache(server.provider().unwrap(), Arc::new(TokenCache::new()));
+    let pool_info = uniswap_v2.get_pool_info(pair, None).await.unwrap();
+    let (token_reserve, weth_reserve) = if pool_info.token0.address == token {
+        (pool_info.reserve0, pool_info.reserve1)
+    } else {
+        (pool_info.reserve1, pool_info.reserve0)
+    };
+    assert_eq!(token_reserve, units(4_000, 18));
+    assert_eq!(weth_reserve, units(2, 18));
+    assert!(!pool_info.liquidity.is_zero());
+    let price = uniswap_v2.get_price(pair, weth(), None).await.unwrap();
+    assert_close(price.price, 2_000.0);
+}
+
+#[tokio::test]
+async fn v3_pool_info() {
+    let mock = environment();
+    let token = mock.create_token("TKN", "Token", 18);
+    let pool = mock
+        .add_v3_pool(
+            token,
+            weth(),
+            3000,
+            get_sqrt_ratio_at_tick(0).unwrap(),
+            1_000_000,
+            Vec::new(),
+            units(1, 18),
+            units(1, 18),
+        )
+        .unwrap();
+    let server = mock.serve().await.unwrap();
+    let uniswap_v3 =
+        UniswapV3::new_with_cache(server.provider().unwrap(), Arc::new(TokenCache::new()));
+    let pool_info = uniswap_v3.get_pool_info(pool, None).await.unwrap();
+    assert_eq!(pool_info.liquidity, U256::from(1_000_000));
+    let symbols = [pool_info.token0.symbol, pool_info.token1.symbol];
+    assert!(symbols.contains(&"TKN".to_string()));
+    assert!(symbols.contains(&"WETH".to_string()));
+}
+
+#[tokio::test]
+async fn missing_pool() {
+    let mock = environment();
+    let server = mock.serve().await.unwrap();
+    let provider = server.provider().unwrap();
+    let uniswap_v2 = UniswapV2::new_with_cache(provider.clone(), Arc::new(TokenCache::new()));
+    let result = uniswap_v2
+        .get_pool_info(Address::from_low_u64_be(0xdead), None)
+        .await;
+    assert!(matches!(result, Err(UniswapError::ContractError(_))));
+    let uniswap_v3 = UniswapV3::new_with_cache(provider, Arc::new(TokenCache::new()));
+    let result = uniswap_v3
+        .get_pool_info(Address::from_low_u64_be(0xdead), None)
+        .await;
+    assert!(matches!(result, Err(UniswapError::ContractError(_))));
+}
+
+#[tokio::test]
+async fn token_prices() {
+    let mock = environment();
+    let token = mock.create_token("TKN", "Token", 18);
+    mock.add_v2_pair(token, weth(), units(4_000, 18), units(2, 18));
+    let server = mock.serve().await.unwrap();
+    let price = Price::new(server.provider().unwrap())
+        .unwrap()
+        .with_cache(Arc::new(TokenCache::new()));
+    let prices = price
+        .get_token_prices_by_token_address(token, None)
+        .await
+        .unwrap();
+    assert_eq!(prices.token_symbol, "TKN");
+    assert_close(prices.eth_price.unwrap(), 0.0005);
+    assert_close(prices.usd_price.unwrap(), 1.0);
+    assert_eq!(prices.price_source, "Uniswap V2");
+    assert!(prices.usdc_price.is_none());
+}
+
+#[tokio::test]
+async fn token_prices_with_zero_reserves() {
+    let mock = environment();
+    let token = mock.create_token("TKN", "Token", 18);
+    mock.add_v2_pair(token, weth(), U256::zero(), U256::zero());
+    let server = mock.serve().await.unwrap();
+    let price = Price::new(server.provider().unwrap())
+        .unwrap()
+        .with_cache(Arc::new(TokenCache::new()));
+    let prices = price
+        .get_token_prices_by_token_address(token, None)
+        .await
+        .unwrap();
+    assert!(prices.eth_price.is_none());
+    assert!(prices.usd_price.is_none());
+}
+
+#[tokio::test]
+async fn token_prices_without_pools() {
+    let mock = environment();
+    let token = mock.create_token("TKN", "Token", 18);
+    let server = mock.serve().await.unwrap();
+    let price = Price::new(server.provider().unwrap())
+        .unwrap()
+        .with_cache(Arc::new(TokenCache::new()));
+    let prices = price
+        .get_token_prices_by_token_address(token, None)
+        .await
+        .unwrap();
+    assert!(prices.eth_price.is_none());
+    assert!(prices.usd_price.is_none());
+    assert!(prices.provenance.is_empty());
+    assert_eq!(prices.price_source, "Unknown");
+}
+
+#[tokio::test]
+async fn zero_decimal_token_prices() {
+    let mock = environment();
+    let token = mock.create_token("ZERO", "Zero Decimals", 0);
+    mock.add_v2_pair(token, usdc(), U256::from(1_000), units(5_000, 6));
+    let server = mock.serve().await.unwrap();
+    let price = Price::new(server.provider().unwrap())
+        .unwrap()
+        .with_cache(Arc::new(TokenCache::new()));
+    let prices = price
+        .get_token_prices_by_token_address(token, None)
+        .await
+        .unwrap();
+    assert_eq!(prices.decimals, 0);
+    assert_close(prices.usdc_price.unwrap(), 5.0);
+    assert_close(prices.usd_price.unwrap(), 5.0);
+}
+
+#[tokio::test]
+async fn bytes32_token_metadata() {
+    let mock = environment();
+    let token = mock.create_token("MKR", "Maker", 18);
+    mock.update_token(token, |token| token.bytes32_metadata = true);
+    let server = mock.serve().await.unwrap();
+    let uniswap_v2 =
+        UniswapV2::new_with_cache(server.provider().unwrap(), Arc::new(TokenCache::new()));
+    let token_info = uniswap_v2.get_token_info(token, None).await.unwrap();
+    assert_eq!(token_info.symbol, "MKR");
+    assert_eq!(token_info.name, "Maker");
+    assert_eq!(token_info.decimals, 18);
+}
+
+#[tokio::test]
+async fn token_without_decimals() {
+    let mock = environment();
+    let token = mock.create_token("TKN", "Token", 18);
+    mock.update_token(token, |token| token.decimals = None);
+    let server = mock.serve().await.unwrap();
+    let uniswap_v2 =
+        UniswapV2::new_with_cache(server.provider().unwrap(), Arc::new(TokenCache::new()));
+    let result = uniswap_v2.get_token_info(token, None).await;
+    assert!(matches!(result, Err(UniswapError::ContractError(_))));
+}
+
+#[tokio::test]
+async fn consult() {
+    let mock = environment();
+    let token = mock.create_token("TKN", "Token", 18);
+    let tick = 6_000;
+    let pool = mock
+        .add_v3_pool(
+            token,
+            weth(),
+            3000,
+            get_sqrt_ratio_at_tick(tick).unwrap(),
+            1_000_000,
+            vec![
+                TickLiquidity {
+                    tick: 0,
+                    liquidity_net: 1_000_000,
+                },
+                TickLiquidity {
+                    tick: 12_000,
+                    liquidity_net: -1_000_000,
+                },
+            ],
+            units(1, 18),
+            units(1, 18),
+        )
+        .unwrap();
+    mock.advance_blocks(100);
+    let server = mock.serve().await.unwrap();
+    let uniswap_v3 =
+        UniswapV3::new_with_cache(server.provider().unwrap(), Arc::new(TokenCache::new()));
+    let seconds_ago = (50 * MOCK_BLOCK_TIME) as u32;
+    let twap = uniswap_v3.consult(pool, seconds_ago, None).await.unwrap();
+    assert_eq!(twap.arithmetic_mean_tick, tick);
+    // the cumulatives are floored at both ends
+    assert!(twap.harmonic_mean_liquidity.abs_diff(1_000_000) <= 1);
+    assert_close(twap.price0, 1.0001f64.powi(tick));
+    // the single observation is from the start of the chain
+    let result = uniswap_v3
+        .consult(pool, (200 * MOCK_BLOCK_TIME) as u32, None)
+        .await;
+    assert!(matches!(result, Err(UniswapError::OracleError(_))));
+}
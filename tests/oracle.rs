@@ -0,0 +1,150 @@
+use ethers::types::{Address, U256};
+use uniswap_sdk::{
+    oracle::{
+        V2ObservationBuffer, compute_twap, consult_cumulatives, current_cumulative_prices,
+        required_cardinality,
+    },
+    types::{UniswapError, V2Observation},
+};
+
+const START: u64 = 1_700_000_000;
+
+fn pair() -> Address {
+    Address::from_low_u64_be(1)
+}
+
+fn ether(amount: u64) -> U256 {
+    U256::from(amount) * U256::exp10(18)
+}
+
+/// observations of a pair whose reserves price token0 at 2 token1 for `elapsed` seconds
+/// and then at 4 token1 for as long
+fn observations(elapsed: u64) -> [V2Observation; 3] {
+    let observation = |timestamp: u64, cumulatives: (U256, U256)| V2Observation {
+        pair: pair(),
+        block_number: 0,
+        timestamp,
+        price0_cumulative: cumulatives.0,
+        price1_cumulative: cumulatives.1,
+    };
+    let start = (U256::zero(), U256::zero());
+    let middle = current_cumulative_prices(
+        start.0,
+        start.1,
+        ether(1),
+        ether(2),
+        START as u32,
+        START + elapsed,
+    );
+    let end = current_cumulative_prices(
+        middle.0,
+        middle.1,
+        ether(1),
+        ether(4),
+        (START + elapsed) as u32,
+        START + 2 * elapsed,
+    );
+    [
+        observation(START, start),
+        observation(START + elapsed, middle),
+        observation(START + 2 * elapsed, end),
+    ]
+}
+
+#[test]
+fn v2_twap() {
+    let [start, middle, end] = observations(600);
+    let twap = compute_twap(&start, &middle, 18, 18).unwrap();
+    assert_eq!(twap.price0, 2.0);
+    assert_eq!(twap.price1, 0.5);
+    // the average of both periods
+    let twap = compute_twap(&start, &end, 18, 18).unwrap();
+    assert_eq!(twap.price0, 3.0);
+    assert_eq!(twap.start_timestamp, START);
+    assert_eq!(twap.end_timestamp, START + 1200);
+    // token0 with 18 decimals priced in a 6 decimal token1
+    let twap = compute_twap(&start, &middle, 18, 6).unwrap();
+    assert!((twap.price0 - 2e12).abs() < 1.0);
+    assert!(matches!(
+        compute_twap(&middle, &start, 18, 18),
+        Err(UniswapError::OracleError(_))
+    ));
+}
+
+#[test]
+fn v2_twap_across_cumulative_overflow() {
+    let [mut start, mut end, _] = observations(600);
+    // shifting both cumulatives by the same amount keeps their wrapped delta
+    let offset = U256::MAX - ether(1);
+    start.price0_cumulative = start.price0_cumulative.overflowing_add(offset).0;
+    end.price0_cumulative = end.price0_cumulative.overflowing_add(offset).0;
+    assert!(end.price0_cumulative < start.price0_cumulative);
+    assert_eq!(compute_twap(&start, &end, 18, 18).unwrap().price0, 2.0);
+}
+
+#[test]
+fn v2_twap_of_other_pair() {
+    let [start, mut end, _] = observations(600);
+    end.pair = Address::from_low_u64_be(2);
+    assert!(matches!(
+        compute_twap(&start, &end, 18, 18),
+        Err(UniswapError::OracleError(_))
+    ));
+}
+
+#[test]
+fn observation_buffer() {
+    let [start, middle, end] = observations(600);
+    let mut buffer = V2ObservationBuffer::new(pair(), 2);
+    assert!(matches!(
+        buffer.twap(600, 18, 18),
+        Err(UniswapError::OracleError(_))
+    ));
+    buffer.push(start.clone()).unwrap();
+    buffer.push(middle.clone()).unwrap();
+    // stale observations are ignored
+    buffer.push(start).unwrap();
+    assert_eq!(buffer.latest().unwrap().timestamp, middle.timestamp);
+    buffer.push(end).unwrap();
+    assert_eq!(buffer.observations.len(), 2);
+    assert_eq!(buffer.twap(600, 18, 18).unwrap().price0, 4.0);
+    assert!(matches!(
+        buffer.twap(1200, 18, 18),
+        Err(UniswapError::OracleError(_))
+    ));
+    let mut other = middle;
+    other.pair = Address::from_low_u64_be(2);
+    assert!(buffer.push(other).is_err());
+}
+
+#[test]
+fn v3_consult() {
+    let liquidity = U256::from(1_000_000u64);
+    let seconds_per_liquidity = |seconds: u64| (U256::from(seconds) << 128) / liquidity;
+    let (tick, harmonic_mean_liquidity) = consult_cumulatives(
+        [-1_000 * 600, -1_000 * 1200],
+        [seconds_per_liquidity(600), seconds_per_liquidity(1200)],
+        600,
+    )
+    .unwrap();
+    assert_eq!(tick, -1_000);
+    assert!(harmonic_mean_liquidity.abs_diff(1_000_000) <= 1);
+    // negative averages round towards negative infinity
+    let (tick, _) =
+        consult_cumulatives([0, -601], [U256::zero(), seconds_per_liquidity(600)], 600).unwrap();
+    assert_eq!(tick, -2);
+    let (tick, _) =
+        consult_cumulatives([0, 601], [U256::zero(), seconds_per_liquidity(600)], 600).unwrap();
+    assert_eq!(tick, 1);
+    assert!(matches!(
+        consult_cumulatives([0, 0], [U256::zero(), U256::zero()], 0),
+        Err(UniswapError::OracleError(_))
+    ));
+}
+
+#[test]
+fn oracle_cardinality() {
+    assert_eq!(required_cardinality(1800, 12), 151);
+    assert_eq!(required_cardinality(1801, 12), 152);
+    assert_eq!(required_cardinality(u32::MAX, 1), u16::MAX);
+}
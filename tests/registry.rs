@@ -0,0 +1,97 @@
+use ethers::types::{Address, U256};
+use std::{str::FromStr, sync::Arc};
+use uniswap_sdk::{
+    global::mainnet::{USDC_ADDRESS, WETH_ADDRESS},
+    mock::MockEnvironment,
+    price::Price,
+    registry::{PoolDiscovery, PoolRegistry},
+    token::TokenCache,
+};
+
+fn units(amount: u64, decimals: u32) -> U256 {
+    U256::from(amount) * U256::exp10(decimals as usize)
+}
+
+fn weth() -> Address {
+    Address::from_str(WETH_ADDRESS).unwrap()
+}
+
+fn usdc() -> Address {
+    Address::from_str(USDC_ADDRESS).unwrap()
+}
+
+#[tokio::test]
+async fn enumerate_v2_pairs() {
+    let mock = Arc::new(MockEnvironment::new());
+    mock.add_mainnet_tokens();
+    let token = mock.create_token("TKN", "Token", 18);
+    let other = mock.create_token("OTH", "Other", 18);
+    let pairs = [
+        mock.add_v2_pair(weth(), usdc(), units(1_000, 18), units(2_000_000, 6)),
+        mock.add_v2_pair(token, weth(), units(4_000, 18), units(2, 18)),
+        mock.add_v2_pair(token, other, units(1_000, 18), units(1_000, 18)),
+    ];
+    let server = mock.serve().await.unwrap();
+    let discovery = PoolDiscovery::new(server.provider().unwrap());
+    let mut registry = PoolRegistry::mainnet();
+
+    // a limited enumeration resumes where it stopped
+    assert_eq!(
+        discovery
+            .enumerate_v2_pairs(&mut registry, Some(2))
+            .await
+            .unwrap(),
+        2
+    );
+    assert_eq!(registry.v2_pairs_enumerated, 2);
+    assert_eq!(
+        discovery
+            .enumerate_v2_pairs(&mut registry, None)
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(registry.v2_pairs_enumerated, 3);
+    assert!(pairs.iter().all(|pair| registry.get(*pair).is_some()));
+    assert_eq!(registry.pools_for_token(token).len(), 2);
+    assert_eq!(registry.pools_for_pair(other, token).len(), 1);
+    assert_eq!(
+        discovery
+            .enumerate_v2_pairs(&mut registry, None)
+            .await
+            .unwrap(),
+        0
+    );
+}
+
+#[tokio::test]
+async fn search_liquid_pools_with_registry() {
+    let mock = Arc::new(MockEnvironment::new());
+    mock.add_mainnet_tokens();
+    let token = mock.create_token("TKN", "Token", 18);
+    let other = mock.create_token("OTH", "Other", 18);
+    mock.add_v2_pair(token, weth(), units(4_000, 18), units(2, 18));
+    // paired with a token outside the base tokens
+    let deep = mock.add_v2_pair(token, other, units(1_000_000, 18), units(1_000, 18));
+    mock.add_v2_pair(token, usdc(), U256::zero(), U256::zero());
+    let server = mock.serve().await.unwrap();
+    let provider = server.provider().unwrap();
+    let price = Price::new(provider.clone())
+        .unwrap()
+        .with_cache(Arc::new(TokenCache::new()));
+    assert_eq!(
+        price.search_liquid_pools(token, None).await.unwrap().len(),
+        1
+    );
+
+    let mut registry = PoolRegistry::mainnet();
+    PoolDiscovery::new(provider)
+        .enumerate_v2_pairs(&mut registry, None)
+        .await
+        .unwrap();
+    let price = price.with_registry(Arc::new(registry));
+    let pools = price.search_liquid_pools(token, None).await.unwrap();
+    // empty pairs are skipped and the deepest pair comes first
+    assert_eq!(pools.len(), 2);
+    assert_eq!(pools[0].address, deep);
+}
@@ -0,0 +1,56 @@
+use ethers::types::{Address, BlockId, U256};
+use std::{str::FromStr, sync::Arc};
+use uniswap_sdk::{
+    global::mainnet::WETH_ADDRESS,
+    mock::MockEnvironment,
+    replay::{RpcRecorder, RpcReplayer, RpcServer},
+    token::TokenCache,
+    types::UniswapError,
+    v2::UniswapV2,
+};
+
+#[tokio::test]
+async fn fixture_round_trip() {
+    let mock = Arc::new(MockEnvironment::new());
+    mock.add_mainnet_tokens();
+    let weth = Address::from_str(WETH_ADDRESS).unwrap();
+    let token = mock.create_token("TKN", "Token", 18);
+    let pair = mock.add_v2_pair(token, weth, U256::exp10(21), U256::exp10(18));
+    let block = Some(BlockId::from(mock.block_number()));
+    let fixture =
+        std::env::temp_dir().join(format!("uniswap-sdk-replay-{}.json", std::process::id()));
+
+    // record a session against the mock chain
+    let mock_server = mock.serve().await.unwrap();
+    let recorder = Arc::new(RpcRecorder::new(&mock_server.url).unwrap());
+    let recorder_server = RpcServer::start(recorder.clone()).await.unwrap();
+    let uniswap_v2 = UniswapV2::new_with_cache(
+        recorder_server.provider().unwrap(),
+        Arc::new(TokenCache::new()),
+    );
+    let recorded = uniswap_v2.get_pool_info(pair, block).await.unwrap();
+    assert!(!recorder.records().is_empty());
+    recorder.save(&fixture).unwrap();
+    drop(recorder_server);
+    drop(mock_server);
+
+    // replay it with the mock chain gone
+    let replay_server = RpcReplayer::serve(&fixture).await.unwrap();
+    let uniswap_v2 = UniswapV2::new_with_cache(
+        replay_server.provider().unwrap(),
+        Arc::new(TokenCache::new()),
+    );
+    let replayed = uniswap_v2.get_pool_info(pair, block).await.unwrap();
+    assert_eq!(replayed.reserve0, recorded.reserve0);
+    assert_eq!(replayed.reserve1, recorded.reserve1);
+    assert_eq!(replayed.liquidity, recorded.liquidity);
+    assert_eq!(replayed.token0.symbol, recorded.token0.symbol);
+    assert_eq!(replayed.token1.symbol, recorded.token1.symbol);
+
+    // requests outside the session are not answered
+    let result = uniswap_v2
+        .get_pool_info(Address::from_low_u64_be(0xdead), block)
+        .await;
+    assert!(matches!(result, Err(UniswapError::ContractError(_))));
+    std::fs::remove_file(&fixture).unwrap();
+}
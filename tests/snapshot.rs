@@ -0,0 +1,103 @@
+use ethers::types::{Address, U256};
+use std::{str::FromStr, sync::Arc};
+use uniswap_sdk::{
+    global::mainnet::{USDC_ADDRESS, WETH_ADDRESS},
+    math::tick::get_sqrt_ratio_at_tick,
+    mock::MockEnvironment,
+    registry::{PoolDiscovery, PoolRegistry},
+    snapshot::{SNAPSHOT_VERSION, StateSnapshot},
+    state::PoolStateManager,
+    types::{PoolLiquidity, PoolVersion, TickLiquidity, UniswapError},
+};
+
+fn units(amount: u64, decimals: u32) -> U256 {
+    U256::from(amount) * U256::exp10(decimals as usize)
+}
+
+fn weth() -> Address {
+    Address::from_str(WETH_ADDRESS).unwrap()
+}
+
+fn usdc() -> Address {
+    Address::from_str(USDC_ADDRESS).unwrap()
+}
+
+#[tokio::test]
+async fn snapshot_round_trip() {
+    let mock = Arc::new(MockEnvironment::new());
+    mock.add_mainnet_tokens();
+    let pair = mock.add_v2_pair(weth(), usdc(), units(1_000, 18), units(2_000_000, 6));
+    let token = mock.create_token("TKN", "Token", 18);
+    let pool = mock
+        .add_v3_pool(
+            token,
+            weth(),
+            3000,
+            get_sqrt_ratio_at_tick(0).unwrap(),
+            1_000_000,
+            vec![
+                TickLiquidity {
+                    tick: -600,
+                    liquidity_net: 1_000_000,
+                },
+                TickLiquidity {
+                    tick: 600,
+                    liquidity_net: -1_000_000,
+                },
+            ],
+            units(1, 18),
+            units(1, 18),
+        )
+        .unwrap();
+    let server = mock.serve().await.unwrap();
+    let provider = server.provider().unwrap();
+    let mut state = PoolStateManager::new(provider.clone());
+    state.add_pool(pair, PoolVersion::V2).await.unwrap();
+    state.add_pool(pool, PoolVersion::V3).await.unwrap();
+    let mut registry = PoolRegistry::mainnet();
+    PoolDiscovery::new(provider.clone())
+        .enumerate_v2_pairs(&mut registry, None)
+        .await
+        .unwrap();
+    let fixture =
+        std::env::temp_dir().join(format!("uniswap-sdk-snapshot-{}.json", std::process::id()));
+    state
+        .snapshot(Some(&registry))
+        .unwrap()
+        .save(&fixture)
+        .unwrap();
+
+    let snapshot = StateSnapshot::load(&fixture).unwrap();
+    std::fs::remove_file(&fixture).unwrap();
+    assert_eq!(snapshot.version, SNAPSHOT_VERSION);
+    assert_eq!(snapshot.block_number, mock.block_number());
+    // the registry's token index is rebuilt on load
+    let loaded_registry = snapshot.registry.clone().unwrap();
+    assert_eq!(loaded_registry.pools_for_pair(usdc(), weth()).len(), 1);
+    let restored = PoolStateManager::new_with_snapshot(provider, snapshot);
+    assert_eq!(restored.len(), 2);
+    assert_eq!(restored.last_block, state.last_block);
+    assert_eq!(restored.reserves(pair), state.reserves(pair));
+    assert_eq!(restored.price(pair), state.price(pair));
+    assert_eq!(restored.price(pool), state.price(pool));
+    let amount_in = U256::from(1_000);
+    assert_eq!(
+        restored.quote(pool, token, amount_in).unwrap(),
+        state.quote(pool, token, amount_in).unwrap()
+    );
+    let Some(PoolLiquidity::V3(restored_pool)) = restored.get(pool) else {
+        panic!("not a V3 pool");
+    };
+    assert_eq!(restored_pool.ticks.len(), 2);
+}
+
+#[test]
+fn snapshot_of_other_version() {
+    let mut snapshot = StateSnapshot::new(1, None);
+    snapshot.version = SNAPSHOT_VERSION + 1;
+    let json = serde_json::to_vec(&snapshot).unwrap();
+    assert!(matches!(
+        StateSnapshot::from_json(&json),
+        Err(UniswapError::StorageError(_))
+    ));
+}
@@ -0,0 +1,216 @@
+use ethers::{
+    abi::{Token, encode},
+    contract::EthEvent,
+    providers::{Http, Provider},
+    types::{Address, H256, I256, Log, U64, U256},
+};
+use std::sync::Arc;
+use uniswap_sdk::{
+    abi::{SyncFilter, TransferFilter, i_uniswap_v3_pool::SwapFilter},
+    math::tick::get_sqrt_ratio_at_tick,
+    state::PoolStateManager,
+    types::{PoolInfo, PoolLiquidity, TokenInfo, V3PoolState},
+};
+
+const START_BLOCK: u64 = 100;
+
+fn address(n: u64) -> Address {
+    Address::from_low_u64_be(n)
+}
+
+fn token(n: u64) -> TokenInfo {
+    TokenInfo {
+        address: address(n),
+        symbol: format!("T{}", n),
+        decimals: 18,
+        name: format!("Token {}", n),
+    }
+}
+
+fn log(pool: Address, block_number: u64, topics: Vec<H256>, data: Vec<u8>) -> Log {
+    Log {
+        address: pool,
+        topics,
+        data: data.into(),
+        block_number: Some(U64::from(block_number)),
+        block_hash: Some(H256::from_low_u64_be(block_number)),
+        ..Default::default()
+    }
+}
+
+fn sync_log(pair: Address, block_number: u64, reserve0: u64, reserve1: u64) -> Log {
+    log(
+        pair,
+        block_number,
+        vec![SyncFilter::signature()],
+        encode(&[
+            Token::Uint(U256::from(reserve0)),
+            Token::Uint(U256::from(reserve1)),
+        ]),
+    )
+}
+
+fn transfer_log(pair: Address, block_number: u64, from: Address, to: Address, value: u64) -> Log {
+    log(
+        pair,
+        block_number,
+        vec![TransferFilter::signature(), from.into(), to.into()],
+        encode(&[Token::Uint(U256::from(value))]),
+    )
+}
+
+fn swap_log(pool: Address, block_number: u64, tick: i32, liquidity: u128) -> Log {
+    log(
+        pool,
+        block_number,
+        vec![SwapFilter::signature(), H256::zero(), H256::zero()],
+        encode(&[
+            Token::Int(U256::zero()),
+            Token::Int(U256::zero()),
+            Token::Uint(get_sqrt_ratio_at_tick(tick).unwrap()),
+            Token::Uint(U256::from(liquidity)),
+            Token::Int(I256::from(tick).into_raw()),
+        ]),
+    )
+}
+
+/// a manager at `START_BLOCK` tracking a V2 pair and a V3 pool loaded between ticks -100 and 100
+fn manager() -> (PoolStateManager, Address, Address) {
+    let provider = Arc::new(Provider::<Http>::try_from("http://127.0.0.1:1").unwrap());
+    let mut manager = PoolStateManager::new(provider);
+    let pair = address(10);
+    let pool = address(11);
+    manager.insert_pool(PoolLiquidity::V2(PoolInfo {
+        address: pair,
+        token0: token(1),
+        token1: token(2),
+        reserve0: U256::from(1_000),
+        reserve1: U256::from(1_000),
+        liquidity: U256::from(1_000),
+    }));
+    manager.insert_pool(PoolLiquidity::V3(V3PoolState {
+        pool,
+        token0: address(1),
+        token1: address(2),
+        fee: 3000,
+        tick_spacing: 60,
+        sqrt_price_x96: get_sqrt_ratio_at_tick(0).unwrap(),
+        tick: 0,
+        liquidity: 1_000_000,
+        ticks: Vec::new(),
+        tick_lower: -100,
+        tick_upper: 100,
+    }));
+    manager.last_block = Some(START_BLOCK);
+    (manager, pair, pool)
+}
+
+fn v2(manager: &PoolStateManager, pair: Address) -> PoolInfo {
+    match manager.get(pair) {
+        Some(PoolLiquidity::V2(pool_info)) => pool_info.clone(),
+        _ => panic!("not a V2 pair"),
+    }
+}
+
+fn v3(manager: &PoolStateManager, pool: Address) -> V3PoolState {
+    match manager.get(pool) {
+        Some(PoolLiquidity::V3(state)) => state.clone(),
+        _ => panic!("not a V3 pool"),
+    }
+}
+
+#[test]
+fn v2_liquidity_follows_lp_mints_and_burns() {
+    let (mut manager, pair, _) = manager();
+    let owner = address(20);
+    assert!(
+        manager
+            .apply_log(&transfer_log(pair, 101, Address::zero(), owner, 50))
+            .unwrap()
+    );
+    assert_eq!(v2(&manager, pair).liquidity, U256::from(1_050));
+    assert!(
+        manager
+            .apply_log(&transfer_log(pair, 102, pair, Address::zero(), 20))
+            .unwrap()
+    );
+    assert_eq!(v2(&manager, pair).liquidity, U256::from(1_030));
+    // transfers between holders leave the supply unchanged
+    assert!(
+        !manager
+            .apply_log(&transfer_log(pair, 102, owner, pair, 20))
+            .unwrap()
+    );
+    assert!(
+        manager
+            .apply_log(&transfer_log(pair, 103, pair, Address::zero(), 2_000))
+            .is_err()
+    );
+}
+
+#[test]
+fn v3_price_outside_window_is_stale() {
+    let (mut manager, _, pool) = manager();
+    assert!(manager.stale_pools().is_empty());
+    manager
+        .apply_log(&swap_log(pool, 101, 99, 1_000_000))
+        .unwrap();
+    assert!(manager.stale_pools().is_empty());
+    manager
+        .apply_log(&swap_log(pool, 102, 100, 1_000_000))
+        .unwrap();
+    assert_eq!(manager.stale_pools(), vec![pool]);
+    manager
+        .apply_log(&swap_log(pool, 103, -101, 1_000_000))
+        .unwrap();
+    assert_eq!(manager.stale_pools(), vec![pool]);
+}
+
+#[test]
+fn reorg_rollback_restores_state() {
+    let (mut manager, pair, pool) = manager();
+    let owner = address(20);
+    for log in [
+        sync_log(pair, 101, 1_100, 1_000),
+        transfer_log(pair, 101, Address::zero(), owner, 50),
+        swap_log(pool, 101, 50, 900_000),
+        sync_log(pair, 102, 1_200, 950),
+        transfer_log(pair, 102, pair, Address::zero(), 30),
+        swap_log(pool, 102, 200, 800_000),
+        sync_log(pair, 103, 1_300, 900),
+    ] {
+        assert!(manager.apply_log(&log).unwrap());
+    }
+    assert_eq!(
+        manager.reserves(pair),
+        Some((U256::from(1_300), U256::from(900)))
+    );
+    assert_eq!(v2(&manager, pair).liquidity, U256::from(1_020));
+    assert_eq!(manager.stale_pools(), vec![pool]);
+    assert_eq!(manager.changed_pools(103).len(), 1);
+
+    // blocks 102 and 103 are reorged out
+    manager.rollback(102);
+    assert_eq!(
+        manager.reserves(pair),
+        Some((U256::from(1_100), U256::from(1_000)))
+    );
+    assert_eq!(v2(&manager, pair).liquidity, U256::from(1_050));
+    let state = v3(&manager, pool);
+    assert_eq!(state.tick, 50);
+    assert_eq!(state.liquidity, 900_000);
+    assert!(manager.stale_pools().is_empty());
+    assert!(manager.changed_pools(102).is_empty());
+
+    // a removed log of block 101 rolls back to the loaded state
+    let mut removed = sync_log(pair, 101, 1_100, 1_000);
+    removed.removed = Some(true);
+    assert!(manager.apply_log(&removed).unwrap());
+    assert_eq!(
+        manager.reserves(pair),
+        Some((U256::from(1_000), U256::from(1_000)))
+    );
+    assert_eq!(v2(&manager, pair).liquidity, U256::from(1_000));
+    assert_eq!(v3(&manager, pool).tick, 0);
+    assert_eq!(manager.last_block, Some(START_BLOCK));
+}
@@ -0,0 +1,160 @@
+use ethers::types::Address;
+use serde_json::json;
+use uniswap_sdk::{
+    tokenlist::{TokenList, TokenListDiff, TokenListVersion, TokenRegistry},
+    types::UniswapError,
+};
+
+fn address(n: u64) -> Address {
+    Address::from_low_u64_be(n)
+}
+
+fn token(n: u64, symbol: &str, name: &str) -> serde_json::Value {
+    json!({
+        "chainId": 1,
+        "address": address(n),
+        "name": name,
+        "symbol": symbol,
+        "decimals": 18,
+    })
+}
+
+fn list(name: &str, version: (u32, u32, u32), tokens: Vec<serde_json::Value>) -> TokenList {
+    let json = json!({
+        "name": name,
+        "timestamp": "2024-01-01T00:00:00.000Z",
+        "version": { "major": version.0, "minor": version.1, "patch": version.2 },
+        "tokens": tokens,
+    });
+    TokenList::from_json(&serde_json::to_vec(&json).unwrap()).unwrap()
+}
+
+#[test]
+fn validation_failure() {
+    let mut tagged = token(2, "TWO", "Token Two");
+    tagged["tags"] = json!(["stablecoin"]);
+    let json = json!({
+        "name": "A list name longer than thirty characters",
+        "timestamp": "2024-01-01T00:00:00.000Z",
+        "version": { "major": 1, "minor": 0, "patch": 0 },
+        "tokens": [
+            token(1, "ONE", "Token One"),
+            token(1, "ONE", "Token One again"),
+            tagged,
+            token(3, "", "Token Three"),
+        ],
+    });
+    let Err(UniswapError::TokenListError(problems)) =
+        TokenList::from_json(&serde_json::to_vec(&json).unwrap())
+    else {
+        panic!("the list should be rejected");
+    };
+    // every problem is reported at once
+    assert!(problems.contains("must be 1 to 30 characters"));
+    assert!(problems.contains("duplicate token"));
+    assert!(problems.contains("undefined tag stablecoin"));
+    assert!(problems.contains("symbol \"\" must be 1 to 20 characters"));
+    assert!(matches!(
+        TokenList::from_json(b"{\"name\": \"Broken\"}"),
+        Err(UniswapError::TokenListError(_))
+    ));
+}
+
+#[test]
+fn merge_conflict_keeps_first_list() {
+    let preferred = list(
+        "Preferred",
+        (1, 0, 0),
+        vec![token(1, "ONE", "Token One"), token(2, "TWO", "Token Two")],
+    );
+    let other = list(
+        "Other",
+        (1, 0, 0),
+        vec![token(1, "UNO", "Token Uno"), token(3, "two", "Other Two")],
+    );
+    let mut registry = TokenRegistry::new_with_lists(&[preferred]);
+    assert_eq!(registry.merge(&other), 1);
+    assert_eq!(registry.len(), 3);
+    // the conflicting address keeps the entry of the list merged first
+    let conflicting = registry.get(1, address(1)).unwrap();
+    assert_eq!(conflicting.symbol, "ONE");
+    assert_eq!(conflicting.name, "Token One");
+    assert!(registry.get_by_symbol(1, "UNO").is_empty());
+    // symbols are not unique across lists
+    let twos = registry.get_by_symbol(1, "Two");
+    assert_eq!(twos.len(), 2);
+    assert!(registry.get(5, address(1)).is_none());
+}
+
+#[test]
+fn version_bump_diff() {
+    let version = TokenListVersion {
+        major: 1,
+        minor: 2,
+        patch: 3,
+    };
+    let old = list(
+        "Versioned",
+        (1, 2, 3),
+        vec![token(1, "ONE", "Token One"), token(2, "TWO", "Token Two")],
+    );
+    let same = TokenListDiff::new(&old, &old);
+    assert!(same.is_empty());
+    assert_eq!(same.required_version(version), version);
+
+    let renamed = list(
+        "Versioned",
+        (1, 2, 4),
+        vec![token(1, "ONE", "Token 1"), token(2, "TWO", "Token Two")],
+    );
+    let diff = TokenListDiff::new(&old, &renamed);
+    assert_eq!(diff.changed.len(), 1);
+    assert_eq!(diff.changed[0].0.name, "Token One");
+    assert_eq!(diff.changed[0].1.name, "Token 1");
+    assert_eq!(
+        diff.required_version(version),
+        TokenListVersion {
+            major: 1,
+            minor: 2,
+            patch: 4,
+        }
+    );
+
+    let extended = list(
+        "Versioned",
+        (1, 3, 0),
+        vec![
+            token(1, "ONE", "Token 1"),
+            token(2, "TWO", "Token Two"),
+            token(3, "THREE", "Token Three"),
+        ],
+    );
+    let diff = TokenListDiff::new(&old, &extended);
+    assert_eq!(diff.added.len(), 1);
+    assert_eq!(diff.changed.len(), 1);
+    assert_eq!(
+        diff.required_version(version),
+        TokenListVersion {
+            major: 1,
+            minor: 3,
+            patch: 0,
+        }
+    );
+
+    let reduced = list(
+        "Versioned",
+        (2, 0, 0),
+        vec![token(3, "THREE", "Token Three")],
+    );
+    let diff = TokenListDiff::new(&old, &reduced);
+    assert_eq!(diff.removed.len(), 2);
+    assert_eq!(diff.added.len(), 1);
+    assert_eq!(
+        diff.required_version(version),
+        TokenListVersion {
+            major: 2,
+            minor: 0,
+            patch: 0,
+        }
+    );
+}